@@ -0,0 +1,270 @@
+use clap::ValueEnum;
+use raylib::prelude::Vector3;
+use rayon::prelude::*;
+use serde::Deserialize;
+
+use crate::color::linear_to_srgb;
+use crate::framebuffer::Framebuffer;
+use crate::material::MaterialId;
+use crate::path_tracer::Rng;
+
+/// Filas que traza cada tarea. Sus muestras se reparten en un búfer propio,
+/// con el margen del filtro arriba y abajo, que después se suma a la imagen.
+const BAND_ROWS: i32 = 4;
+/// Diferencia en algún canal entre pixeles vecinos, ya comprimidos a algo
+/// parecido a la pantalla, a partir de la cual el modo adaptativo los refina.
+const COLOR_THRESHOLD: f32 = 0.1;
+/// Lo mismo para la profundidad, relativa a la del pixel más cercano.
+const DEPTH_THRESHOLD: f32 = 0.05;
+
+/// Suma ponderada de RGBA de las muestras que caen en un pixel y suma de
+/// sus pesos.
+type Cell = ([f32; 4], f32);
+
+/// Filtro de reconstrucción: cuánto pesa cada muestra en los pixeles según
+/// su distancia al centro de cada uno.
+#[derive(ValueEnum, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Filter {
+    /// Promedio de las muestras del propio pixel.
+    #[default]
+    Box,
+    /// Triángulo de un pixel de radio: suaviza un poco más que la caja.
+    Tent,
+    /// Mitchell-Netravali (B = C = 1/3) de dos pixeles de radio: más nítido
+    /// que la tienda, con lóbulos negativos leves.
+    Mitchell,
+}
+
+impl Filter {
+    /// Pixeles vecinos, a cada lado, a los que llega una muestra.
+    fn margin(self) -> i32 {
+        match self {
+            Filter::Box => 0,
+            Filter::Tent => 1,
+            Filter::Mitchell => 2,
+        }
+    }
+
+    /// Peso en un eje a `d` pixeles del centro; el del filtro es el producto
+    /// de los dos ejes. La caja solo se evalúa en el propio pixel.
+    fn weight(self, d: f32) -> f32 {
+        let d = d.abs();
+        match self {
+            Filter::Box => 1.0,
+            Filter::Tent => (1.0 - d).max(0.0),
+            Filter::Mitchell => {
+                const B: f32 = 1.0 / 3.0;
+                const C: f32 = 1.0 / 3.0;
+                let (d2, d3) = (d * d, d * d * d);
+                let w = if d < 1.0 {
+                    (12.0 - 9.0 * B - 6.0 * C) * d3 + (-18.0 + 12.0 * B + 6.0 * C) * d2 + (6.0 - 2.0 * B)
+                } else if d < 2.0 {
+                    (-B - 6.0 * C) * d3 + (6.0 * B + 30.0 * C) * d2 + (-12.0 * B - 48.0 * C) * d + (8.0 * B + 24.0 * C)
+                } else {
+                    0.0
+                };
+                w / 6.0
+            }
+        }
+    }
+}
+
+/// Muestreo de cada pixel en el modo Whitted.
+#[derive(Clone, Copy, Debug)]
+pub struct AntiAliasing {
+    /// Estratos por eje: `grid`² muestras por pixel, cada una en un punto al
+    /// azar de su estrato. Con 1 se traza un solo rayo por el centro.
+    pub grid: u32,
+    pub filter: Filter,
+    /// Traza primero un rayo por pixel y solo usa la grilla completa donde
+    /// los vecinos difieren en color, profundidad o material.
+    pub adaptive: bool,
+}
+
+impl Default for AntiAliasing {
+    fn default() -> Self {
+        AntiAliasing { grid: 1, filter: Filter::Box, adaptive: false }
+    }
+}
+
+/// Lo que devuelve un rayo primario: el color, su cobertura (alfa) y, para
+/// el modo adaptativo, a qué distancia y en qué material chocó (`None` si
+/// fue al cielo).
+#[derive(Clone, Copy, Debug)]
+pub struct Sample {
+    pub color: Vector3,
+    pub alpha: f32,
+    pub depth: f32,
+    pub material: Option<MaterialId>,
+}
+
+impl Sample {
+    /// Si entre dos pixeles vecinos hay un borde que merece más muestras.
+    fn differs(&self, other: &Sample) -> bool {
+        let color = perceived(self.color) - perceived(other.color);
+        if color.x.abs().max(color.y.abs()).max(color.z.abs()) > COLOR_THRESHOLD {
+            return true;
+        }
+        if self.material != other.material {
+            return true;
+        }
+        let nearest = self.depth.min(other.depth);
+        nearest.is_finite() && (self.depth - other.depth).abs() > DEPTH_THRESHOLD * nearest
+    }
+}
+
+/// La radiancia con Reinhard y en sRGB: no depende del mapeo de tonos
+/// elegido, pero las diferencias se miden como se verían y no en lineal.
+fn perceived(c: Vector3) -> Vector3 {
+    let f = |x: f32| linear_to_srgb(x / (1.0 + x));
+    Vector3::new(f(c.x), f(c.y), f(c.z))
+}
+
+/// Renderiza la imagen con `trace(x, y, offset, lens)`, que traza el rayo
+/// que pasa por el punto `offset` del pixel y sale del punto `lens` de la
+/// apertura (ambos en [0, 1)²). Cada pixel usa al menos `base`² muestras.
+///
+/// Las muestras se reparten con el filtro entre los pixeles cercanos; cada
+/// una pesa además `1 / n` si su pixel tiene `n`, para que los pixeles
+/// refinados no tapen a sus vecinos.
+pub fn render<F>(width: i32, height: i32, settings: &AntiAliasing, base: u32, trace: F) -> Framebuffer
+where
+    F: Fn(i32, i32, (f32, f32), (f32, f32)) -> Sample + Sync,
+{
+    let full = settings.grid.max(base);
+    let probes: Option<Vec<Sample>> = (settings.adaptive && full > base).then(|| {
+        (0..width * height)
+            .into_par_iter()
+            .map(|index| trace(index % width, index / width, (0.5, 0.5), (0.5, 0.5)))
+            .collect()
+    });
+    let refine: Option<Vec<bool>> = probes.as_ref().map(|probes| edges(probes, width, height));
+
+    let filter = settings.filter;
+    let margin = filter.margin();
+    let bands: Vec<(i32, Vec<Cell>)> = (0..height)
+        .into_par_iter()
+        .step_by(BAND_ROWS as usize)
+        .map(|y0| {
+            let y1 = (y0 + BAND_ROWS).min(height);
+            let top = y0 - margin;
+            let mut band: Vec<Cell> = vec![([0.0; 4], 0.0); ((y1 + margin - top) * width) as usize];
+            for y in y0..y1 {
+                for x in 0..width {
+                    let index = (y * width + x) as usize;
+                    let n = match &refine {
+                        Some(refine) if !refine[index] => base,
+                        _ => full,
+                    };
+
+                    let mut splat = |offset: (f32, f32), sample: &Sample| {
+                        let rgba = [sample.color.x, sample.color.y, sample.color.z, sample.alpha];
+                        let share = 1.0 / (n * n) as f32;
+                        // Pesos horizontales de las columnas `x - margin..=x + margin` (margin ≤ 2).
+                        let mut wx = [0.0f32; 5];
+                        for (k, w) in wx.iter_mut().enumerate().take((2 * margin + 1) as usize) {
+                            *w = filter.weight(offset.0 - 0.5 - (k as i32 - margin) as f32) * share;
+                        }
+                        for py in y - margin..=y + margin {
+                            let wy = filter.weight(y as f32 + offset.1 - (py as f32 + 0.5));
+                            for px in (x - margin).max(0)..=(x + margin).min(width - 1) {
+                                let w = wy * wx[(px - x + margin) as usize];
+                                if w != 0.0 {
+                                    let cell = &mut band[((py - top) * width + px) as usize];
+                                    for (sum, value) in cell.0.iter_mut().zip(rgba) {
+                                        *sum += value * w;
+                                    }
+                                    cell.1 += w;
+                                }
+                            }
+                        }
+                    };
+
+                    match &probes {
+                        // El rayo de prueba ya es la única muestra del pixel.
+                        Some(probes) if n == 1 => splat((0.5, 0.5), &probes[index]),
+                        _ => stratified(x, y, n, width, |offset, lens| splat(offset, &trace(x, y, offset, lens))),
+                    }
+                }
+            }
+            (top, band)
+        })
+        .collect();
+
+    let mut image: Vec<Cell> = vec![([0.0; 4], 0.0); (width * height) as usize];
+    for (top, band) in bands {
+        let rows = band.len() as i32 / width;
+        for y in top.max(0)..(top + rows).min(height) {
+            let src = ((y - top) * width) as usize;
+            let dst = (y * width) as usize;
+            for (pixel, cell) in image[dst..dst + width as usize].iter_mut().zip(&band[src..src + width as usize]) {
+                for (sum, value) in pixel.0.iter_mut().zip(cell.0) {
+                    *sum += value;
+                }
+                pixel.1 += cell.1;
+            }
+        }
+    }
+
+    let pixels = image
+        .into_iter()
+        .map(|(sum, weight)| {
+            if weight <= 0.0 {
+                return [0.0; 4];
+            }
+            // Los lóbulos negativos de Mitchell pueden dejar algún canal fuera de rango.
+            let [r, g, b, a] = sum.map(|c| (c / weight).max(0.0));
+            [r, g, b, a.min(1.0)]
+        })
+        .collect();
+    Framebuffer::from_pixels(width, height, pixels).expect("un pixel por cada posición de la imagen")
+}
+
+/// Llama a `sample(offset, lens)` con las `n`² muestras del pixel: un punto
+/// al azar dentro de cada estrato del pixel y otro en un estrato de la
+/// apertura, emparejados con una permutación para que la posición en el
+/// pixel no decida la del lente. La semilla depende solo del pixel, así que
+/// la imagen no parpadea entre frames.
+fn stratified(x: i32, y: i32, n: u32, width: i32, mut sample: impl FnMut((f32, f32), (f32, f32))) {
+    if n == 1 {
+        sample((0.5, 0.5), (0.5, 0.5));
+        return;
+    }
+
+    let mut rng = Rng::new(((y * width + x) as u64).wrapping_mul(0x9E3779B97F4A7C15));
+    let count = (n * n) as usize;
+    let mut lens_strata: Vec<u32> = (0..n * n).collect();
+    for i in (1..count).rev() {
+        let j = ((rng.next_f32() * (i + 1) as f32) as usize).min(i);
+        lens_strata.swap(i, j);
+    }
+
+    let strata = n as f32;
+    let point = |cell: u32, rng: &mut Rng| {
+        let (i, j) = (cell % n, cell / n);
+        ((i as f32 + rng.next_f32()) / strata, (j as f32 + rng.next_f32()) / strata)
+    };
+    for (cell, &lens_cell) in lens_strata.iter().enumerate() {
+        let offset = point(cell as u32, &mut rng);
+        let lens = point(lens_cell, &mut rng);
+        sample(offset, lens);
+    }
+}
+
+/// Pixeles que difieren de alguno de sus cuatro vecinos.
+fn edges(probes: &[Sample], width: i32, height: i32) -> Vec<bool> {
+    (0..width * height)
+        .into_par_iter()
+        .map(|index| {
+            let (x, y) = (index % width, index / width);
+            let here = &probes[index as usize];
+            [(-1, 0), (1, 0), (0, -1), (0, 1)].iter().any(|&(dx, dy)| {
+                let (nx, ny) = (x + dx, y + dy);
+                (0..width).contains(&nx)
+                    && (0..height).contains(&ny)
+                    && here.differs(&probes[(ny * width + nx) as usize])
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,127 @@
+use raylib::prelude::Vector3;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::material::{Material, MaterialId};
+use crate::ray_intersect::Intersect;
+use crate::shape::VoxelState;
+use crate::voxel::{face_uv_optimized, Dda, VoxelGrid};
+
+/// Voxel como se guardaba antes de la paleta: el material completo en cada
+/// celda. `id` solo sirve para devolver el mismo impacto que la grilla.
+struct ReferenceVoxel {
+    material: Material,
+    id: MaterialId,
+    state: VoxelState,
+}
+
+/// Grilla de referencia con un `HashMap` consultado en cada celda, como se
+/// guardaban los voxels antes de los chunks. Solo sirve para comparar.
+pub struct HashMapGrid {
+    cells: HashMap<(i32, i32, i32), ReferenceVoxel>,
+}
+
+impl HashMapGrid {
+    pub fn from_grid(grid: &VoxelGrid) -> Self {
+        HashMapGrid {
+            cells: grid
+                .iter()
+                .map(|(cell, voxel)| {
+                    let material = grid.material(voxel.material).clone();
+                    (cell, ReferenceVoxel { material, id: voxel.material, state: voxel.state })
+                })
+                .collect(),
+        }
+    }
+
+    /// El recorrido de siempre: misma caja y mismas formas que
+    /// `VoxelGrid::intersect_ray`, visitando cada celda. Cada impacto calcula
+    /// la normal y las UV y clona el material, y cada fallo crea un material
+    /// negro, como hacía el `Intersect` de entonces.
+    pub fn intersect_ray(&self, grid: &VoxelGrid, ro: &Vector3, rd: &Vector3, t_max: f32) -> Intersect {
+        let Some(t_entry) = grid.entry_distance(ro, rd, t_max) else {
+            std::hint::black_box(Material::black());
+            return Intersect::empty();
+        };
+        let start = *ro + *rd * t_entry;
+        let mut dda = Dda::new(&start, rd);
+        let (limit_min, limit_max) = grid.cell_limits();
+
+        let outside = |c: &[i32; 3]| (0..3).any(|a| c[a] < limit_min[a] || c[a] > limit_max[a]);
+
+        while !outside(&dda.cell) && dda.t_entered() + t_entry <= t_max {
+            let [cx, cy, cz] = dda.cell;
+            if let Some(voxel) = self.cells.get(&(cx, cy, cz)) {
+                let t_hit = dda.t_entered() + t_entry;
+                let shape = voxel.material.shape.oriented(&voxel.state);
+                let surface = if shape.is_full() {
+                    let normal = match dda.face {
+                        0 => Vector3::new(if rd.x > 0.0 { -1.0 } else { 1.0 }, 0.0, 0.0),
+                        1 => Vector3::new(0.0, if rd.y > 0.0 { -1.0 } else { 1.0 }, 0.0),
+                        2 => Vector3::new(0.0, 0.0, if rd.z > 0.0 { -1.0 } else { 1.0 }),
+                        _ => Vector3::new(0.0, 1.0, 0.0),
+                    };
+                    Some((t_hit, normal))
+                } else {
+                    shape.intersect((cx, cy, cz), ro, rd, t_hit - 1e-4, t_max)
+                };
+                if let Some((t_hit, normal)) = surface {
+                    let hit_point = *ro + *rd * t_hit;
+                    let (u, v) = face_uv_optimized(&hit_point, cx, cy, cz, &normal, &voxel.state);
+                    std::hint::black_box(voxel.material.clone());
+                    return Intersect::new_with_uv(hit_point, normal, t_hit, voxel.id, u, v)
+                        .with_state(voxel.state);
+                }
+            }
+            dda.advance();
+        }
+        std::hint::black_box(Material::black());
+        Intersect::empty()
+    }
+}
+
+/// Resultado de trazar los mismos rayos con las dos grillas.
+pub struct Comparison {
+    pub rays: usize,
+    pub reference: Duration,
+    pub hierarchical: Duration,
+    /// Rayos en los que una grilla acierta y la otra no, o a otra distancia.
+    pub mismatches: usize,
+    pub hits: usize,
+}
+
+/// Traza `rays` (origen, dirección) `repeats` veces con cada grilla, en un
+/// solo hilo, y devuelve el mejor tiempo de cada una.
+pub fn compare(grid: &VoxelGrid, rays: &[(Vector3, Vector3)], t_max: f32, repeats: u32) -> Comparison {
+    let reference_grid = HashMapGrid::from_grid(grid);
+
+    let time = |trace: &dyn Fn(&Vector3, &Vector3) -> Intersect| {
+        (0..repeats.max(1))
+            .map(|_| {
+                let start = Instant::now();
+                for (ro, rd) in rays {
+                    std::hint::black_box(trace(ro, rd));
+                }
+                start.elapsed()
+            })
+            .min()
+            .unwrap_or_default()
+    };
+    let reference = time(&|ro, rd| reference_grid.intersect_ray(grid, ro, rd, t_max));
+    let hierarchical = time(&|ro, rd| grid.intersect_ray(ro, rd, t_max));
+
+    let mut mismatches = 0;
+    let mut hits = 0;
+    for (ro, rd) in rays {
+        let a = reference_grid.intersect_ray(grid, ro, rd, t_max);
+        let b = grid.intersect_ray(ro, rd, t_max);
+        if b.is_intersecting {
+            hits += 1;
+        }
+        if a.is_intersecting != b.is_intersecting || (a.distance - b.distance).abs() > 1e-3 {
+            mismatches += 1;
+        }
+    }
+
+    Comparison { rays: rays.len(), reference, hierarchical, mismatches, hits }
+}
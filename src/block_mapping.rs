@@ -0,0 +1,345 @@
+use serde::de::{self, Deserializer, MapAccess, Visitor};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::Path;
+
+use crate::material::Material;
+use crate::material_library::MaterialLibrary;
+
+/// Bloques de Minecraft que siempre son aire.
+const AIR_BLOCKS: [&str; 4] = ["air", "cave_air", "void_air", "structure_void"];
+
+/// Correspondencia por defecto entre bloques vanilla y nuestros materiales.
+const DEFAULT_BLOCKS: [(&str, &str); 47] = [
+    ("stone", "stone"),
+    ("cobblestone", "stone"),
+    ("mossy_cobblestone", "stone"),
+    ("stone_bricks", "stone"),
+    ("mossy_stone_bricks", "stone"),
+    ("cracked_stone_bricks", "stone"),
+    ("smooth_stone", "stone"),
+    ("andesite", "stone"),
+    ("polished_andesite", "stone"),
+    ("chiseled_stone_bricks", "stone_pillar"),
+    ("stone_stairs", "stone_stairs"),
+    ("cobblestone_stairs", "stone_stairs"),
+    ("mossy_cobblestone_stairs", "stone_stairs"),
+    ("stone_brick_stairs", "stone_stairs"),
+    ("mossy_stone_brick_stairs", "stone_stairs"),
+    ("stone_slab", "stone_slab"),
+    ("smooth_stone_slab", "stone_slab"),
+    ("cobblestone_slab", "stone_slab"),
+    ("mossy_cobblestone_slab", "stone_slab"),
+    ("stone_brick_slab", "stone_slab"),
+    ("mossy_stone_brick_slab", "stone_slab"),
+    ("cobblestone_wall", "stone_pillar"),
+    ("stone_brick_wall", "stone_pillar"),
+    ("grass_block", "grass"),
+    ("dirt", "dirt"),
+    ("coarse_dirt", "dirt"),
+    ("rooted_dirt", "dirt"),
+    ("podzol", "dirt"),
+    ("dirt_path", "dirt"),
+    ("farmland", "dirt"),
+    ("netherrack", "netherrack"),
+    ("magma_block", "magma"),
+    ("lava", "magma"),
+    ("gold_block", "gold"),
+    ("raw_gold_block", "gold"),
+    ("obsidian", "obsidian"),
+    ("crying_obsidian", "glowing_obsidian"),
+    ("chest", "wood_chest"),
+    ("trapped_chest", "wood_chest"),
+    ("barrel", "wood_chest"),
+    ("glass", "glass"),
+    ("glass_pane", "glass"),
+    ("water", "water"),
+    ("ice", "ice"),
+    ("packed_ice", "ice"),
+    ("blue_ice", "ice"),
+    ("tinted_glass", "stained_glass"),
+];
+
+/// Colores de Minecraft en el orden de sus valores de datos antiguos.
+pub const DYE_COLORS: [&str; 16] = [
+    "white", "orange", "magenta", "light_blue", "yellow", "lime", "pink", "gray",
+    "light_gray", "cyan", "purple", "blue", "brown", "green", "red", "black",
+];
+
+/// Bloque con sus propiedades de estado, p. ej.
+/// `minecraft:stone_brick_stairs[facing=east,half=top]`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BlockState {
+    pub name: String,
+    pub properties: BTreeMap<String, String>,
+}
+
+impl BlockState {
+    /// Bloque sin propiedades. Los nombres sin espacio de nombres se
+    /// consideran `minecraft:`.
+    pub fn new(name: &str) -> BlockState {
+        let name = if name.contains(':') { name.to_string() } else { format!("minecraft:{}", name) };
+        BlockState { name, properties: BTreeMap::new() }
+    }
+
+    pub fn with(mut self, key: &str, value: &str) -> BlockState {
+        self.properties.insert(key.to_string(), value.to_string());
+        self
+    }
+
+    /// Interpreta `nombre[clave=valor,...]`, el formato de los comandos del
+    /// juego y de las paletas de `.schem`.
+    pub fn parse(text: &str) -> Result<BlockState, String> {
+        let text = text.trim();
+        let (name, rest) = match text.find('[') {
+            Some(open) => (&text[..open], Some(&text[open + 1..])),
+            None => (text, None),
+        };
+        if name.is_empty() {
+            return Err(format!("bloque sin nombre en `{}`", text));
+        }
+        let mut state = BlockState::new(name);
+        if let Some(rest) = rest {
+            let inner = rest
+                .strip_suffix(']')
+                .ok_or_else(|| format!("falta `]` en `{}`", text))?;
+            for pair in inner.split(',').filter(|p| !p.trim().is_empty()) {
+                let (key, value) = pair
+                    .split_once('=')
+                    .ok_or_else(|| format!("propiedad sin `=` en `{}`", text))?;
+                state.properties.insert(key.trim().to_string(), value.trim().to_string());
+            }
+        }
+        Ok(state)
+    }
+}
+
+impl fmt::Display for BlockState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        if !self.properties.is_empty() {
+            let properties: Vec<String> = self.properties.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+            write!(f, "[{}]", properties.join(","))?;
+        }
+        Ok(())
+    }
+}
+
+/// Resultado de buscar un bloque en la correspondencia. `T` es el material
+/// o lo que se haya obtenido de él.
+pub enum Mapped<T> {
+    Block(T),
+    Air,
+    Unmapped,
+}
+
+/// Regla para un bloque: se aplica si todas sus propiedades coinciden con las
+/// del estado. `None` significa aire.
+#[derive(Debug, Clone)]
+struct Rule {
+    properties: BTreeMap<String, String>,
+    material: Option<Material>,
+}
+
+/// Correspondencia entre bloques de Minecraft (con estado) y materiales.
+#[derive(Debug, Clone, Default)]
+pub struct BlockMapping {
+    rules: HashMap<String, Vec<Rule>>,
+}
+
+impl BlockMapping {
+    /// Reglas integradas para los bloques vanilla que tienen material propio.
+    /// Se resuelven con la biblioteca para respetar materiales redefinidos.
+    pub fn with_defaults(materials: &MaterialLibrary) -> BlockMapping {
+        let mut mapping = BlockMapping::default();
+        for name in AIR_BLOCKS {
+            mapping.add(BlockState::new(name), None);
+        }
+        for (block, material) in DEFAULT_BLOCKS {
+            mapping.add(BlockState::new(block), materials.get(material));
+        }
+        for color in DYE_COLORS {
+            let stained = materials.get("stained_glass");
+            mapping.add(BlockState::new(&format!("{}_stained_glass", color)), stained.clone());
+            mapping.add(BlockState::new(&format!("{}_stained_glass_pane", color)), stained);
+        }
+        mapping
+    }
+
+    /// Añade las reglas de un archivo TOML o JSON con una tabla `blocks` de
+    /// `"bloque[propiedades]" = "material"`. Reemplazan a las integradas y se
+    /// guardan en el orden del archivo, que decide los empates de `lookup`.
+    pub fn load(&mut self, path: &Path, materials: &MaterialLibrary) -> Result<(), String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("No se pudo leer {}: {}", path.display(), e))?;
+
+        let is_json = path
+            .extension()
+            .map(|ext| ext.eq_ignore_ascii_case("json"))
+            .unwrap_or(false);
+        let file: MappingFile = if is_json {
+            serde_json::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))?
+        } else {
+            toml::from_str(&text).map_err(|e| format!("{}:\n{}", path.display(), e))?
+        };
+
+        for (key, name) in file.blocks.0 {
+            let material = if name == "air" {
+                None
+            } else {
+                Some(materials.get(&name).ok_or_else(|| format!(
+                    "{}: [blocks] `{}`: material desconocido `{}` (disponibles: air, {})",
+                    path.display(), key.0, name, materials.names().join(", ")
+                ))?)
+            };
+            self.add(key.0, material);
+        }
+        Ok(())
+    }
+
+    fn add(&mut self, state: BlockState, material: Option<Material>) {
+        let rules = self.rules.entry(state.name).or_default();
+        rules.retain(|rule| rule.properties != state.properties);
+        rules.push(Rule { properties: state.properties, material });
+    }
+
+    /// Busca la regla más específica (la que más propiedades fija) entre las
+    /// que coinciden con el estado. Entre dos igual de específicas gana la
+    /// añadida después: las del archivo sobre las integradas, y dentro del
+    /// archivo la que aparece más abajo.
+    pub fn lookup(&self, state: &BlockState) -> Mapped<&Material> {
+        let best = self.rules.get(&state.name).and_then(|rules| {
+            rules
+                .iter()
+                .filter(|rule| rule.properties.iter().all(|(k, v)| state.properties.get(k) == Some(v)))
+                .max_by_key(|rule| rule.properties.len())
+        });
+        match best {
+            Some(Rule { material: Some(material), .. }) => Mapped::Block(material),
+            Some(Rule { material: None, .. }) => Mapped::Air,
+            None => Mapped::Unmapped,
+        }
+    }
+
+    /// Si hay alguna regla para el bloque, aunque ninguna coincida con este
+    /// estado. Sirve para decidir si el informe muestra las propiedades.
+    pub fn knows(&self, name: &str) -> bool {
+        self.rules.contains_key(name)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MappingFile {
+    #[serde(default)]
+    blocks: BlockRules,
+}
+
+/// Entradas de `blocks` en el orden en que aparecen en el archivo. Un mapa
+/// las desordenaría y los empates cambiarían de una ejecución a otra.
+#[derive(Default)]
+struct BlockRules(Vec<(BlockKey, String)>);
+
+impl<'de> Deserialize<'de> for BlockRules {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct RulesVisitor;
+
+        impl<'de> Visitor<'de> for RulesVisitor {
+            type Value = BlockRules;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("una tabla de `\"bloque[propiedades]\" = \"material\"`")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<BlockRules, A::Error> {
+                let mut rules = Vec::new();
+                while let Some(entry) = map.next_entry()? {
+                    rules.push(entry);
+                }
+                Ok(BlockRules(rules))
+            }
+        }
+
+        deserializer.deserialize_map(RulesVisitor)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(try_from = "String")]
+struct BlockKey(BlockState);
+
+impl TryFrom<String> for BlockKey {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, String> {
+        BlockState::parse(&s).map(BlockKey)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(name: &str, text: &str) -> Result<BlockMapping, String> {
+        let path = std::env::temp_dir().join(format!("blocks-test-{}-{}", std::process::id(), name));
+        std::fs::write(&path, text).unwrap();
+        let mut mapping = BlockMapping::with_defaults(&MaterialLibrary::default());
+        let result = mapping.load(&path, &MaterialLibrary::default());
+        std::fs::remove_file(&path).unwrap();
+        result.map(|_| mapping)
+    }
+
+    fn metallic(mapping: &BlockMapping, state: &str) -> Option<f32> {
+        match mapping.lookup(&BlockState::parse(state).unwrap()) {
+            Mapped::Block(material) => Some(material.metallic),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn most_specific_rule_wins() {
+        let mapping = load("specific.toml", r#"
+            [blocks]
+            "oak_stairs[facing=east,half=top]" = "gold"
+            "oak_stairs" = "stone"
+            "oak_stairs[half=bottom]" = "air"
+        "#).unwrap();
+        let gold = MaterialLibrary::default().get("gold").unwrap().metallic;
+        let stone = MaterialLibrary::default().get("stone").unwrap().metallic;
+        assert_eq!(metallic(&mapping, "oak_stairs[facing=east,half=top]"), Some(gold));
+        assert_eq!(metallic(&mapping, "oak_stairs[facing=west,half=top]"), Some(stone));
+        assert!(matches!(mapping.lookup(&BlockState::parse("oak_stairs[half=bottom]").unwrap()), Mapped::Air));
+        assert!(matches!(mapping.lookup(&BlockState::new("birch_stairs")), Mapped::Unmapped));
+    }
+
+    #[test]
+    fn ties_go_to_the_later_rule_in_the_file() {
+        let state = "oak_stairs[facing=east,half=top]";
+        let gold = MaterialLibrary::default().get("gold").unwrap().metallic;
+        let stone = MaterialLibrary::default().get("stone").unwrap().metallic;
+
+        for _ in 0..8 {
+            let mapping = load("ties.toml", r#"
+                [blocks]
+                "oak_stairs[facing=east]" = "stone"
+                "oak_stairs[half=top]" = "gold"
+            "#).unwrap();
+            assert_eq!(metallic(&mapping, state), Some(gold));
+
+            let mapping = load("ties.json", r#"{ "blocks": {
+                "oak_stairs[half=top]": "gold",
+                "oak_stairs[facing=east]": "stone"
+            } }"#).unwrap();
+            assert_eq!(metallic(&mapping, state), Some(stone));
+        }
+    }
+
+    #[test]
+    fn unknown_material_is_an_error() {
+        let err = load("unknown.toml", "[blocks]\n\"stone\" = \"cheese\"\n").err().unwrap();
+        assert!(err.contains("material desconocido `cheese`"), "{}", err);
+        let err = load("broken.toml", "[blocks]\n\"stone[facing\" = \"stone\"\n").err().unwrap();
+        assert!(err.contains("falta `]`"), "{}", err);
+    }
+}
@@ -0,0 +1,287 @@
+use raylib::prelude::Vector3;
+
+use crate::ray_intersect::{Intersect, RayIntersect};
+
+/// Cubetas en las que se reparten los centroides para evaluar los cortes.
+const SAH_BINS: usize = 16;
+/// Con tan pocas primitivas no compensa seguir dividiendo.
+const MAX_LEAF_SIZE: usize = 4;
+/// Coste de visitar un nodo frente al de intersecar una primitiva.
+const TRAVERSAL_COST: f32 = 1.0;
+
+/// Caja alineada a los ejes en coordenadas de mundo.
+#[derive(Debug, Clone, Copy)]
+pub struct Aabb {
+    pub min: Vector3,
+    pub max: Vector3,
+}
+
+impl Aabb {
+    /// Caja que no contiene nada; es el neutro de `union`.
+    pub const EMPTY: Aabb = Aabb {
+        min: Vector3 { x: f32::INFINITY, y: f32::INFINITY, z: f32::INFINITY },
+        max: Vector3 { x: f32::NEG_INFINITY, y: f32::NEG_INFINITY, z: f32::NEG_INFINITY },
+    };
+
+    pub fn new(min: Vector3, max: Vector3) -> Self {
+        Aabb { min, max }
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: Vector3::new(self.min.x.min(other.min.x), self.min.y.min(other.min.y), self.min.z.min(other.min.z)),
+            max: Vector3::new(self.max.x.max(other.max.x), self.max.y.max(other.max.y), self.max.z.max(other.max.z)),
+        }
+    }
+
+    pub fn grow(&self, point: Vector3) -> Aabb {
+        self.union(&Aabb::new(point, point))
+    }
+
+    pub fn centroid(&self) -> Vector3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn surface_area(&self) -> f32 {
+        let d = self.max - self.min;
+        if d.x < 0.0 || d.y < 0.0 || d.z < 0.0 {
+            return 0.0;
+        }
+        2.0 * (d.x * d.y + d.y * d.z + d.z * d.x)
+    }
+
+    /// Distancia de entrada del rayo en la caja (0 si empieza dentro), o
+    /// `None` si no la toca antes de `t_max`. `inv_dir` es `1 / rd`.
+    #[inline]
+    pub fn hit(&self, ro: &Vector3, inv_dir: &Vector3, t_max: f32) -> Option<f32> {
+        let t0 = (self.min - *ro) * *inv_dir;
+        let t1 = (self.max - *ro) * *inv_dir;
+        let t_near = t0.x.min(t1.x).max(t0.y.min(t1.y)).max(t0.z.min(t1.z)).max(0.0);
+        let t_far = t0.x.max(t1.x).min(t0.y.max(t1.y)).min(t0.z.max(t1.z)).min(t_max);
+        (t_near <= t_far).then_some(t_near)
+    }
+}
+
+#[derive(Debug)]
+enum Node {
+    /// Primitivas `start..start + count` del arreglo reordenado.
+    Leaf { bounds: Aabb, start: usize, count: usize },
+    /// El hijo izquierdo está justo después del nodo; `right` es el índice del derecho.
+    Interior { bounds: Aabb, right: usize },
+}
+
+impl Node {
+    fn bounds(&self) -> &Aabb {
+        match self {
+            Node::Leaf { bounds, .. } | Node::Interior { bounds, .. } => bounds,
+        }
+    }
+}
+
+/// Jerarquía de volúmenes envolventes construida con la heurística de área
+/// (SAH). Las primitivas sin caja, como los planos infinitos, quedan aparte
+/// y se prueban siempre.
+pub struct Bvh {
+    nodes: Vec<Node>,
+    primitives: Vec<Box<dyn RayIntersect>>,
+    unbounded: Vec<Box<dyn RayIntersect>>,
+}
+
+impl Bvh {
+    pub fn new(objects: Vec<Box<dyn RayIntersect>>) -> Self {
+        let mut items = Vec::new();
+        let mut unbounded = Vec::new();
+        for object in objects {
+            match object.bounding_box() {
+                Some(bounds) => items.push((bounds, object)),
+                None => unbounded.push(object),
+            }
+        }
+
+        let mut bvh = Bvh { nodes: Vec::new(), primitives: Vec::with_capacity(items.len()), unbounded };
+        if !items.is_empty() {
+            bvh.build(items);
+        }
+        bvh
+    }
+
+    pub fn len(&self) -> usize {
+        self.primitives.len() + self.unbounded.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Construye el subárbol de `items` y devuelve el índice de su nodo.
+    fn build(&mut self, mut items: Vec<(Aabb, Box<dyn RayIntersect>)>) -> usize {
+        let bounds = items.iter().fold(Aabb::EMPTY, |acc, (b, _)| acc.union(b));
+        let index = self.nodes.len();
+
+        let split = if items.len() > MAX_LEAF_SIZE { best_split(&items, &bounds) } else { None };
+        let Some(split) = split else {
+            self.nodes.push(Node::Leaf { bounds, start: self.primitives.len(), count: items.len() });
+            self.primitives.extend(items.into_iter().map(|(_, object)| object));
+            return index;
+        };
+
+        let right_items: Vec<_> = {
+            let (left, right): (Vec<_>, Vec<_>) =
+                items.drain(..).partition(|(b, _)| split.bin(b) <= split.last_left);
+            items = left;
+            right
+        };
+        self.nodes.push(Node::Interior { bounds, right: 0 });
+        self.build(items);
+        let right = self.build(right_items);
+        if let Node::Interior { right: slot, .. } = &mut self.nodes[index] {
+            *slot = right;
+        }
+        index
+    }
+
+    /// Impacto más cercano con cualquier primitiva antes de `t_max`.
+    pub fn intersect(&self, ro: &Vector3, rd: &Vector3, t_max: f32) -> Intersect {
+        self.closest(ro, rd, t_max, |object, limit| object.ray_intersect_before(ro, rd, limit))
+    }
+
+    /// Salida más cercana de un rayo que empieza dentro de un medio; ver
+    /// `RayIntersect::exit_medium`.
+    pub fn exit_medium(&self, ro: &Vector3, rd: &Vector3) -> Intersect {
+        self.closest(ro, rd, f32::INFINITY, |object, _| object.exit_medium(ro, rd))
+    }
+
+    /// Recorre las hojas que toca el rayo y se queda con el resultado de
+    /// `test` más cercano. `test` recibe la distancia del mejor hasta ahora.
+    fn closest(
+        &self,
+        ro: &Vector3,
+        rd: &Vector3,
+        t_max: f32,
+        test: impl Fn(&dyn RayIntersect, f32) -> Intersect,
+    ) -> Intersect {
+        let mut closest = Intersect::empty();
+        let mut limit = t_max;
+        let mut consider = |hit: Intersect, limit: &mut f32| {
+            if hit.is_intersecting && hit.distance <= *limit {
+                *limit = hit.distance;
+                closest = hit;
+            }
+        };
+
+        for object in &self.unbounded {
+            consider(test(object.as_ref(), limit), &mut limit);
+        }
+        if self.nodes.is_empty() {
+            return closest;
+        }
+
+        let inv_dir = Vector3::new(1.0 / rd.x, 1.0 / rd.y, 1.0 / rd.z);
+        let mut stack = vec![0usize];
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if node.bounds().hit(ro, &inv_dir, limit).is_none() {
+                continue;
+            }
+            match *node {
+                Node::Leaf { start, count, .. } => {
+                    for object in &self.primitives[start..start + count] {
+                        consider(test(object.as_ref(), limit), &mut limit);
+                    }
+                }
+                Node::Interior { right, .. } => {
+                    // Primero el hijo más cercano, para acortar `limit` cuanto antes.
+                    let left = index + 1;
+                    let t_left = self.nodes[left].bounds().hit(ro, &inv_dir, limit);
+                    let t_right = self.nodes[right].bounds().hit(ro, &inv_dir, limit);
+                    match (t_left, t_right) {
+                        (Some(a), Some(b)) if a <= b => stack.extend([right, left]),
+                        (Some(_), Some(_)) => stack.extend([left, right]),
+                        (Some(_), None) => stack.push(left),
+                        (None, Some(_)) => stack.push(right),
+                        (None, None) => {}
+                    }
+                }
+            }
+        }
+        closest
+    }
+}
+
+/// Corte entre cubetas: a la izquierda van las cubetas `0..=last_left`.
+#[derive(Debug, Clone, Copy)]
+struct Split {
+    axis: usize,
+    lo: f32,
+    extent: f32,
+    last_left: usize,
+}
+
+impl Split {
+    fn bin(&self, b: &Aabb) -> usize {
+        let offset = (axis_value(&b.centroid(), self.axis) - self.lo) / self.extent;
+        ((offset * SAH_BINS as f32) as usize).min(SAH_BINS - 1)
+    }
+}
+
+#[inline]
+fn axis_value(v: &Vector3, axis: usize) -> f32 {
+    match axis {
+        0 => v.x,
+        1 => v.y,
+        _ => v.z,
+    }
+}
+
+/// Mejor corte según la SAH, repartiendo los centroides en cubetas. `None`
+/// si dividir cuesta más que dejar una hoja.
+fn best_split(items: &[(Aabb, Box<dyn RayIntersect>)], bounds: &Aabb) -> Option<Split> {
+    let centroids = items.iter().fold(Aabb::EMPTY, |acc, (b, _)| acc.grow(b.centroid()));
+    let leaf_cost = items.len() as f32;
+    let parent_area = bounds.surface_area().max(f32::EPSILON);
+
+    let mut best: Option<(Split, f32)> = None;
+    for axis in 0..3 {
+        let lo = axis_value(&centroids.min, axis);
+        let extent = axis_value(&centroids.max, axis) - lo;
+        if extent <= 1e-6 {
+            continue;
+        }
+        let split = |last_left| Split { axis, lo, extent, last_left };
+
+        let mut bins = [(Aabb::EMPTY, 0usize); SAH_BINS];
+        for (b, _) in items {
+            let bin = &mut bins[split(0).bin(b)];
+            bin.0 = bin.0.union(b);
+            bin.1 += 1;
+        }
+
+        // Áreas y cuentas acumuladas desde la derecha para cada corte.
+        let mut right_area = [0.0f32; SAH_BINS];
+        let mut right_count = [0usize; SAH_BINS];
+        let (mut acc, mut count) = (Aabb::EMPTY, 0);
+        for i in (1..SAH_BINS).rev() {
+            acc = acc.union(&bins[i].0);
+            count += bins[i].1;
+            right_area[i] = acc.surface_area();
+            right_count[i] = count;
+        }
+
+        let (mut acc, mut count) = (Aabb::EMPTY, 0);
+        for i in 0..SAH_BINS - 1 {
+            acc = acc.union(&bins[i].0);
+            count += bins[i].1;
+            let (left, right) = (count, right_count[i + 1]);
+            if left == 0 || right == 0 {
+                continue;
+            }
+            let cost = TRAVERSAL_COST
+                + (acc.surface_area() * left as f32 + right_area[i + 1] * right as f32) / parent_area;
+            if best.is_none_or(|(_, c)| cost < c) {
+                best = Some((split(i), cost));
+            }
+        }
+    }
+
+    best.filter(|&(_, cost)| cost < leaf_cost).map(|(split, _)| split)
+}
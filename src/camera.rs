@@ -1,64 +1,277 @@
-use raylib::prelude::*;
+use clap::ValueEnum;
+use raylib::prelude::Vector3;
+use serde::Deserialize;
+use std::f32::consts::PI;
 
+/// Inclinación del isométrico clásico: la vista cae por la diagonal de un cubo.
+const ISOMETRIC_ELEVATION: f32 = 0.615_479_7; // atan(1/√2)
+
+pub struct Ray {
+    pub origin: Vector3,
+    pub direction: Vector3,
+}
+
+/// Cómo se proyecta la escena sobre la imagen.
+#[derive(ValueEnum, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Projection {
+    /// Cámara estenopeica: los objetos lejanos se ven más pequeños.
+    #[default]
+    Perspective,
+    /// Rayos paralelos; el alto visible es el que abarca el campo de visión
+    /// a la distancia del objetivo.
+    Orthographic,
+    /// Ortográfica mirando por una diagonal a 45° y 35,26° de elevación.
+    Isometric,
+}
+
+impl Projection {
+    /// La siguiente en el ciclo de la tecla O.
+    pub fn next(self) -> Projection {
+        match self {
+            Projection::Perspective => Projection::Orthographic,
+            Projection::Orthographic => Projection::Isometric,
+            Projection::Isometric => Projection::Perspective,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Projection::Perspective => "perspectiva",
+            Projection::Orthographic => "ortográfica",
+            Projection::Isometric => "isométrica",
+        }
+    }
+}
+
+/// Forma de la apertura del lente; es la forma que toman los puntos de luz
+/// desenfocados (bokeh).
+#[derive(ValueEnum, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Bokeh {
+    #[default]
+    Circle,
+    Hexagon,
+}
+
+impl Bokeh {
+    /// Punto de la apertura de radio 1 para `(u, v)` uniformes en [0, 1)²,
+    /// repartido de forma uniforme por el área.
+    pub fn sample(self, u: f32, v: f32) -> (f32, f32) {
+        match self {
+            // Mapeo concéntrico de Shirley: conserva la estratificación.
+            Bokeh::Circle => {
+                let (a, b) = (2.0 * u - 1.0, 2.0 * v - 1.0);
+                if a == 0.0 && b == 0.0 {
+                    return (0.0, 0.0);
+                }
+                let (r, theta) = if a.abs() > b.abs() {
+                    (a, PI / 4.0 * (b / a))
+                } else {
+                    (b, PI / 2.0 - PI / 4.0 * (a / b))
+                };
+                (r * theta.cos(), r * theta.sin())
+            }
+            // Uno de los seis triángulos del centro a dos vértices vecinos.
+            Bokeh::Hexagon => {
+                let sector = ((u * 6.0) as usize).min(5);
+                let u = u * 6.0 - sector as f32;
+                let corner = |k: usize| {
+                    let angle = k as f32 * PI / 3.0;
+                    (angle.cos(), angle.sin())
+                };
+                let (c0, c1) = (corner(sector), corner(sector + 1));
+                let su = u.sqrt();
+                let (w0, w1) = (su * (1.0 - v), su * v);
+                (c0.0 * w0 + c1.0 * w1, c0.1 * w0 + c1.1 * w1)
+            }
+        }
+    }
+}
+
+/// Cámara que mira de `eye` a `target`. La base (`forward`, `right`, `up_vec`)
+/// se recalcula cada vez que cambia la posición o la proyección.
+///
+/// `orbit_eye` es el ojo sin ajustar a la diagonal isométrica: al arrastrar
+/// el ratón se acumula ahí y la vista salta de diagonal en pasos de 90°.
+///
+/// Con `aperture > 0` es un lente delgado: los rayos salen de distintos
+/// puntos de la apertura y solo queda nítido el plano a `focus_distance`.
+#[derive(Clone, Copy, Debug)]
 pub struct Camera {
-    pub eye: Vector3,     
-    pub center: Vector3, 
-    pub up: Vector3,      
-    pub forward: Vector3, 
-    pub right: Vector3,   
+    pub eye: Vector3,
+    pub target: Vector3,
+    pub up: Vector3,
+    pub vfov_deg: f32,
+    /// Radio del lente en bloques; 0 es una cámara estenopeica.
+    pub aperture: f32,
+    /// Distancia al plano nítido a lo largo de la vista; `None` enfoca el
+    /// objetivo.
+    pub focus_distance: Option<f32>,
+    pub bokeh: Bokeh,
+    projection: Projection,
+    orbit_eye: Vector3,
+    forward: Vector3,
+    right: Vector3,
+    up_vec: Vector3,
 }
 
 impl Camera {
-    pub fn new(eye: Vector3, center: Vector3, up: Vector3) -> Self {
-        let mut camera = Camera {
-            eye,
-            center,
-            up,
-            forward: Vector3::zero(), 
-            right: Vector3::zero(),   
+    pub fn new(eye: Vector3, target: Vector3, up: Vector3, vfov_deg: f32, projection: Projection) -> Self {
+        let mut cam = Self {
+            eye, target, up, vfov_deg, projection,
+            aperture: 0.0,
+            focus_distance: None,
+            bokeh: Bokeh::default(),
+            orbit_eye: eye,
+            forward: Vector3::zero(),
+            right: Vector3::zero(),
+            up_vec: Vector3::zero(),
         };
-        camera.update_basis_vectors();
-        camera
+        cam.set_position(eye, target);
+        cam
     }
 
-    pub fn update_basis_vectors(&mut self) {
-        self.forward = (self.center - self.eye).normalized();
-        
+    fn update_vectors(&mut self) {
+        self.forward = (self.target - self.eye).normalized();
         self.right = self.forward.cross(self.up).normalized();
-        
-        self.up = self.right.cross(self.forward);
+        self.up_vec = self.right.cross(self.forward);
+    }
+
+    /// En isométrica el ojo se lleva a la diagonal más cercana, a la misma
+    /// distancia del objetivo.
+    pub fn set_position(&mut self, eye: Vector3, target: Vector3) {
+        self.orbit_eye = eye;
+        self.place(eye, target);
     }
 
+    fn place(&mut self, eye: Vector3, target: Vector3) {
+        self.eye = eye;
+        self.target = target;
+        if self.projection == Projection::Isometric {
+            let relative = eye - target;
+            let (sx, sz) = (sign(relative.x), sign(relative.z));
+            let (cos_e, sin_e) = (ISOMETRIC_ELEVATION.cos(), ISOMETRIC_ELEVATION.sin());
+            let diagonal = Vector3::new(sx * cos_e * 0.5f32.sqrt(), sin_e, sz * cos_e * 0.5f32.sqrt());
+            self.eye = target + diagonal * relative.length();
+        }
+        self.update_vectors();
+    }
+
+    pub fn projection(&self) -> Projection {
+        self.projection
+    }
+
+    pub fn set_projection(&mut self, projection: Projection) {
+        self.projection = projection;
+        self.place(self.orbit_eye, self.target);
+    }
+
+    /// Desplaza el ojo y el objetivo juntos.
+    pub fn translate(&mut self, offset: Vector3) {
+        self.orbit_eye += offset;
+        self.place(self.eye + offset, self.target + offset);
+    }
+
+    /// Gira el ojo alrededor del objetivo; `pitch` se limita para no pasar
+    /// por encima de los polos.
     pub fn orbit(&mut self, yaw: f32, pitch: f32) {
+        let relative = self.orbit_eye - self.target;
+        let radius = relative.length();
 
-        let relative_pos = self.eye - self.center;
-
-        let radius = relative_pos.length();
-
-        let current_yaw = relative_pos.z.atan2(relative_pos.x);
-        let current_pitch = (relative_pos.y / radius).asin();
-        
-        let new_yaw = current_yaw + yaw;
-        let new_pitch = (current_pitch + pitch).clamp(-1.5, 1.5); 
-        
-        let cos_pitch = new_pitch.cos();
-        let new_relative_pos = Vector3::new(
-            radius * cos_pitch * new_yaw.cos(),  
-            radius * new_pitch.sin(),              
-            radius * cos_pitch * new_yaw.sin(),  
+        let theta = relative.z.atan2(relative.x) + yaw;
+        let phi = ((relative.y / radius).asin() + pitch).clamp(-1.5, 1.5);
+
+        let relative = Vector3::new(
+            radius * phi.cos() * theta.cos(),
+            radius * phi.sin(),
+            radius * phi.cos() * theta.sin(),
         );
-        
-        self.eye = self.center + new_relative_pos;
-        
-        self.update_basis_vectors();
+        self.set_position(self.target + relative, self.target);
+    }
+
+    #[inline]
+    pub fn forward(&self) -> Vector3 {
+        self.forward
     }
 
-    pub fn basis_change(&self, v: &Vector3) -> Vector3 {
+    #[inline]
+    pub fn right(&self) -> Vector3 {
+        self.right
+    }
+
+    #[inline]
+    pub fn has_lens(&self) -> bool {
+        self.aperture > 0.0
+    }
+
+    pub fn focus(&self) -> f32 {
+        self.focus_distance.unwrap_or_else(|| (self.target - self.eye).length())
+    }
 
-        Vector3::new(
-            v.x * self.right.x + v.y * self.up.x - v.z * self.forward.x,
-            v.x * self.right.y + v.y * self.up.y - v.z * self.forward.y,
-            v.x * self.right.z + v.y * self.up.z - v.z * self.forward.z,
-        )
+    /// Enfoca el punto que alcanzó `ray` a distancia `t`.
+    pub fn focus_on(&mut self, ray: &Ray, t: f32) {
+        self.focus_distance = Some((t * ray.direction.dot(self.forward)).max(1e-3));
     }
+
+    #[inline]
+    pub fn get_ray(&self, x: i32, y: i32, width: i32, height: i32) -> Ray {
+        self.get_ray_offset(x, y, 0.5, 0.5, width, height)
+    }
+
+    /// Rayo que pasa por el punto `(dx, dy)` del pixel, con `dx, dy` en [0, 1).
+    #[inline]
+    pub fn get_ray_offset(&self, x: i32, y: i32, dx: f32, dy: f32, width: i32, height: i32) -> Ray {
+        let aspect = width as f32 / height as f32;
+        let fov = (self.vfov_deg * PI / 180.0).tan();
+
+        let ndc_x = ((x as f32 + dx) / width as f32) * 2.0 - 1.0;
+        let ndc_y = 1.0 - ((y as f32 + dy) / height as f32) * 2.0;
+
+        match self.projection {
+            Projection::Perspective => {
+                let direction = self.right * (ndc_x * aspect * fov) +
+                               self.up_vec * (ndc_y * fov) +
+                               self.forward;
+                Ray {
+                    origin: self.eye,
+                    direction: direction.normalized(),
+                }
+            }
+            // Mismo encuadre que la perspectiva en el plano del objetivo; los
+            // rayos salen del plano del ojo.
+            Projection::Orthographic | Projection::Isometric => {
+                let half_height = (self.target - self.eye).length() * fov;
+                Ray {
+                    origin: self.eye
+                        + self.right * (ndc_x * aspect * half_height)
+                        + self.up_vec * (ndc_y * half_height),
+                    direction: self.forward,
+                }
+            }
+        }
+    }
+
+    /// Como `get_ray_offset` con `offset = (dx, dy)`, pero saliendo del punto
+    /// `lens` de la apertura (en [0, 1)², ver `Bokeh::sample`) hacia el punto
+    /// que el rayo central tiene en el plano de foco. Sin lente es el rayo
+    /// central.
+    #[inline]
+    pub fn get_ray_lens(&self, x: i32, y: i32, offset: (f32, f32), lens: (f32, f32), width: i32, height: i32) -> Ray {
+        let ray = self.get_ray_offset(x, y, offset.0, offset.1, width, height);
+        if !self.has_lens() {
+            return ray;
+        }
+        let focus_point = ray.origin + ray.direction * (self.focus() / ray.direction.dot(self.forward));
+        let (lx, ly) = self.bokeh.sample(lens.0, lens.1);
+        let origin = ray.origin + self.right * (lx * self.aperture) + self.up_vec * (ly * self.aperture);
+        Ray {
+            origin,
+            direction: (focus_point - origin).normalized(),
+        }
+    }
+}
+
+fn sign(v: f32) -> f32 {
+    if v < 0.0 { -1.0 } else { 1.0 }
 }
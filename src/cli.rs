@@ -0,0 +1,360 @@
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use raylib::prelude::{Color, Vector3};
+use serde::Deserialize;
+use std::path::PathBuf;
+
+use crate::antialias::Filter;
+use crate::camera::{Bokeh, Projection};
+use crate::color::ToneMapper;
+use crate::light::{Light, DEFAULT_LIGHT_INTENSITY, SPOT_INNER_FRACTION};
+
+use crate::RENDER_SCALE;
+
+
+/// Raytracer del diorama del portal en ruinas de Minecraft.
+///
+/// Sin subcomando se abre la ventana interactiva (equivale a `view`).
+#[derive(Parser, Debug)]
+#[command(name = "minecraft-diorama-raytracer", version, args_conflicts_with_subcommands = true)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    #[command(flatten)]
+    pub view: ViewArgs,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Abre la ventana interactiva.
+    View(ViewArgs),
+    /// Renderiza un único frame a un archivo PNG/JPEG, o EXR/HDR en coma
+    /// flotante, sin abrir ventana.
+    Render(RenderArgs),
+    /// Muestra información de la escena y la cámara inicial.
+    Info(InfoArgs),
+    /// Mide el recorrido jerárquico de la grilla frente al `HashMap` anterior
+    /// con los rayos primarios de la cámara.
+    Bench(BenchArgs),
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ViewArgs {
+    #[command(flatten)]
+    pub scene: SceneArgs,
+
+    #[command(flatten)]
+    pub camera: CameraArgs,
+
+    #[command(flatten)]
+    pub quality: QualityArgs,
+
+    /// Factor de reducción de la resolución interna respecto a la ventana.
+    #[arg(long, default_value_t = RENDER_SCALE, value_parser = clap::value_parser!(i32).range(1..))]
+    pub scale: i32,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct RenderArgs {
+    /// Archivo de salida; el formato se elige por la extensión. `.exr` y
+    /// `.hdr` guardan la radiancia lineal sin mapeo de tonos.
+    #[arg(short, long)]
+    pub output: String,
+
+    #[command(flatten)]
+    pub scene: SceneArgs,
+
+    #[command(flatten)]
+    pub camera: CameraArgs,
+
+    #[command(flatten)]
+    pub quality: QualityArgs,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct InfoArgs {
+    #[command(flatten)]
+    pub scene: SceneArgs,
+
+    #[command(flatten)]
+    pub camera: CameraArgs,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct BenchArgs {
+    #[command(flatten)]
+    pub scene: SceneArgs,
+
+    #[command(flatten)]
+    pub camera: CameraArgs,
+
+    #[command(flatten)]
+    pub quality: QualityArgs,
+
+    /// Veces que se trazan todos los rayos; se informa el mejor tiempo.
+    #[arg(long, default_value_t = 5, value_parser = clap::value_parser!(u32).range(1..))]
+    pub repeats: u32,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct SceneArgs {
+    /// Archivo de escena TOML o JSON con capas, paleta, luces, cielo, cámaras
+    /// y ajustes de render. Las demás opciones tienen prioridad sobre él.
+    #[arg(long)]
+    pub scene: Option<PathBuf>,
+
+    /// Directorio de capas: se cargan todos sus `.txt` en orden natural
+    /// (por defecto `layers`).
+    #[arg(long)]
+    pub layers: Option<PathBuf>,
+
+    /// Modelo a importar: MagicaVoxel `.vox`, Sponge `.schem`, MCEdit
+    /// `.schematic` o estructura `.nbt`. Se puede repetir.
+    #[arg(long = "model")]
+    pub models: Vec<PathBuf>,
+
+    /// Correspondencia de bloques de Minecraft a materiales (TOML/JSON) que
+    /// se suma a la integrada y a la de la escena. Se puede repetir.
+    #[arg(long = "blocks")]
+    pub blocks: Vec<PathBuf>,
+
+    /// Dirección del sol como `x,y,z` (se normaliza).
+    #[arg(long, value_parser = parse_direction, allow_hyphen_values = true)]
+    pub sun: Option<Vector3>,
+
+    /// Luz puntual como `x,y,z[,r,g,b[,intensidad]]` (color 0-255). Se puede repetir.
+    #[arg(long = "light", value_parser = parse_point_light, allow_hyphen_values = true)]
+    pub lights: Vec<Light>,
+
+    /// Foco como `x,y,z,dx,dy,dz,ángulo[,r,g,b[,intensidad]]`. Se puede repetir.
+    #[arg(long = "spot", value_parser = parse_spot_light, allow_hyphen_values = true)]
+    pub spots: Vec<Light>,
+
+    /// Escala de la luz que emiten los voxels emisivos (magma, obsidiana
+    /// brillante) sobre su entorno. 0 la desactiva (por defecto 4).
+    #[arg(long, value_parser = parse_non_negative)]
+    pub emissive_strength: Option<f32>,
+}
+
+impl SceneArgs {
+    /// Todas las luces puntuales y focos pedidos en la línea de comandos.
+    pub fn all_lights(&self) -> Vec<Light> {
+        self.lights.iter().chain(&self.spots).copied().collect()
+    }
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct CameraArgs {
+    /// Posición inicial de la cámara como `x,y,z`.
+    #[arg(long, value_parser = parse_vec3, allow_hyphen_values = true)]
+    pub eye: Option<Vector3>,
+
+    /// Punto al que mira la cámara como `x,y,z`.
+    #[arg(long, value_parser = parse_vec3, allow_hyphen_values = true)]
+    pub target: Option<Vector3>,
+
+    /// Campo de visión vertical en grados (por defecto 45).
+    #[arg(long, value_parser = parse_fov)]
+    pub fov: Option<f32>,
+
+    /// Cámara predefinida de la escena con la que empezar.
+    #[arg(long)]
+    pub camera: Option<String>,
+
+    /// Proyección inicial; en la ventana se alterna con O (por defecto
+    /// perspective).
+    #[arg(long, value_enum)]
+    pub projection: Option<Projection>,
+
+    /// Radio del lente en bloques para la profundidad de campo; 0 es una
+    /// cámara estenopeica, todo nítido (por defecto 0).
+    #[arg(long, value_parser = parse_non_negative)]
+    pub aperture: Option<f32>,
+
+    /// Distancia al plano nítido (por defecto la distancia al objetivo).
+    #[arg(long, value_parser = parse_positive)]
+    pub focus_distance: Option<f32>,
+
+    /// Forma de la apertura y de los puntos de luz desenfocados.
+    #[arg(long, value_enum)]
+    pub bokeh: Option<Bokeh>,
+}
+
+/// Algoritmo con el que se calcula cada pixel.
+#[derive(ValueEnum, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Integrator {
+    /// Raytracing clásico: sombras, reflejos y refracción deterministas.
+    Whitted,
+    /// Path tracing Monte Carlo con iluminación global; acumula muestras.
+    Path,
+}
+
+/// Ajustes de calidad. Los que no se indican toman el valor de la escena o
+/// el valor por defecto del programa.
+#[derive(Args, Debug, Clone)]
+pub struct QualityArgs {
+    /// Ancho de la imagen en pixeles (por defecto 1200).
+    #[arg(long, value_parser = clap::value_parser!(i32).range(1..))]
+    pub width: Option<i32>,
+
+    /// Alto de la imagen en pixeles (por defecto 800).
+    #[arg(long, value_parser = clap::value_parser!(i32).range(1..))]
+    pub height: Option<i32>,
+
+    /// Distancia máxima que recorre un rayo (por defecto sin límite: el rayo
+    /// sigue hasta salir de la estructura).
+    #[arg(long)]
+    pub max_dist: Option<f32>,
+
+    /// Número máximo de rebotes de reflexión (por defecto 3).
+    #[arg(long)]
+    pub max_reflections: Option<u32>,
+
+    /// Integrador inicial; en la ventana se alterna con P (por defecto whitted).
+    #[arg(long, value_enum)]
+    pub integrator: Option<Integrator>,
+
+    /// Muestras por pixel al renderizar con `--integrator path` (por defecto 64).
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub samples: Option<u32>,
+
+    /// Rebotes máximos de cada camino en el path tracer (por defecto 5).
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub max_bounces: Option<u32>,
+
+    /// Supersampling del modo Whitted: N×N muestras estratificadas por pixel
+    /// (por defecto 1, un rayo por el centro).
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub aa: Option<u32>,
+
+    /// Filtro con el que se combinan las muestras de cada pixel (por defecto
+    /// box).
+    #[arg(long, value_enum)]
+    pub filter: Option<Filter>,
+
+    /// Usa las N×N muestras de `--aa` solo donde los pixeles vecinos
+    /// difieren en color, profundidad o material.
+    #[arg(long)]
+    pub adaptive: bool,
+
+    /// Curva con la que la radiancia se lleva al rango de la pantalla; en la
+    /// ventana se alterna con T (por defecto aces).
+    #[arg(long, value_enum)]
+    pub tonemap: Option<ToneMapper>,
+
+    /// Exposición en pasos (EV): +1 duplica la luz y -1 la reduce a la
+    /// mitad (por defecto 0).
+    #[arg(long, value_parser = parse_finite, allow_hyphen_values = true)]
+    pub exposure: Option<f32>,
+}
+
+fn parse_numbers(s: &str) -> Result<Vec<f32>, String> {
+    s.split(',')
+        .map(|p| p.trim())
+        .map(|p| p.parse::<f32>().map_err(|_| format!("`{}` no es un número válido", p)))
+        .collect()
+}
+
+pub fn parse_vec3(s: &str) -> Result<Vector3, String> {
+    let values = parse_numbers(s)?;
+    if values.len() != 3 {
+        return Err(format!("se esperaba `x,y,z`, se recibió `{}`", s));
+    }
+    Ok(Vector3::new(values[0], values[1], values[2]))
+}
+
+/// Color e intensidad opcionales al final de una luz: `[r,g,b[,intensidad]]`.
+fn parse_light_tail(values: &[f32], s: &str) -> Result<(Color, f32), String> {
+    let color = match values.len() {
+        0 => Color::WHITE,
+        3 | 4 => {
+            if values[..3].iter().any(|c| !(0.0..=255.0).contains(c)) {
+                return Err(format!("el color de la luz debe estar entre 0 y 255: `{}`", s));
+            }
+            Color::new(values[0] as u8, values[1] as u8, values[2] as u8, 255)
+        }
+        _ => return Err(format!("formato de luz inválido: `{}`", s)),
+    };
+    let intensity = values.get(3).copied().unwrap_or(DEFAULT_LIGHT_INTENSITY);
+    if intensity < 0.0 {
+        return Err("la intensidad de la luz no puede ser negativa".to_string());
+    }
+    Ok((color, intensity))
+}
+
+fn parse_point_light(s: &str) -> Result<Light, String> {
+    let values = parse_numbers(s)?;
+    if values.len() < 3 {
+        return Err(format!("se esperaba `x,y,z[,r,g,b[,intensidad]]`, se recibió `{}`", s));
+    }
+    let (color, intensity) = parse_light_tail(&values[3..], s)?;
+    Ok(Light::new(Vector3::new(values[0], values[1], values[2]), color, intensity))
+}
+
+fn parse_spot_light(s: &str) -> Result<Light, String> {
+    let values = parse_numbers(s)?;
+    if values.len() < 7 {
+        return Err(format!(
+            "se esperaba `x,y,z,dx,dy,dz,ángulo[,r,g,b[,intensidad]]`, se recibió `{}`", s
+        ));
+    }
+    let direction = Vector3::new(values[3], values[4], values[5]);
+    if direction.length() < 1e-6 {
+        return Err("la dirección del foco no puede ser el vector cero".to_string());
+    }
+    let angle = values[6];
+    if angle <= 0.0 || angle >= 90.0 {
+        return Err("el ángulo del foco debe estar entre 0 y 90 grados".to_string());
+    }
+    let (color, intensity) = parse_light_tail(&values[7..], s)?;
+    Ok(Light::spot(
+        Vector3::new(values[0], values[1], values[2]),
+        direction,
+        color,
+        intensity,
+        angle * SPOT_INNER_FRACTION,
+        angle,
+    ))
+}
+
+fn parse_direction(s: &str) -> Result<Vector3, String> {
+    let dir = parse_vec3(s)?;
+    if dir.length() < 1e-6 {
+        return Err("la dirección no puede ser el vector cero".to_string());
+    }
+    Ok(dir.normalized())
+}
+
+fn parse_finite(s: &str) -> Result<f32, String> {
+    s.parse::<f32>()
+        .ok()
+        .filter(|v| v.is_finite())
+        .ok_or_else(|| format!("`{}` no es un número válido", s))
+}
+
+fn parse_non_negative(s: &str) -> Result<f32, String> {
+    let value = parse_finite(s)?;
+    if value < 0.0 {
+        return Err("el valor no puede ser negativo".to_string());
+    }
+    Ok(value)
+}
+
+fn parse_positive(s: &str) -> Result<f32, String> {
+    let value = parse_finite(s)?;
+    if value <= 0.0 {
+        return Err("el valor debe ser mayor que cero".to_string());
+    }
+    Ok(value)
+}
+
+fn parse_fov(s: &str) -> Result<f32, String> {
+    let fov = s
+        .parse::<f32>()
+        .map_err(|_| format!("`{}` no es un número válido", s))?;
+    if fov <= 0.0 || fov >= 90.0 {
+        return Err("el campo de visión debe estar entre 0 y 90 grados".to_string());
+    }
+    Ok(fov)
+}
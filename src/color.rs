@@ -0,0 +1,159 @@
+use clap::ValueEnum;
+use raylib::prelude::{Color, Vector3};
+use rayon::prelude::*;
+use serde::Deserialize;
+
+use crate::framebuffer::Framebuffer;
+
+lazy_static::lazy_static! {
+    /// Valor lineal de cada uno de los 256 niveles de un canal sRGB de 8 bits.
+    static ref SRGB_TO_LINEAR: [f32; 256] =
+        std::array::from_fn(|i| srgb_to_linear(i as f32 / 255.0));
+}
+
+/// Decodifica un canal sRGB en [0, 1] a luz lineal.
+#[inline]
+pub fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Codifica un canal lineal en [0, 1] a sRGB.
+#[inline]
+pub fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.003_130_8 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Color sRGB con canales en [0, 1], como los de los materiales y las
+/// paletas, pasado a lineal.
+#[inline]
+pub fn srgb_to_linear_rgb(c: Vector3) -> Vector3 {
+    Vector3::new(srgb_to_linear(c.x), srgb_to_linear(c.y), srgb_to_linear(c.z))
+}
+
+/// Color de 8 bits (texturas, luces, sol) pasado a lineal.
+#[inline]
+pub fn color_to_linear(c: Color) -> Vector3 {
+    let table = &*SRGB_TO_LINEAR;
+    Vector3::new(table[c.r as usize], table[c.g as usize], table[c.b as usize])
+}
+
+/// Curva que comprime la radiancia, sin límite, al rango de la pantalla.
+#[derive(ValueEnum, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ToneMapper {
+    /// `x / (1 + x)` por canal: suave, pero apaga los blancos.
+    Reinhard,
+    /// Aproximación de la transformada de ACES (RRT + ODT) de Stephen Hill.
+    #[default]
+    Aces,
+    /// AgX: desatura hacia blanco las luces muy intensas en lugar de saturar
+    /// cada canal por separado.
+    Agx,
+}
+
+impl ToneMapper {
+    /// El siguiente en el ciclo de la tecla T.
+    pub fn next(self) -> ToneMapper {
+        match self {
+            ToneMapper::Reinhard => ToneMapper::Aces,
+            ToneMapper::Aces => ToneMapper::Agx,
+            ToneMapper::Agx => ToneMapper::Reinhard,
+        }
+    }
+
+    /// Radiancia lineal a luz lineal de pantalla en [0, 1].
+    fn map(self, c: Vector3) -> Vector3 {
+        match self {
+            ToneMapper::Reinhard => Vector3::new(c.x / (1.0 + c.x), c.y / (1.0 + c.y), c.z / (1.0 + c.z)),
+            ToneMapper::Aces => {
+                const INPUT: [[f32; 3]; 3] = [
+                    [0.59719, 0.35458, 0.04823],
+                    [0.07600, 0.90834, 0.01566],
+                    [0.02840, 0.13383, 0.83777],
+                ];
+                const OUTPUT: [[f32; 3]; 3] = [
+                    [1.60475, -0.53108, -0.07367],
+                    [-0.10208, 1.10813, -0.00605],
+                    [-0.00327, -0.07276, 1.07602],
+                ];
+                let fit = |v: f32| (v * (v + 0.024_578_6) - 0.000_090_537) / (v * (0.983_729 * v + 0.432_951) + 0.238_081);
+                let v = mul(&INPUT, c);
+                mul(&OUTPUT, Vector3::new(fit(v.x), fit(v.y), fit(v.z)))
+            }
+            ToneMapper::Agx => {
+                const INSET: [[f32; 3]; 3] = [
+                    [0.842_479_06, 0.078_433_6, 0.079_223_745],
+                    [0.042_328_242, 0.878_468_6, 0.079_166_13],
+                    [0.042_375_655, 0.078_433_6, 0.879_143],
+                ];
+                const OUTSET: [[f32; 3]; 3] = [
+                    [1.196_879, -0.098_020_88, -0.099_029_74],
+                    [-0.052_896_85, 1.151_903_1, -0.098_961_18],
+                    [-0.052_971_635, -0.098_043_45, 1.151_073_7],
+                ];
+                const MIN_EV: f32 = -12.473_931;
+                const MAX_EV: f32 = 4.026_069;
+                // Contraste por defecto de AgX, ajustado con un polinomio.
+                let curve = |v: f32| {
+                    let x = ((v.max(1e-10).log2().clamp(MIN_EV, MAX_EV)) - MIN_EV) / (MAX_EV - MIN_EV);
+                    let (x2, x4) = (x * x, x * x * x * x);
+                    15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x - 0.00232
+                };
+                let v = mul(&INSET, c);
+                let v = mul(&OUTSET, Vector3::new(curve(v.x), curve(v.y), curve(v.z)));
+                // La curva sale con gamma 2.2; se vuelve a lineal para codificar en sRGB.
+                let linear = |v: f32| v.clamp(0.0, 1.0).powf(2.2);
+                Vector3::new(linear(v.x), linear(v.y), linear(v.z))
+            }
+        }
+    }
+}
+
+fn mul(m: &[[f32; 3]; 3], v: Vector3) -> Vector3 {
+    Vector3::new(
+        m[0][0] * v.x + m[0][1] * v.y + m[0][2] * v.z,
+        m[1][0] * v.x + m[1][1] * v.y + m[1][2] * v.z,
+        m[2][0] * v.x + m[2][1] * v.y + m[2][2] * v.z,
+    )
+}
+
+/// Salida de la imagen: la radiancia lineal se escala por la exposición, se
+/// comprime con el mapeo de tonos y se codifica en sRGB de 8 bits. Es el
+/// único lugar donde se limita el color.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ToneMapping {
+    pub operator: ToneMapper,
+    /// Pasos (EV): cada +1 duplica la luz.
+    pub exposure: f32,
+}
+
+impl ToneMapping {
+    pub fn to_color(self, radiance: Vector3) -> Color {
+        let scale = self.exposure.exp2();
+        let exposed = Vector3::new(
+            (radiance.x * scale).max(0.0),
+            (radiance.y * scale).max(0.0),
+            (radiance.z * scale).max(0.0),
+        );
+        let mapped = self.operator.map(exposed);
+        let encode = |c: f32| (linear_to_srgb(c.clamp(0.0, 1.0)) * 255.0).round() as u8;
+        Color::new(encode(mapped.x), encode(mapped.y), encode(mapped.z), 255)
+    }
+
+    /// El frame entero para la pantalla o un PNG; el alfa se descarta.
+    pub fn to_colors(self, frame: &Framebuffer) -> Vec<Color> {
+        frame
+            .pixels()
+            .par_iter()
+            .map(|&[r, g, b, _]| self.to_color(Vector3::new(r, g, b)))
+            .collect()
+    }
+}
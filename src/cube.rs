@@ -1,11 +1,12 @@
-use crate::material::Material;
+use crate::bvh::Aabb;
+use crate::material::MaterialId;
 use crate::ray_intersect::{Intersect, RayIntersect};
 use raylib::prelude::Vector3;
 
 pub struct Cube {
     pub center: Vector3,
     pub size: f32,
-    pub material: Material,
+    pub material: MaterialId,
 }
 
 impl RayIntersect for Cube {
@@ -70,6 +71,11 @@ impl RayIntersect for Cube {
             (normal, u, v)
         };
         
-        Intersect::new_with_uv(point, normal, t, self.material.clone(), u, v)
+        Intersect::new_with_uv(point, normal, t, self.material, u, v)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let half = Vector3::new(self.size / 2.0, self.size / 2.0, self.size / 2.0);
+        Some(Aabb::new(self.center - half, self.center + half))
     }
 }
\ No newline at end of file
@@ -1,87 +1,91 @@
-use raylib::prelude::*;
+use image::codecs::hdr::HdrEncoder;
+use image::{ImageBuffer, Rgb, Rgba};
+use std::fs::File;
+use std::io::BufWriter;
 
+/// Imagen de radiancia lineal en coma flotante, RGBA por pixel y sin
+/// límite. No depende de raylib: es lo que devuelven los integradores y lo
+/// que se guarda en OpenEXR o `.hdr` antes de pasar por el mapeo de tonos.
+///
+/// El alfa es la cobertura de la geometría, con el color premultiplicado;
+/// con el cielo encendido todo es opaco.
+#[derive(Debug, Clone)]
 pub struct Framebuffer {
-    pub width: u32,
-    pub height: u32,
-    pub color_buffer: Image,
-    background_color: Color,
-    current_color: Color,
-    cached_texture: Option<Texture2D>,
+    width: i32,
+    height: i32,
+    pixels: Vec<[f32; 4]>,
 }
 
 impl Framebuffer {
-    pub fn new(width: u32, height: u32) -> Self {
-        let color_buffer = Image::gen_image_color(width as i32, height as i32, Color::BLACK);
-        Framebuffer {
-            width,
-            height,
-            color_buffer,
-            background_color: Color::BLACK,
-            current_color: Color::WHITE,
-            cached_texture: None,
-        }
+    /// Imagen negra y transparente.
+    pub fn new(width: i32, height: i32) -> Self {
+        Framebuffer { width, height, pixels: vec![[0.0; 4]; (width.max(0) * height.max(0)) as usize] }
     }
 
-    pub fn clear(&mut self) {
-        for y in 0..self.height as i32 {
-            for x in 0..self.width as i32 {
-                self.color_buffer.draw_pixel(x, y, self.background_color);
-            }
+    /// Envuelve pixeles ya calculados, fila por fila desde arriba.
+    pub fn from_pixels(width: i32, height: i32, pixels: Vec<[f32; 4]>) -> Result<Self, String> {
+        if pixels.len() != (width.max(0) * height.max(0)) as usize {
+            return Err(format!(
+                "El frame tiene {} pixeles, se esperaban {}",
+                pixels.len(),
+                width * height
+            ));
         }
+        Ok(Framebuffer { width, height, pixels })
     }
 
-    #[inline] 
-    pub fn set_pixel(&mut self, x: u32, y: u32) {
-        if x < self.width && y < self.height {
-            self.color_buffer.draw_pixel(x as i32, y as i32, self.current_color);
-        }
+    pub fn width(&self) -> i32 {
+        self.width
     }
 
-    pub fn set_background_color(&mut self, color: Color) {
-        self.background_color = color;
+    pub fn height(&self) -> i32 {
+        self.height
     }
 
-    #[inline]
-    pub fn set_current_color(&mut self, color: Color) {
-        self.current_color = color;
+    pub fn pixels(&self) -> &[[f32; 4]] {
+        &self.pixels
     }
 
-    pub fn _render_to_file(&self, file_path: &str) {
-        self.color_buffer.export_image(file_path);
+    pub fn pixels_mut(&mut self) -> &mut [[f32; 4]] {
+        &mut self.pixels
     }
 
-    pub fn swap_buffers(&mut self, rl: &mut RaylibHandle, thread: &RaylibThread) {
-        match &self.cached_texture {
-            Some(_) => {
-                if let Ok(new_texture) = rl.load_texture_from_image(thread, &self.color_buffer) {
-                    self.cached_texture = Some(new_texture);
-                }
-            },
-            None => {
-                if let Ok(new_texture) = rl.load_texture_from_image(thread, &self.color_buffer) {
-                    self.cached_texture = Some(new_texture);
-                }
-            }
+    /// Guarda la radiancia tal cual, sin exposición ni mapeo de tonos:
+    /// OpenEXR (`.exr`, RGBA de 32 bits) o Radiance (`.hdr`, RGBE sin alfa).
+    pub fn save_hdr(&self, path: &str) -> Result<(), String> {
+        if self.width <= 0 || self.height <= 0 {
+            return Err(format!("Resolución inválida: {}x{}", self.width, self.height));
         }
+        let (width, height) = (self.width as u32, self.height as u32);
 
-        if let Some(texture) = &self.cached_texture {
-            let mut d = rl.begin_drawing(thread);
-            d.clear_background(Color::BLACK);
-            
-            d.draw_texture_pro(
-                texture,
-                Rectangle::new(0.0, 0.0, self.width as f32, self.height as f32),
-                Rectangle::new(0.0, 0.0, d.get_screen_width() as f32, d.get_screen_height() as f32),
-                Vector2::zero(),
-                0.0,
-                Color::WHITE
-            );
+        match extension(path).as_deref() {
+            Some("exr") => {
+                let data = self.pixels.iter().flatten().copied().collect();
+                let buffer: ImageBuffer<Rgba<f32>, Vec<f32>> = ImageBuffer::from_raw(width, height, data)
+                    .ok_or_else(|| "No se pudo crear el buffer de imagen".to_string())?;
+                buffer
+                    .save(path)
+                    .map_err(|e| format!("No se pudo guardar {}: {}", path, e))
+            }
+            Some("hdr") => {
+                let data: Vec<Rgb<f32>> = self.pixels.iter().map(|&[r, g, b, _]| Rgb([r, g, b])).collect();
+                let file = File::create(path).map_err(|e| format!("No se pudo crear {}: {}", path, e))?;
+                HdrEncoder::new(BufWriter::new(file))
+                    .encode(&data, width as usize, height as usize)
+                    .map_err(|e| format!("No se pudo guardar {}: {}", path, e))
+            }
+            _ => Err(format!("{} no es un formato HDR (.exr o .hdr)", path)),
         }
     }
 }
 
-impl Drop for Framebuffer {
-    fn drop(&mut self) {
+/// Si `path` pide una imagen en coma flotante en lugar de 8 bits.
+pub fn is_hdr_path(path: &str) -> bool {
+    matches!(extension(path).as_deref(), Some("exr" | "hdr"))
+}
 
-    }
-}
\ No newline at end of file
+fn extension(path: &str) -> Option<String> {
+    std::path::Path::new(path)
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_ascii_lowercase())
+}
@@ -0,0 +1,30 @@
+use raylib::prelude::Color;
+use image::{ImageBuffer, Rgb};
+
+/// Guarda un frame ya renderizado en disco. El formato (PNG, JPEG, ...)
+/// se elige a partir de la extensión del archivo.
+pub fn save_pixels(path: &str, width: i32, height: i32, pixels: &[Color]) -> Result<(), String> {
+    if width <= 0 || height <= 0 {
+        return Err(format!("Resolución inválida: {}x{}", width, height));
+    }
+    if pixels.len() != (width * height) as usize {
+        return Err(format!(
+            "El frame tiene {} pixeles, se esperaban {}",
+            pixels.len(),
+            width * height
+        ));
+    }
+
+    let mut data = Vec::with_capacity(pixels.len() * 3);
+    for color in pixels {
+        data.extend_from_slice(&[color.r, color.g, color.b]);
+    }
+
+    let buffer: ImageBuffer<Rgb<u8>, Vec<u8>> =
+        ImageBuffer::from_raw(width as u32, height as u32, data)
+            .ok_or_else(|| "No se pudo crear el buffer de imagen".to_string())?;
+
+    buffer
+        .save(path)
+        .map_err(|e| format!("No se pudo guardar {}: {}", path, e))
+}
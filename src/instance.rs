@@ -0,0 +1,198 @@
+use raylib::prelude::Vector3;
+use std::sync::Arc;
+
+use crate::bvh::Aabb;
+use crate::light::{Light, LightKind};
+use crate::material::MaterialId;
+use crate::ray_intersect::{Intersect, RayIntersect};
+use crate::voxel::VoxelGrid;
+
+/// Giro (y reflejo) rígido más traslación. Las columnas de `axes` son las
+/// imágenes de los ejes locales; al ser ortonormales, la inversa es la
+/// traspuesta y las distancias a lo largo del rayo no cambian.
+#[derive(Debug, Clone, Copy)]
+pub struct Transform {
+    axes: [Vector3; 3],
+    offset: Vector3,
+}
+
+impl Transform {
+    /// Refleja los ejes marcados en `mirror` y después gira `degrees` grados
+    /// alrededor de X, de Y y de Z, en ese orden. Los múltiplos de 90° son
+    /// exactos, así que los voxels siguen cayendo en celdas enteras.
+    pub fn rotation(degrees: [f32; 3], mirror: [bool; 3]) -> Transform {
+        let [(cx, sx), (cy, sy), (cz, sz)] = degrees.map(cos_sin);
+        let rotate = |v: Vector3| {
+            let v = Vector3::new(v.x, v.y * cx - v.z * sx, v.y * sx + v.z * cx);
+            let v = Vector3::new(v.x * cy + v.z * sy, v.y, -v.x * sy + v.z * cy);
+            Vector3::new(v.x * cz - v.y * sz, v.x * sz + v.y * cz, v.z)
+        };
+        let flip = |axis: usize| if mirror[axis] { -1.0 } else { 1.0 };
+        Transform {
+            axes: [
+                rotate(Vector3::new(flip(0), 0.0, 0.0)),
+                rotate(Vector3::new(0.0, flip(1), 0.0)),
+                rotate(Vector3::new(0.0, 0.0, flip(2))),
+            ],
+            offset: Vector3::zero(),
+        }
+    }
+
+    /// La misma transformación, desplazada para que la caja `min..max` quede
+    /// con su esquina mínima en `corner`.
+    pub fn placed(self, min: Vector3, max: Vector3, corner: Vector3) -> Transform {
+        let center = (min + max) * 0.5;
+        let half = (max - min) * 0.5;
+        let [a, b, c] = self.axes;
+        let extent = Vector3::new(
+            a.x.abs() * half.x + b.x.abs() * half.y + c.x.abs() * half.z,
+            a.y.abs() * half.x + b.y.abs() * half.y + c.y.abs() * half.z,
+            a.z.abs() * half.x + b.z.abs() * half.y + c.z.abs() * half.z,
+        );
+        Transform { offset: corner + extent - self.direction(center), ..self }
+    }
+
+    #[inline]
+    pub fn direction(&self, v: Vector3) -> Vector3 {
+        self.axes[0] * v.x + self.axes[1] * v.y + self.axes[2] * v.z
+    }
+
+    #[inline]
+    pub fn point(&self, p: Vector3) -> Vector3 {
+        self.direction(p) + self.offset
+    }
+
+    #[inline]
+    pub fn inverse_direction(&self, v: Vector3) -> Vector3 {
+        Vector3::new(self.axes[0].dot(v), self.axes[1].dot(v), self.axes[2].dot(v))
+    }
+
+    #[inline]
+    pub fn inverse_point(&self, p: Vector3) -> Vector3 {
+        self.inverse_direction(p - self.offset)
+    }
+}
+
+/// Coseno y seno exactos para los múltiplos de 90°.
+fn cos_sin(degrees: f32) -> (f32, f32) {
+    if degrees % 90.0 == 0.0 {
+        match (degrees / 90.0).rem_euclid(4.0) as i32 {
+            0 => (1.0, 0.0),
+            1 => (0.0, 1.0),
+            2 => (-1.0, 0.0),
+            _ => (0.0, -1.0),
+        }
+    } else {
+        let radians = degrees.to_radians();
+        (radians.cos(), radians.sin())
+    }
+}
+
+/// Grilla cargada una sola vez para colocarla en varios lugares. `materials`
+/// traduce cada material de su paleta al de la paleta de la escena.
+pub struct VoxelModel {
+    pub grid: VoxelGrid,
+    materials: Vec<MaterialId>,
+}
+
+impl VoxelModel {
+    /// Registra los materiales del modelo en la paleta de `scene`.
+    pub fn new(grid: VoxelGrid, scene: &mut VoxelGrid) -> Result<Self, String> {
+        let materials = grid.materials().map(|m| scene.intern_material(m.clone())).collect::<Result<_, _>>()?;
+        Ok(VoxelModel { grid, materials })
+    }
+}
+
+/// Copia de un modelo colocada con un `Transform`. Los rayos se pasan al
+/// espacio del modelo y se recorren con su propio DDA.
+pub struct VoxelInstance {
+    model: Arc<VoxelModel>,
+    transform: Transform,
+}
+
+impl VoxelInstance {
+    pub fn new(model: Arc<VoxelModel>, transform: Transform) -> Self {
+        VoxelInstance { model, transform }
+    }
+
+    pub fn voxel_count(&self) -> usize {
+        self.model.grid.len()
+    }
+
+    /// Las luces de los bloques emisivos del modelo, ya colocadas.
+    pub fn emissive_lights(&self, strength: f32) -> Vec<Light> {
+        let mut lights = self.model.grid.emissive_lights(strength);
+        for light in &mut lights {
+            light.position = self.transform.point(light.position);
+            if let LightKind::Voxel { faces } = &mut light.kind {
+                *faces = faces.map(|n| self.transform.direction(n));
+            }
+        }
+        lights
+    }
+
+    /// Pasa un impacto del modelo al espacio de la escena.
+    fn to_world(&self, mut hit: Intersect) -> Intersect {
+        if !hit.is_intersecting {
+            return hit;
+        }
+        hit.point = self.transform.point(hit.point);
+        hit.normal = self.transform.direction(hit.normal);
+        hit.material = self.model.materials[hit.material.index()];
+        hit.state = hit.state.transformed(|v| self.transform.direction(v));
+        hit
+    }
+}
+
+impl RayIntersect for VoxelInstance {
+    fn ray_intersect(&self, ray_origin: &Vector3, ray_direction: &Vector3) -> Intersect {
+        self.ray_intersect_before(ray_origin, ray_direction, f32::INFINITY)
+    }
+
+    fn ray_intersect_before(&self, ray_origin: &Vector3, ray_direction: &Vector3, t_max: f32) -> Intersect {
+        let ro = self.transform.inverse_point(*ray_origin);
+        let rd = self.transform.inverse_direction(*ray_direction);
+        let hit = self.model.grid.intersect_ray(&ro, &rd, t_max);
+        self.to_world(hit)
+    }
+
+    fn exit_medium(&self, ray_origin: &Vector3, ray_direction: &Vector3) -> Intersect {
+        let ro = self.transform.inverse_point(*ray_origin);
+        let rd = self.transform.inverse_direction(*ray_direction);
+        let exit = self.model.grid.exit_medium(&ro, &rd);
+        if !exit.is_intersecting {
+            return self.ray_intersect(ray_origin, ray_direction);
+        }
+        self.to_world(exit)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let (min, max) = self.model.grid.calculate_bounds();
+        let corners = (0..8).map(|i| {
+            Vector3::new(
+                if i & 1 == 0 { min.x } else { max.x },
+                if i & 2 == 0 { min.y } else { max.y },
+                if i & 4 == 0 { min.z } else { max.z },
+            )
+        });
+        Some(corners.fold(Aabb::EMPTY, |acc, corner| acc.grow(self.transform.point(corner))))
+    }
+}
+
+impl RayIntersect for Arc<VoxelInstance> {
+    fn ray_intersect(&self, ray_origin: &Vector3, ray_direction: &Vector3) -> Intersect {
+        self.as_ref().ray_intersect(ray_origin, ray_direction)
+    }
+
+    fn ray_intersect_before(&self, ray_origin: &Vector3, ray_direction: &Vector3, t_max: f32) -> Intersect {
+        self.as_ref().ray_intersect_before(ray_origin, ray_direction, t_max)
+    }
+
+    fn exit_medium(&self, ray_origin: &Vector3, ray_direction: &Vector3) -> Intersect {
+        self.as_ref().exit_medium(ray_origin, ray_direction)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.as_ref().bounding_box()
+    }
+}
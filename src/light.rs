@@ -1,9 +1,48 @@
 use raylib::prelude::*;
+use std::collections::HashMap;
 
+use crate::color::color_to_linear;
+
+/// Aporte mínimo que se considera al recortar luces lejanas.
+const MIN_LIGHT_CONTRIBUTION: f32 = 0.05;
+/// Intensidad de las luces puntuales y focos cuando no se indica otra.
+pub const DEFAULT_LIGHT_INTENSITY: f32 = 8.0;
+/// Fracción del ángulo del foco que recibe intensidad completa.
+pub const SPOT_INNER_FRACTION: f32 = 0.8;
+/// Emisión mínima para que un bloque ilumine a los demás. Por debajo (la
+/// netherrack, por ejemplo) el bloque brilla pero no es una luz.
+pub const MIN_LIGHT_EMISSION: f32 = 0.5;
+/// Distancia por delante de la cara expuesta desde la que sale la luz de un
+/// voxel emisivo. Sin ella, el suelo al mismo nivel que un bloque hundido en
+/// él quedaría de canto a la luz.
+const EMITTER_OFFSET: f32 = 0.25;
+/// Lado, en bits, de las celdas en las que `LightGrid` reparte las luces.
+const LIGHT_CELL_BITS: i32 = 4;
+/// Celdas de `LightGrid`, a cada lado de la suya, hasta las que llega una luz.
+const MAX_LIGHT_CELLS: i32 = 4;
+
+#[derive(Debug, Clone, Copy)]
+pub enum LightKind {
+    Point,
+    /// Foco: ilumina solo dentro del cono. Los ángulos se guardan como cosenos
+    /// del semiángulo interior (intensidad completa) y exterior (sin luz).
+    Spot {
+        direction: Vector3,
+        inner_cos: f32,
+        outer_cos: f32,
+    },
+    /// Voxel emisivo: un cubo unitario centrado en `position` que emite por sus
+    /// caras expuestas. `faces` guarda la normal de cada cara expuesta y cero
+    /// en las tapadas.
+    Voxel { faces: [Vector3; 6] },
+}
+
+#[derive(Debug, Clone, Copy)]
 pub struct Light {
     pub position: Vector3,
     pub color: Color,
     pub intensity: f32,
+    pub kind: LightKind,
 }
 
 impl Light {
@@ -12,6 +51,188 @@ impl Light {
             position,
             color,
             intensity,
+            kind: LightKind::Point,
+        }
+    }
+
+    pub fn spot(
+        position: Vector3,
+        direction: Vector3,
+        color: Color,
+        intensity: f32,
+        inner_angle_deg: f32,
+        outer_angle_deg: f32,
+    ) -> Self {
+        let outer = outer_angle_deg.to_radians();
+        let inner = inner_angle_deg.to_radians().min(outer);
+        Light {
+            position,
+            color,
+            intensity,
+            kind: LightKind::Spot {
+                direction: direction.normalized(),
+                inner_cos: inner.cos(),
+                outer_cos: outer.cos(),
+            },
+        }
+    }
+
+    pub fn voxel(center: Vector3, faces: [Vector3; 6], color: Color, intensity: f32) -> Self {
+        Light {
+            position: center,
+            color,
+            intensity,
+            kind: LightKind::Voxel { faces },
+        }
+    }
+
+    /// Punto de la luz hacia el que se lanza el rayo de sombra desde `point`.
+    /// Un voxel emite desde delante del centro de la cara expuesta que más
+    /// mira hacia `point`.
+    #[inline]
+    pub fn sample_point(&self, point: Vector3) -> Vector3 {
+        match self.kind {
+            LightKind::Voxel { faces } => {
+                let offset = point - self.position;
+                faces
+                    .iter()
+                    .filter(|n| **n != Vector3::zero())
+                    .max_by(|a, b| a.dot(offset).total_cmp(&b.dot(offset)))
+                    .map_or(self.position, |n| self.position + *n * (0.5 + EMITTER_OFFSET))
+            }
+            _ => self.position,
+        }
+    }
+
+    /// Distancia a partir de la cual el aporte de la luz es despreciable.
+    #[inline]
+    pub fn range(&self) -> f32 {
+        (self.intensity / MIN_LIGHT_CONTRIBUTION - 1.0).max(0.0).sqrt()
+    }
+
+    /// Color de la luz en lineal; `color` está en sRGB.
+    #[inline]
+    pub fn color_vector(&self) -> Vector3 {
+        color_to_linear(self.color)
+    }
+
+    /// Caída con la distancia; el 1 evita la singularidad junto a la luz.
+    #[inline]
+    pub fn attenuation(&self, distance: f32) -> f32 {
+        self.intensity / (1.0 + distance * distance)
+    }
+
+    /// Factor del cono para un foco (1 para luces puntuales). `to_point` va
+    /// desde la luz hacia el punto iluminado y debe estar normalizado.
+    #[inline]
+    pub fn cone_factor(&self, to_point: Vector3) -> f32 {
+        match self.kind {
+            LightKind::Point | LightKind::Voxel { .. } => 1.0,
+            LightKind::Spot { direction, inner_cos, outer_cos } => {
+                let cos_angle = direction.dot(to_point);
+                if cos_angle <= outer_cos {
+                    0.0
+                } else if cos_angle >= inner_cos {
+                    1.0
+                } else {
+                    let t = (cos_angle - outer_cos) / (inner_cos - outer_cos);
+                    t * t * (3.0 - 2.0 * t)
+                }
+            }
         }
     }
-}
\ No newline at end of file
+}
+
+/// Luces de los voxels emisivos repartidas en celdas de 16³. Cada celda
+/// guarda los índices de las luces cuyo alcance la toca, así cada punto solo
+/// recorre las que le pueden llegar.
+#[derive(Debug, Clone, Default)]
+pub struct LightGrid {
+    lights: Vec<Light>,
+    cells: HashMap<(i32, i32, i32), Vec<u32>>,
+}
+
+impl LightGrid {
+    /// Las luces con intensidad o posición no finitas se descartan, y el
+    /// alcance de cada una se limita a `MAX_LIGHT_CELLS` celdas alrededor de
+    /// la suya: más allá, una luz muy intensa deja de iluminar.
+    pub fn new(lights: &[Light]) -> Self {
+        let lights: Vec<Light> = lights
+            .iter()
+            .filter(|l| l.intensity.is_finite() && l.position.x.is_finite() && l.position.y.is_finite() && l.position.z.is_finite())
+            .copied()
+            .collect();
+        let mut cells: HashMap<(i32, i32, i32), Vec<u32>> = HashMap::new();
+        for (index, light) in lights.iter().enumerate() {
+            // El punto de muestreo puede estar hasta una celda fuera del centro.
+            let reach = (light.range() + 1.0).min((MAX_LIGHT_CELLS << LIGHT_CELL_BITS) as f32);
+            let min = Self::key(light.position - Vector3::new(reach, reach, reach));
+            let max = Self::key(light.position + Vector3::new(reach, reach, reach));
+            for x in min.0..=max.0 {
+                for y in min.1..=max.1 {
+                    for z in min.2..=max.2 {
+                        cells.entry((x, y, z)).or_default().push(index as u32);
+                    }
+                }
+            }
+        }
+        LightGrid { lights, cells }
+    }
+
+    #[inline]
+    fn key(p: Vector3) -> (i32, i32, i32) {
+        (
+            (p.x.floor() as i32) >> LIGHT_CELL_BITS,
+            (p.y.floor() as i32) >> LIGHT_CELL_BITS,
+            (p.z.floor() as i32) >> LIGHT_CELL_BITS,
+        )
+    }
+
+    pub fn len(&self) -> usize {
+        self.lights.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lights.is_empty()
+    }
+
+    /// Luces que pueden iluminar `point`.
+    #[inline]
+    pub fn near(&self, point: Vector3) -> impl Iterator<Item = &Light> + '_ {
+        let indices = self.cells.get(&Self::key(point)).map_or(&[][..], Vec::as_slice);
+        indices.iter().map(|&i| &self.lights[i as usize])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn voxel_light(position: Vector3, intensity: f32) -> Light {
+        Light::voxel(position, [Vector3::new(0.0, 1.0, 0.0); 6], Color::WHITE, intensity)
+    }
+
+    #[test]
+    fn non_finite_lights_are_dropped() {
+        let lights = [
+            voxel_light(Vector3::zero(), f32::INFINITY),
+            voxel_light(Vector3::zero(), f32::NAN),
+            voxel_light(Vector3::new(f32::NAN, 0.0, 0.0), 1.0),
+            voxel_light(Vector3::new(0.5, 0.5, 0.5), 1.0),
+        ];
+        let grid = LightGrid::new(&lights);
+        assert_eq!(grid.len(), 1);
+        assert_eq!(grid.near(Vector3::new(0.5, 0.5, 0.5)).count(), 1);
+    }
+
+    #[test]
+    fn reach_is_capped() {
+        let grid = LightGrid::new(&[voxel_light(Vector3::new(0.5, 0.5, 0.5), 1e12)]);
+        let side = 2 * MAX_LIGHT_CELLS as usize + 1;
+        assert_eq!(grid.cells.len(), side * side * side);
+        let edge = (MAX_LIGHT_CELLS << LIGHT_CELL_BITS) as f32;
+        assert_eq!(grid.near(Vector3::new(edge, 0.0, -edge)).count(), 1);
+        assert_eq!(grid.near(Vector3::new(edge + 16.0, 0.0, 0.0)).count(), 0);
+        assert!(LightGrid::new(&[]).is_empty());
+    }
+}
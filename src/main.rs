@@ -5,9 +5,11 @@ use raylib::prelude::*;
 use rayon::prelude::*;
 use std::sync::Arc;
 use std::f32::consts::PI;
+use std::process::ExitCode;
 
 mod voxel;
 mod material;
+mod material_library;
 mod light;
 mod texture;
 mod ray_intersect;
@@ -16,16 +18,57 @@ mod cube;
 mod framebuffer;
 mod plane;
 mod sphere;
+mod headless;
+mod cli;
+mod path_tracer;
+mod scene;
+mod vox;
+mod nbt;
+mod block_mapping;
+mod schematic;
+mod shape;
+mod bench;
+mod world;
+mod bvh;
+mod mesh;
+mod instance;
+mod antialias;
+mod color;
 
+use antialias::{AntiAliasing, Sample};
+use camera::{Bokeh, Camera, Ray};
+use color::ToneMapping;
+use framebuffer::Framebuffer;
 use voxel::VoxelGrid;
-use material::{Material, MaterialType};
+use world::World;
+use material::Material;
+use ray_intersect::Intersect;
+use light::{Light, LightGrid, LightKind};
+use clap::Parser;
+use cli::{BenchArgs, CameraArgs, Cli, Command, InfoArgs, Integrator, QualityArgs, RenderArgs, SceneArgs, ViewArgs};
+use scene::{ModelSource, RenderOverrides, Scene, Sky};
+use std::path::Path;
 
 const WIDTH: i32 = 1200;
 const HEIGHT: i32 = 800;
 const RENDER_SCALE: i32 = 1;
-const MAX_RAY_DIST: f32 = 50.0;
-const MAX_DDA_STEPS: u32 = 100;
+const MAX_RAY_DIST: f32 = f32::INFINITY;
 const MAX_REFLECTIONS: u32 = 3;
+const MAX_INTERNAL_BOUNCES: u32 = 4;
+const MAX_PATH_BOUNCES: u32 = 5;
+const PATH_SAMPLES: u32 = 64;
+const DEFAULT_FOV: f32 = 45.0;
+/// Con lente, el modo Whitted traza al menos `LENS_STRATA`² muestras por
+/// pixel, cada una desde un punto distinto de la apertura.
+const LENS_STRATA: u32 = 4;
+/// Apertura con la que se abre el lente desde la tecla ] y por debajo de la
+/// cual [ lo cierra.
+const MIN_APERTURE: f32 = 0.02;
+/// Pasos de exposición de las teclas - y =.
+const EXPOSURE_STEP: f32 = 0.5;
+/// Margen con el que el rayo de sombra se detiene antes de la luz, para que
+/// no choque con el propio bloque emisivo.
+const SHADOW_EPSILON: f32 = 1e-3;
 
 #[derive(Clone, Copy)]
 struct BoundingSphere {
@@ -33,63 +76,43 @@ struct BoundingSphere {
     radius: f32,
 }
 
+/// Ajustes de render resueltos: línea de comandos, luego escena, luego los
+/// valores por defecto.
 #[derive(Clone, Copy, Debug)]
-struct SimpleCamera {
-    pub eye: Vector3,
-    pub target: Vector3,
-    pub up: Vector3,
-    pub vfov_deg: f32,
-    forward: Vector3,
-    right: Vector3,
-    up_vec: Vector3,
+struct RenderSettings {
+    width: i32,
+    height: i32,
+    max_ray_dist: f32,
+    max_reflections: u32,
+    max_bounces: u32,
+    integrator: Integrator,
+    samples: u32,
+    antialiasing: AntiAliasing,
+    tone_mapping: ToneMapping,
 }
 
-impl SimpleCamera {
-    pub fn new(eye: Vector3, target: Vector3, up: Vector3, vfov_deg: f32) -> Self {
-        let mut cam = Self { 
-            eye, target, up, vfov_deg,
-            forward: Vector3::zero(),
-            right: Vector3::zero(),
-            up_vec: Vector3::zero(),
-        };
-        cam.update_vectors();
-        cam
-    }
-
-    fn update_vectors(&mut self) {
-        self.forward = (self.target - self.eye).normalized();
-        self.right = self.forward.cross(self.up).normalized();
-        self.up_vec = self.right.cross(self.forward);
-    }
-
-    pub fn set_position(&mut self, eye: Vector3, target: Vector3) {
-        self.eye = eye;
-        self.target = target;
-        self.update_vectors();
-    }
-
-    #[inline]
-    pub fn get_ray(&self, x: i32, y: i32, width: i32, height: i32) -> Ray {
-        let aspect = width as f32 / height as f32;
-        let fov = (self.vfov_deg * PI / 180.0).tan();
-
-        let ndc_x = ((x as f32 + 0.5) / width as f32) * 2.0 - 1.0;
-        let ndc_y = 1.0 - ((y as f32 + 0.5) / height as f32) * 2.0;
-
-        let direction = self.right * (ndc_x * aspect * fov) + 
-                       self.up_vec * (ndc_y * fov) + 
-                       self.forward;
-        
-        Ray {
-            origin: self.eye,
-            direction: direction.normalized(),
+impl RenderSettings {
+    fn resolve(quality: &QualityArgs, scene: &RenderOverrides) -> Self {
+        RenderSettings {
+            width: quality.width.or(scene.width).unwrap_or(WIDTH),
+            height: quality.height.or(scene.height).unwrap_or(HEIGHT),
+            max_ray_dist: quality.max_dist.or(scene.max_dist).unwrap_or(MAX_RAY_DIST),
+            max_reflections: quality.max_reflections.or(scene.max_reflections).unwrap_or(MAX_REFLECTIONS),
+            max_bounces: quality.max_bounces.or(scene.max_bounces).unwrap_or(MAX_PATH_BOUNCES),
+            integrator: quality.integrator.or(scene.integrator).unwrap_or(Integrator::Whitted),
+            samples: quality.samples.or(scene.samples).unwrap_or(PATH_SAMPLES),
+            antialiasing: AntiAliasing {
+                grid: quality.aa.or(scene.aa).unwrap_or(1),
+                filter: quality.filter.or(scene.filter).unwrap_or_default(),
+                adaptive: quality.adaptive || scene.adaptive.unwrap_or(false),
+            },
+            tone_mapping: ToneMapping {
+                operator: quality.tonemap.or(scene.tonemap).unwrap_or_default(),
+                exposure: quality.exposure.or(scene.exposure).unwrap_or(0.0),
+            },
         }
     }
 }
-struct Ray {
-    origin: Vector3,
-    direction: Vector3,
-}
 
 #[derive(Clone, Copy)]
 struct DirLight {
@@ -99,15 +122,18 @@ struct DirLight {
 }
 
 struct SharedRenderState {
-    grid: Arc<VoxelGrid>,
+    world: Arc<World>,
     bounding_sphere: BoundingSphere,
     sun: DirLight,
+    /// Luces puntuales y focos de la escena.
+    lights: Vec<Light>,
+    /// Luces de los voxels emisivos, que solo se usan si `emissive_enabled`.
+    emissive: LightGrid,
+    emissive_enabled: bool,
     skybox_enabled: bool,
+    settings: RenderSettings,
 }
 
-unsafe impl Sync for SharedRenderState {}
-unsafe impl Send for SharedRenderState {}
-
 #[inline]
 fn ray_intersects_sphere(ray: &Ray, sphere: &BoundingSphere) -> bool {
     let oc = ray.origin - sphere.center;
@@ -118,8 +144,9 @@ fn ray_intersects_sphere(ray: &Ray, sphere: &BoundingSphere) -> bool {
     discriminant >= 0.0 && (-b - discriminant.sqrt()) / (2.0 * a) > 0.0
 }
 
+/// Radiancia lineal del cielo; el degradado está pensado en sRGB.
 #[inline]
-fn enhanced_skybox(rd: Vector3) -> Color {
+fn enhanced_skybox(rd: Vector3) -> Vector3 {
     let t = (rd.y * 0.5 + 0.5).clamp(0.0, 1.0);
     
     let horizon_color = Vector3::new(1.0, 0.9, 0.8);
@@ -139,42 +166,73 @@ fn enhanced_skybox(rd: Vector3) -> Color {
     let noise = (rd.x * 5.0 + rd.z * 3.0).sin() * 0.02;
     let final_color = sky_color + Vector3::new(noise, noise * 0.5, noise * 0.3);
     
-    Color::new(
-        (final_color.x.clamp(0.0, 1.0) * 255.0) as u8,
-        (final_color.y.clamp(0.0, 1.0) * 255.0) as u8,
-        (final_color.z.clamp(0.0, 1.0) * 255.0) as u8,
-        255,
-    )
+    color::srgb_to_linear_rgb(Vector3::new(
+        final_color.x.clamp(0.0, 1.0),
+        final_color.y.clamp(0.0, 1.0),
+        final_color.z.clamp(0.0, 1.0),
+    ))
 }
 
-fn cast_ray_recursive(ray: &Ray, state: &SharedRenderState, depth: u32) -> Color {
-    if depth > MAX_REFLECTIONS {
+/// Radiancia lineal que llega por `ray`.
+fn cast_ray_recursive(ray: &Ray, state: &SharedRenderState, depth: u32) -> Vector3 {
+    if depth > state.settings.max_reflections {
         return if state.skybox_enabled {
             enhanced_skybox(ray.direction)
         } else {
-            Color::BLACK
+            Vector3::zero()
         };
     }
 
-    let hit = state.grid.intersect_ray(&ray.origin, &ray.direction, MAX_RAY_DIST, MAX_DDA_STEPS);
+    let hit = state.world.intersect_ray(&ray.origin, &ray.direction, state.settings.max_ray_dist);
+    shade_ray(ray, &hit, state, depth)
+}
+
+/// Rayo primario con lo que el antialiasing adaptativo compara entre pixeles.
+/// Sin cielo, lo que no choca con nada queda transparente.
+fn trace_primary(ray: &Ray, state: &SharedRenderState) -> Sample {
+    let hit = state.world.intersect_ray(&ray.origin, &ray.direction, state.settings.max_ray_dist);
+    Sample {
+        color: shade_ray(ray, &hit, state, 0),
+        alpha: if hit.is_intersecting || state.skybox_enabled { 1.0 } else { 0.0 },
+        depth: if hit.is_intersecting { hit.distance } else { f32::INFINITY },
+        material: hit.is_intersecting.then_some(hit.material),
+    }
+}
 
+/// Radiancia que ve `ray` dado su impacto más cercano, con los reflejos y
+/// refracciones a partir de la profundidad `depth`.
+fn shade_ray(ray: &Ray, hit: &Intersect, state: &SharedRenderState, depth: u32) -> Vector3 {
     if !hit.is_intersecting {
         return if state.skybox_enabled {
             enhanced_skybox(ray.direction)
         } else {
-            Color::BLACK
+            Vector3::zero()
         };
     }
 
-    let base_color = shade_pixel_pbr(
-        hit.point, hit.normal, &hit.material, hit.u, hit.v, 
-        &state.sun, &state.grid, ray.origin, ray.direction
-    );
+    let base_color = shade_pixel_pbr(hit, state, ray.origin, ray.direction);
     
     let mut final_color = base_color;
 
-    let reflection_strength = hit.material.albedo[2];
-    if reflection_strength > 0.01 && depth < MAX_REFLECTIONS {
+    let material = state.world.material(hit.material);
+    let transmission = material.transmission();
+    let reflection_strength = material.albedo[2];
+    if transmission > 0.01 && depth < state.settings.max_reflections {
+        // Vidrio, agua, hielo: Fresnel reparte la luz entre reflejo y refracción.
+        let ior = material.refractive_index.max(1.0);
+        let cos_i = (-ray.direction.dot(hit.normal)).clamp(0.0, 1.0);
+        let fresnel = fresnel_schlick(cos_i, 1.0, ior);
+
+        let reflect_ray = Ray {
+            origin: hit.point + hit.normal * 0.0001,
+            direction: reflect_vector(ray.direction, hit.normal),
+        };
+        let reflected = cast_ray_recursive(&reflect_ray, state, depth + 1);
+        let refracted = trace_refraction(ray, hit, state, depth);
+
+        final_color = base_color * (1.0 - transmission)
+            + (reflected * fresnel + refracted * (1.0 - fresnel)) * transmission;
+    } else if reflection_strength > 0.01 && depth < state.settings.max_reflections {
         let reflect_dir = reflect_vector(ray.direction, hit.normal);
         let reflect_origin = hit.point + hit.normal * 0.0001; 
         let reflect_ray = Ray {
@@ -182,11 +240,10 @@ fn cast_ray_recursive(ray: &Ray, state: &SharedRenderState, depth: u32) -> Color
             direction: reflect_dir,
         };
         
-        let reflect_color = cast_ray_recursive(&reflect_ray, state, depth + 1);
-        let reflect_vec = color_to_vector3(reflect_color);
+        let reflect_vec = cast_ray_recursive(&reflect_ray, state, depth + 1);
         
         // Para metales: tinte del reflejo con el color del material
-        if hit.material.metallic > 0.5 {
+        if material.metallic > 0.5 {
             let tinted_reflection = Vector3::new(
                 reflect_vec.x * (0.3 + base_color.x * 0.7),
                 reflect_vec.y * (0.3 + base_color.y * 0.7),
@@ -204,209 +261,500 @@ fn cast_ray_recursive(ray: &Ray, state: &SharedRenderState, depth: u32) -> Color
         }
     }
 
-    vector3_to_color(final_color)
+    final_color
+}
+
+/// Sigue un rayo refractado a través de un medio transparente: entra por `hit`,
+/// rebota por reflexión interna total si hace falta y continúa la escena al salir.
+fn trace_refraction(ray: &Ray, hit: &Intersect, state: &SharedRenderState, depth: u32) -> Vector3 {
+    let material = state.world.material(hit.material);
+    let ior = material.refractive_index.max(1.0);
+    let tint = material.get_diffuse_color_sharp(hit.u, hit.v, &hit.normal, &hit.state);
+
+    let mut direction = match refract_vector(ray.direction, hit.normal, 1.0 / ior) {
+        Some(dir) => dir,
+        None => return Vector3::zero(),
+    };
+    let mut origin = hit.point - hit.normal * 0.0001;
+
+    for _ in 0..MAX_INTERNAL_BOUNCES {
+        let exit = state.world.exit_medium(&origin, &direction);
+        if !exit.is_intersecting {
+            break;
+        }
+
+        // La normal de salida apunta hacia afuera; Snell necesita la que enfrenta al rayo.
+        match refract_vector(direction, -exit.normal, ior) {
+            Some(out_dir) => {
+                let out_ray = Ray {
+                    origin: exit.point + exit.normal * 0.0001,
+                    direction: out_dir,
+                };
+                let behind = cast_ray_recursive(&out_ray, state, depth + 1);
+                return behind * tint;
+            }
+            None => {
+                direction = reflect_vector(direction, -exit.normal);
+                origin = exit.point - exit.normal * 0.0001;
+            }
+        }
+    }
+
+    Vector3::zero()
+}
+
+/// Fracción de luz que llega desde `dir` atravesando materiales transparentes.
+/// Devuelve 0 si un bloque opaco bloquea el camino.
+fn light_transmittance(world: &World, origin: Vector3, dir: Vector3, max_dist: f32) -> f32 {
+    let mut transmittance = 1.0;
+    let mut ro = origin;
+
+    for _ in 0..MAX_INTERNAL_BOUNCES {
+        let remaining = max_dist - (ro - origin).length();
+        if remaining <= 0.0 {
+            return transmittance;
+        }
+        let hit = world.intersect_ray(&ro, &dir, remaining);
+        if !hit.is_intersecting {
+            return transmittance;
+        }
+        let material = world.material(hit.material);
+        if !material.is_transparent() {
+            return 0.0;
+        }
+
+        transmittance *= material.transmission();
+        let exit = world.exit_medium(&(hit.point - hit.normal * 0.0001), &dir);
+        if !exit.is_intersecting {
+            return transmittance;
+        }
+        ro = exit.point + exit.normal * 0.0001;
+    }
+
+    transmittance
 }
 
 fn shade_pixel_pbr(
-    hit_point: Vector3,
-    normal: Vector3,
-    mat: &Material,
-    u: f32,
-    v: f32,
-    sun: &DirLight,
-    grid: &VoxelGrid,
+    hit: &Intersect,
+    state: &SharedRenderState,
     cam_pos: Vector3,
     _ray_dir: Vector3,
 ) -> Vector3 {
-    let albedo = mat.get_diffuse_color_sharp(u, v, &normal);
+    let sun = &state.sun;
+    let world = &state.world;
+    let (hit_point, normal, mat) = (hit.point, hit.normal, world.material(hit.material));
+    let albedo = mat.get_diffuse_color_sharp(hit.u, hit.v, &normal, &hit.state);
+    let view_dir = (cam_pos - hit_point).normalized();
+    let shadow_origin = hit_point + normal * 0.0001; // Offset reducido
     
     let ndotl = normal.dot(-sun.dir).max(0.0);
     let ambient = 0.25;
     let diffuse = ndotl * 0.75;
     
     let shadow_factor = if ndotl > 0.05 {
-        let shadow_origin = hit_point + normal * 0.0001; // Offset reducido
-        let shadow_ray = grid.intersect_ray(&shadow_origin, &-sun.dir, 20.0, 50);
-        if shadow_ray.is_intersecting { 0.3 } else { 1.0 }
+        0.3 + 0.7 * light_transmittance(world, shadow_origin, -sun.dir, state.settings.max_ray_dist)
     } else {
         1.0
     };
     
-    let specular = if mat.specular > 5.0 {
-        let view_dir = (cam_pos - hit_point).normalized();
-        let reflect_dir = reflect_vector(sun.dir, normal);
-        let spec_dot = view_dir.dot(reflect_dir).max(0.0);
-        let roughness_factor = 1.0 / (mat.roughness * 50.0 + 1.0);
-        spec_dot.powf(mat.specular * roughness_factor) * 0.5 * shadow_factor
-    } else {
-        0.0
-    };
+    let specular = phong_specular(mat, view_dir, sun.dir, normal) * shadow_factor;
+
+    // Luces puntuales, focos y voxels emisivos cercanos: caída con la
+    // distancia y sombra hasta la luz.
+    let emissive = state.emissive_enabled.then(|| state.emissive.near(hit_point));
+    let mut local_lighting = Vector3::zero();
+    for light in state.lights.iter().chain(emissive.into_iter().flatten()) {
+        let to_light = light.sample_point(hit_point) - hit_point;
+        let distance = to_light.length();
+        if distance < 1e-4 || distance > light.range() {
+            continue;
+        }
+        let light_dir = to_light / distance;
+        let light_ndotl = normal.dot(light_dir);
+        if light_ndotl <= 0.0 {
+            continue;
+        }
+        let cone = light.cone_factor(-light_dir);
+        if cone <= 0.0 {
+            continue;
+        }
+        let visibility = light_transmittance(world, shadow_origin, light_dir, distance - SHADOW_EPSILON);
+        if visibility <= 0.0 {
+            continue;
+        }
+
+        let strength = light.attenuation(distance) * cone * visibility;
+        let light_specular = phong_specular(mat, view_dir, -light_dir, normal);
+        local_lighting += light.color_vector() * ((light_ndotl + light_specular) * strength);
+    }
     
     let emission = if mat.emission > 0.0 {
-        mat.get_emission_color(u, v, &normal) * mat.emission
+        mat.get_emission_color(hit.u, hit.v, &normal, &hit.state) * mat.emission
     } else {
         Vector3::zero()
     };
     
     let total_lighting = ambient + (diffuse + specular) * shadow_factor;
-    let lit_color = albedo * total_lighting;
-    let final_color = lit_color + emission;
-    
-    Vector3::new(
-        final_color.x / (1.0 + final_color.x * 0.8),
-        final_color.y / (1.0 + final_color.y * 0.8),
-        final_color.z / (1.0 + final_color.z * 0.8)
-    )
+    let lit_color = albedo * total_lighting + albedo * local_lighting;
+    lit_color + emission
+}
+
+/// Brillo especular de Phong para una luz que incide en dirección `incident`.
+fn phong_specular(mat: &Material, view_dir: Vector3, incident: Vector3, normal: Vector3) -> f32 {
+    if mat.specular <= 5.0 {
+        return 0.0;
+    }
+    let reflect_dir = reflect_vector(incident, normal);
+    let spec_dot = view_dir.dot(reflect_dir).max(0.0);
+    let roughness_factor = 1.0 / (mat.roughness * 50.0 + 1.0);
+    spec_dot.powf(mat.specular * roughness_factor) * 0.5
 }
 
 fn reflect_vector(incident: Vector3, normal: Vector3) -> Vector3 {
     incident - normal * 2.0 * incident.dot(normal)
 }
 
-fn color_to_vector3(color: Color) -> Vector3 {
-    Vector3::new(
-        color.r as f32 / 255.0,
-        color.g as f32 / 255.0,
-        color.b as f32 / 255.0,
-    )
+/// Ley de Snell. `eta` es n1 / n2 y `normal` debe enfrentar al rayo incidente.
+/// Devuelve `None` cuando hay reflexión interna total.
+fn refract_vector(incident: Vector3, normal: Vector3, eta: f32) -> Option<Vector3> {
+    let cos_i = (-normal.dot(incident)).clamp(-1.0, 1.0);
+    let sin2_t = eta * eta * (1.0 - cos_i * cos_i);
+    if sin2_t > 1.0 {
+        return None;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    Some((incident * eta + normal * (eta * cos_i - cos_t)).normalized())
 }
 
-fn vector3_to_color(v: Vector3) -> Color {
-    Color::new(
-        (v.x.clamp(0.0, 1.0) * 255.0) as u8,
-        (v.y.clamp(0.0, 1.0) * 255.0) as u8,
-        (v.z.clamp(0.0, 1.0) * 255.0) as u8,
-        255,
-    )
+/// Aproximación de Schlick para la reflectancia de Fresnel entre medios n1 -> n2.
+fn fresnel_schlick(cos_i: f32, n1: f32, n2: f32) -> f32 {
+    let r0 = ((n1 - n2) / (n1 + n2)).powi(2);
+    let mut cos = cos_i;
+    if n1 > n2 {
+        let eta = n1 / n2;
+        let sin2_t = eta * eta * (1.0 - cos * cos);
+        if sin2_t > 1.0 {
+            return 1.0;
+        }
+        cos = (1.0 - sin2_t).sqrt();
+    }
+    r0 + (1.0 - r0) * (1.0 - cos).powi(5)
 }
 
 fn render_parallel_optimized(
-    camera: &SimpleCamera,
+    camera: &Camera,
     width: i32,
     height: i32,
     state: &SharedRenderState,
-) -> Vec<Color> {
-    let pixel_coords: Vec<(i32, i32)> = (0..height)
-        .flat_map(|y| (0..width).map(move |x| (x, y)))
-        .collect();
+) -> Framebuffer {
+    let base = if camera.has_lens() { LENS_STRATA } else { 1 };
+    antialias::render(width, height, &state.settings.antialiasing, base, |x, y, offset, lens| {
+        let ray = camera.get_ray_lens(x, y, offset, lens, width, height);
+        trace_primary(&ray, state)
+    })
+}
 
-    pixel_coords
-        .par_iter()
-        .map(|(x, y)| {
-            let ray = camera.get_ray(*x, *y, width, height);
-            cast_ray_recursive(&ray, state, 0)
-        })
-        .collect()
+/// Carga la escena pedida (archivo o directorio de capas) y aplica encima las
+/// opciones de la línea de comandos.
+fn load_scene(args: &SceneArgs) -> Result<Scene, String> {
+    let mut scene = match (&args.scene, &args.layers) {
+        (Some(path), _) => Scene::load(path)?,
+        // Con solo modelos no se cargan las capas por defecto.
+        (None, None) if !args.models.is_empty() => Scene::default(),
+        (None, layers) => Scene::from_layers_dir(layers.as_deref().unwrap_or(Path::new("layers"))),
+    };
+    if let (Some(_), Some(dir)) = (&args.scene, &args.layers) {
+        scene.layers = Scene::from_layers_dir(dir).layers;
+    }
+    if let Some(sun) = args.sun {
+        scene.sky.sun_direction = sun;
+    }
+    for mapping in &args.blocks {
+        scene.blocks.load(mapping, &scene.materials)?;
+    }
+    scene.models.extend(args.models.iter().map(|path| ModelSource { path: path.clone(), offset: [0, 0, 0] }));
+    scene.lights.extend(args.all_lights());
+    if args.emissive_strength.is_some() {
+        scene.render.emissive_strength = args.emissive_strength;
+    }
+    Ok(scene)
 }
 
-fn create_diorama_grid() -> VoxelGrid {
-    let mut grid = VoxelGrid::new();
-
-    let mat_of = |ch: char| -> Option<Material> {
-        match ch {
-            'M' => Some(Material::new_with_type(MaterialType::Dirt)),
-            'T' => Some(Material::new_with_type(MaterialType::Grass)),
-            'P' => Some(Material::new_with_type(MaterialType::Netherrack)),
-            'R' => Some(Material::new_with_type(MaterialType::Stone)),
-            'L' => Some(Material::new_with_type(MaterialType::Magma)),
-            'O' => Some(Material::new_with_type(MaterialType::Gold)),
-            'B' => Some(Material::new_with_type(MaterialType::Obsidian)),
-            'S' => Some(Material::new_with_type(MaterialType::StoneStairs)),
-            'Z' => Some(Material::new_with_type(MaterialType::StoneSlab)),
-            'J' => Some(Material::new_with_type(MaterialType::StonePillar)),
-            'C' => Some(Material::new_with_type(MaterialType::WoodChest)),
-            'W' => Some(Material::new_with_type(MaterialType::GlowingObsidian)),
-            'V' | '.' => None,
-            _ => None,
-        }
+fn create_sun(sky: &Sky) -> DirLight {
+    DirLight {
+        dir: sky.sun_direction,
+        color: sky.sun_color,
+        intensity: sky.sun_intensity,
+    }
+}
+
+/// Cámara inicial: la predefinida que se pida (o la de la escena) y, si no
+/// hay, una diagonal que mira al centro de la estructura. Las opciones de
+/// cámara de la línea de comandos tienen prioridad.
+fn create_camera(
+    structure_center: Vector3,
+    structure_radius: f32,
+    args: &CameraArgs,
+    scene: &Scene,
+) -> Result<Camera, String> {
+    let preset = match &args.camera {
+        Some(name) => Some(scene.camera(name)?),
+        None => scene.default_camera.map(|i| &scene.cameras[i]),
     };
 
-    for layer_num in 1..=9 {
-        let filename = format!("layers/Capa {}.txt", layer_num);
-        
-        match std::fs::read_to_string(&filename) {
-            Ok(content) => {
-                let y = layer_num - 1;
-                println!("✓ Cargando {}: y={}", filename, y);
-                
-                for (z, line) in content.lines().enumerate() {
-                    for (x, ch) in line.chars().enumerate() {
-                        if let Some(material) = mat_of(ch) {
-                            grid.insert(x as i32, y, z as i32, material);
-                        }
-                    }
-                }
-            }
-            Err(_) => {
-                println!("⚠ No se pudo cargar {}, creando capa de prueba", filename);
-                if layer_num == 1 {
-                    for x in 0..16 {
-                        for z in 0..16 {
-                            let mat = match (x + z) % 5 {
-                                0 => Material::new_with_type(MaterialType::Grass),
-                                1 => Material::new_with_type(MaterialType::Stone),
-                                2 => Material::new_with_type(MaterialType::Gold),
-                                3 => Material::new_with_type(MaterialType::GlowingObsidian),
-                                _ => Material::new_with_type(MaterialType::Dirt),
-                            };
-                            grid.insert(x, 0, z, mat);
-                        }
-                    }
-                }
+    let camera_distance = structure_radius * 2.5;
+    let eye = args.eye.or(preset.map(|p| p.eye)).unwrap_or_else(|| {
+        structure_center + Vector3::new(
+            camera_distance * 0.7, 
+            camera_distance * 0.4, 
+            camera_distance * 0.7
+        )
+    });
+    let target = args.target.or(preset.map(|p| p.target)).unwrap_or(structure_center);
+    let fov = args.fov.or(preset.and_then(|p| p.fov)).unwrap_or(DEFAULT_FOV);
+    let projection = args
+        .projection
+        .or(preset.and_then(|p| p.projection))
+        .or(scene.projection)
+        .unwrap_or_default();
+    let mut camera = Camera::new(eye, target, Vector3::new(0.0, 1.0, 0.0), fov, projection);
+    camera.aperture = args.aperture.or(preset.and_then(|p| p.aperture)).unwrap_or(0.0);
+    camera.focus_distance = args.focus_distance.or(preset.and_then(|p| p.focus_distance));
+    camera.bokeh = args.bokeh.or(preset.and_then(|p| p.bokeh)).unwrap_or_default();
+    Ok(camera)
+}
+
+fn create_render_state(
+    world: World,
+    structure_center: Vector3,
+    structure_radius: f32,
+    scene: &Scene,
+    settings: RenderSettings,
+) -> SharedRenderState {
+    let emissive = LightGrid::new(&world.emissive_lights(scene.emissive_strength()));
+
+    SharedRenderState {
+        world: Arc::new(world),
+        bounding_sphere: BoundingSphere {
+            center: structure_center,
+            radius: structure_radius * 1.2,
+        },
+        sun: create_sun(&scene.sky),
+        lights: scene.lights.clone(),
+        emissive,
+        emissive_enabled: true,
+        skybox_enabled: scene.sky.enabled,
+        settings,
+    }
+}
+
+/// Renderiza un único frame sin abrir ventana y lo guarda en `args.output`.
+fn run_headless(args: &RenderArgs) -> Result<(), String> {
+    let scene = load_scene(&args.scene)?;
+    let settings = RenderSettings::resolve(&args.quality, &scene.render);
+    let mut world = scene.build_world()?;
+    let (structure_center, structure_radius) = world.bounding_sphere();
+
+    let cam = create_camera(structure_center, structure_radius, &args.camera, &scene)?;
+    let render_state = create_render_state(
+        world, structure_center, structure_radius, &scene, settings
+    );
+
+    let (width, height) = (settings.width, settings.height);
+    println!("Renderizando {}x{} -> {}", width, height, args.output);
+    let render_start = std::time::Instant::now();
+    let frame = match settings.integrator {
+        Integrator::Whitted => render_parallel_optimized(&cam, width, height, &render_state),
+        Integrator::Path => {
+            let mut accumulator = path_tracer::Accumulator::new(width, height);
+            for sample in 0..settings.samples {
+                let samples = path_tracer::render_path_traced(&cam, width, height, &render_state, sample);
+                accumulator.add_frame(&samples);
             }
+            accumulator.resolve()
         }
+    };
+    println!("✓ Render completado en {:.1}ms", render_start.elapsed().as_secs_f32() * 1000.0);
+
+    if framebuffer::is_hdr_path(&args.output) {
+        frame.save_hdr(&args.output)?;
+    } else {
+        let pixels = settings.tone_mapping.to_colors(&frame);
+        headless::save_pixels(&args.output, width, height, &pixels)?;
     }
+    println!("✓ Imagen guardada en {}", args.output);
+    Ok(())
+}
 
-    println!("✓ Grilla creada con {} voxels", grid.cells.len());
-    grid
+fn run_info(args: &InfoArgs) -> Result<(), String> {
+    let scene = load_scene(&args.scene)?;
+    let mut world = scene.build_world()?;
+    if world.voxel_count() == 0 {
+        return Err("La escena no tiene ningún voxel".to_string());
+    }
+
+    let bounds = world.bounds();
+    let (bmin, bmax) = (bounds.min, bounds.max);
+    let (structure_center, structure_radius) = world.bounding_sphere();
+    let cam = create_camera(structure_center, structure_radius, &args.camera, &scene)?;
+    let sun = create_sun(&scene.sky);
+
+    if let Some(source) = &scene.source {
+        println!("Escena:      {}", source.display());
+    }
+    println!("Capas:       {}", scene.layers.len());
+    println!("Voxels:      {}", world.voxel_count());
+    println!("Objetos:     {}", scene.objects.len());
+    println!("Instancias:  {}", world.instances().len());
+    println!("Límites:     ({:.1}, {:.1}, {:.1}) - ({:.1}, {:.1}, {:.1})",
+             bmin.x, bmin.y, bmin.z, bmax.x, bmax.y, bmax.z);
+    println!("Centro:      ({:.2}, {:.2}, {:.2})", structure_center.x, structure_center.y, structure_center.z);
+    println!("Radio:       {:.2}", structure_radius);
+    println!("Cámara:      ({:.2}, {:.2}, {:.2})", cam.eye.x, cam.eye.y, cam.eye.z);
+    println!("Objetivo:    ({:.2}, {:.2}, {:.2})", cam.target.x, cam.target.y, cam.target.z);
+    println!("FOV:         {:.1}°", cam.vfov_deg);
+    println!("Proyección:  {}", cam.projection().name());
+    if cam.has_lens() {
+        println!("Lente:       apertura {:.3}, foco a {:.2}, bokeh {:?}", cam.aperture, cam.focus(), cam.bokeh);
+    }
+    println!("Sol:         ({:.3}, {:.3}, {:.3})", sun.dir.x, sun.dir.y, sun.dir.z);
+    for preset in &scene.cameras {
+        println!("Vista {:9} ({:.2}, {:.2}, {:.2}) -> ({:.2}, {:.2}, {:.2})",
+                 preset.name, preset.eye.x, preset.eye.y, preset.eye.z,
+                 preset.target.x, preset.target.y, preset.target.z);
+    }
+    for light in &scene.lights {
+        let kind = match light.kind {
+            LightKind::Point => "puntual",
+            LightKind::Spot { .. } => "foco",
+            LightKind::Voxel { .. } => "emisiva",
+        };
+        println!("Luz {:7}  ({:.2}, {:.2}, {:.2}) intensidad {:.1}",
+                 kind, light.position.x, light.position.y, light.position.z, light.intensity);
+    }
+    println!("Luces emisivas: {}", LightGrid::new(&world.emissive_lights(scene.emissive_strength())).len());
+    println!("Threads:     {}", rayon::current_num_threads());
+    Ok(())
 }
 
-fn main() {
+/// Traza los rayos primarios con la grilla jerárquica y con la referencia
+/// `HashMap` y compara tiempos.
+fn run_bench(args: &BenchArgs) -> Result<(), String> {
+    let scene = load_scene(&args.scene)?;
+    let settings = RenderSettings::resolve(&args.quality, &scene.render);
+    let mut grid = scene.build_grid()?;
+    if grid.is_empty() {
+        return Err("La escena no tiene ningún voxel".to_string());
+    }
+    let structure_center = grid.get_center();
+    let structure_radius = grid.get_bounding_sphere_radius();
+    let cam = create_camera(structure_center, structure_radius, &args.camera, &scene)?;
+
+    let (width, height) = (settings.width, settings.height);
+    let rays: Vec<(Vector3, Vector3)> = (0..height)
+        .flat_map(|y| (0..width).map(move |x| (x, y)))
+        .map(|(x, y)| {
+            let ray = cam.get_ray(x, y, width, height);
+            (ray.origin, ray.direction)
+        })
+        .collect();
+
+    println!("Rayos:        {}x{} = {}, {} repeticiones en un hilo", width, height, rays.len(), args.repeats);
+    let result = bench::compare(&grid, &rays, settings.max_ray_dist, args.repeats);
+    let report = |name: &str, time: std::time::Duration| {
+        let secs = time.as_secs_f64();
+        println!("{:13} {:8.1} ms  ({:.2} Mrayos/s)", name, secs * 1000.0, result.rays as f64 / secs / 1e6);
+    };
+    report("HashMap:", result.reference);
+    report("Jerarquía:", result.hierarchical);
+    println!("Aceleración:  {:.2}x", result.reference.as_secs_f64() / result.hierarchical.as_secs_f64());
+    println!("Impactos:     {} de {}", result.hits, result.rays);
+    if result.mismatches > 0 {
+        return Err(format!("{} rayos dan un resultado distinto con las dos grillas", result.mismatches));
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+
     let num_threads = num_cpus::get();
     rayon::ThreadPoolBuilder::new()
         .num_threads(num_threads)
         .build_global()
         .expect("Failed to build thread pool");
 
+    let result = match cli.command {
+        Some(Command::Render(args)) => run_headless(&args),
+        Some(Command::Info(args)) => run_info(&args),
+        Some(Command::Bench(args)) => run_bench(&args),
+        Some(Command::View(args)) => run_interactive(&args, num_threads),
+        None => run_interactive(&cli.view, num_threads),
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Escena de la ventana lista para renderizar. Se arma al abrir la ventana y
+/// al recargar con R; entre medias todos los frames comparten la misma grilla.
+struct LoadedView {
+    scene: Scene,
+    state: SharedRenderState,
+    center: Vector3,
+    radius: f32,
+}
+
+impl LoadedView {
+    fn build(scene: Scene, settings: RenderSettings) -> Result<Self, String> {
+        let mut world = scene.build_world()?;
+        let (center, radius) = world.bounding_sphere();
+        let state = create_render_state(world, center, radius, &scene, settings);
+        Ok(LoadedView { scene, state, center, radius })
+    }
+}
+
+fn run_interactive(args: &ViewArgs, num_threads: usize) -> Result<(), String> {
+    let scene = load_scene(&args.scene)?;
+    let settings = RenderSettings::resolve(&args.quality, &scene.render);
+    let (window_width, window_height) = (settings.width, settings.height);
+
     let (mut rl, thread) = raylib::init()
-        .size(WIDTH, HEIGHT)
+        .size(window_width, window_height)
         .title("Minecraft Diorama - Raytracer Optimizado")
         .build();
 
     rl.set_target_fps(60);
 
-    let mut grid = create_diorama_grid();
-    let structure_center = grid.get_center();
-    let structure_radius = grid.get_bounding_sphere_radius();
-    
-    let bounding_sphere = BoundingSphere {
-        center: structure_center,
-        radius: structure_radius * 1.2,
-    };
+    let mut view = LoadedView::build(scene, settings)?;
     
-    println!("Centro de estructura: {:?}", structure_center);
-    println!("Radio de estructura: {:.2}", structure_radius);
+    println!("Centro de estructura: {:?}", view.center);
+    println!("Radio de estructura: {:.2}", view.radius);
     
-    let mut skybox_enabled = true;
+    let mut skybox_enabled = view.state.skybox_enabled;
+    let mut emissive_enabled = true;
+    println!("Luces emisivas: {}", view.state.emissive.len());
     
-    let camera_distance = structure_radius * 2.5;
-    let mut cam = SimpleCamera::new(
-        structure_center + Vector3::new(
-            camera_distance * 0.7, 
-            camera_distance * 0.4, 
-            camera_distance * 0.7
-        ),
-        structure_center,
-        Vector3::new(0.0, 1.0, 0.0),
-        45.0,
-    );
+    let mut cam = create_camera(view.center, view.radius, &args.camera, &view.scene)?;
 
-    let render_width = WIDTH / RENDER_SCALE;
-    let render_height = HEIGHT / RENDER_SCALE;
+    let render_width = (window_width / args.scale).max(1);
+    let render_height = (window_height / args.scale).max(1);
     
     let mut img = Image::gen_image_color(render_width, render_height, Color::BLACK);
     let mut tex = rl.load_texture_from_image(&thread, &img)
-                    .expect("Failed to create texture");
+                    .map_err(|e| format!("No se pudo crear la textura: {}", e))?;
+
+    // El path tracer suma un frame por iteración mientras la vista no cambie.
+    let mut integrator = settings.integrator;
+    // El mapeo de tonos se aplica al mostrar: cambiarlo no reinicia la acumulación.
+    let mut tone_mapping = settings.tone_mapping;
+    let mut accumulator = path_tracer::Accumulator::new(render_width, render_height);
+    let mut last_view = (cam.eye, cam.target);
 
     let mut frame_count = 0;
     let mut fps_timer = std::time::Instant::now();
@@ -419,29 +767,146 @@ fn main() {
 
     while !rl.window_should_close() {
         handle_input_smooth(&mut cam, &rl);
+        let mut scene_changed = false;
+
+        if let Some(preset) = pressed_camera_preset(&rl).and_then(|i| view.scene.cameras.get(i)) {
+            cam.set_position(preset.eye, preset.target);
+            if let Some(fov) = preset.fov {
+                cam.vfov_deg = fov;
+            }
+            if let Some(projection) = preset.projection {
+                cam.set_projection(projection);
+            }
+            if let Some(aperture) = preset.aperture {
+                cam.aperture = aperture;
+            }
+            cam.focus_distance = preset.focus_distance;
+            if let Some(bokeh) = preset.bokeh {
+                cam.bokeh = bokeh;
+            }
+            scene_changed = true;
+            println!("Cámara: {}", preset.name);
+        }
         
+        if rl.is_key_pressed(KeyboardKey::KEY_O) {
+            cam.set_projection(cam.projection().next());
+            scene_changed = true;
+            println!("Proyección: {}", cam.projection().name());
+        }
+
+        // Lente: [ y ] cierran y abren la apertura, B cambia la forma del
+        // bokeh y el clic derecho enfoca lo que hay bajo el ratón.
+        if rl.is_key_pressed(KeyboardKey::KEY_RIGHT_BRACKET) {
+            cam.aperture = if cam.has_lens() { cam.aperture * 1.5 } else { MIN_APERTURE };
+            scene_changed = true;
+            println!("Apertura: {:.3}", cam.aperture);
+        }
+        if rl.is_key_pressed(KeyboardKey::KEY_LEFT_BRACKET) && cam.has_lens() {
+            cam.aperture /= 1.5;
+            if cam.aperture < MIN_APERTURE {
+                cam.aperture = 0.0;
+            }
+            scene_changed = true;
+            println!("Apertura: {:.3}", cam.aperture);
+        }
+        if rl.is_key_pressed(KeyboardKey::KEY_B) {
+            cam.bokeh = match cam.bokeh {
+                Bokeh::Circle => Bokeh::Hexagon,
+                Bokeh::Hexagon => Bokeh::Circle,
+            };
+            scene_changed = true;
+            println!("Bokeh: {:?}", cam.bokeh);
+        }
+        if rl.is_mouse_button_pressed(MouseButton::MOUSE_BUTTON_RIGHT) {
+            let mouse = rl.get_mouse_position();
+            let px = ((mouse.x as i32) / args.scale).clamp(0, render_width - 1);
+            let py = ((mouse.y as i32) / args.scale).clamp(0, render_height - 1);
+            let ray = cam.get_ray(px, py, render_width, render_height);
+            let hit = view.state.world.intersect_ray(&ray.origin, &ray.direction, view.state.settings.max_ray_dist);
+            if hit.is_intersecting {
+                cam.focus_on(&ray, hit.distance);
+                scene_changed = true;
+                println!("Foco: {:.2}", cam.focus());
+            }
+        }
+
         if rl.is_key_pressed(KeyboardKey::KEY_K) {
             skybox_enabled = !skybox_enabled;
+            view.state.skybox_enabled = skybox_enabled;
+            scene_changed = true;
             println!("Skybox: {}", if skybox_enabled { "Activado" } else { "Desactivado" });
         }
 
-        let render_state = SharedRenderState {
-            grid: Arc::new(grid.clone()),
-            bounding_sphere,
-            sun: {
-                let mut sun_dir = Vector3::new(-0.6, -0.8, -0.4);
-                sun_dir.normalize();
-                DirLight {
-                    dir: sun_dir,
-                    color: Color::WHITE,
-                    intensity: 1.2,
+        if rl.is_key_pressed(KeyboardKey::KEY_L) {
+            emissive_enabled = !emissive_enabled;
+            view.state.emissive_enabled = emissive_enabled;
+            scene_changed = true;
+            println!("Luz emisiva: {}", if emissive_enabled { "Activada" } else { "Desactivada" });
+            if view.state.emissive.is_empty() {
+                println!("   La escena no tiene bloques que iluminen");
+            }
+        }
+
+        if rl.is_key_pressed(KeyboardKey::KEY_P) {
+            integrator = match integrator {
+                Integrator::Whitted => Integrator::Path,
+                Integrator::Path => Integrator::Whitted,
+            };
+            scene_changed = true;
+            println!("Integrador: {:?}", integrator);
+        }
+
+        if rl.is_key_pressed(KeyboardKey::KEY_T) {
+            tone_mapping.operator = tone_mapping.operator.next();
+            println!("Mapeo de tonos: {:?}", tone_mapping.operator);
+        }
+        if rl.is_key_pressed(KeyboardKey::KEY_EQUAL) {
+            tone_mapping.exposure += EXPOSURE_STEP;
+            println!("Exposición: {:+.1} EV", tone_mapping.exposure);
+        }
+        if rl.is_key_pressed(KeyboardKey::KEY_MINUS) {
+            tone_mapping.exposure -= EXPOSURE_STEP;
+            println!("Exposición: {:+.1} EV", tone_mapping.exposure);
+        }
+
+        // Recargar es lo único que reconstruye la grilla. Los ajustes de
+        // calidad se vuelven a resolver con los de la escena nueva; la cámara,
+        // la resolución de la ventana y los interruptores se mantienen.
+        if rl.is_key_pressed(KeyboardKey::KEY_R) {
+            let reloaded = load_scene(&args.scene).and_then(|scene| {
+                let settings = RenderSettings::resolve(&args.quality, &scene.render);
+                LoadedView::build(scene, settings)
+            });
+            match reloaded {
+                Ok(loaded) => {
+                    view = loaded;
+                    view.state.skybox_enabled = skybox_enabled;
+                    view.state.emissive_enabled = emissive_enabled;
+                    scene_changed = true;
+                    println!("✓ Escena recargada: {} voxels", view.state.world.voxel_count());
                 }
-            },
-            skybox_enabled,
-        };
+                Err(e) => eprintln!("No se pudo recargar la escena: {}", e),
+            }
+        }
+
+        let view_pos = (cam.eye, cam.target);
+        if scene_changed || view_pos != last_view {
+            accumulator.reset();
+            last_view = view_pos;
+        }
 
         let render_start = std::time::Instant::now();
-        let pixels = render_parallel_optimized(&cam, render_width, render_height, &render_state);
+        let frame = match integrator {
+            Integrator::Whitted => render_parallel_optimized(&cam, render_width, render_height, &view.state),
+            Integrator::Path => {
+                let samples = path_tracer::render_path_traced(
+                    &cam, render_width, render_height, &view.state, accumulator.samples()
+                );
+                accumulator.add_frame(&samples);
+                accumulator.resolve()
+            }
+        };
+        let pixels = tone_mapping.to_colors(&frame);
         let render_time = render_start.elapsed();
 
         render_times[render_time_index] = render_time.as_secs_f32() * 1000.0;
@@ -468,7 +933,7 @@ fn main() {
             }
             
             tex = rl.load_texture_from_image(&thread, &img)
-                   .expect("Failed to update texture");
+                   .map_err(|e| format!("No se pudo actualizar la textura: {}", e))?;
         }
 
         let mut d = rl.begin_drawing(&thread);
@@ -477,7 +942,7 @@ fn main() {
         d.draw_texture_pro(
             &tex, 
             Rectangle::new(0.0, 0.0, render_width as f32, render_height as f32),
-            Rectangle::new(0.0, 0.0, WIDTH as f32, HEIGHT as f32),
+            Rectangle::new(0.0, 0.0, window_width as f32, window_height as f32),
             Vector2::zero(), 
             0.0, 
             Color::WHITE
@@ -492,17 +957,31 @@ fn main() {
                    10, 35, 12, Color::LIGHTGRAY);
         
         d.draw_text("WASD: mover | QE: altura | Mouse+Click: rotar | Shift: rápido", 10, 50, 11, Color::LIGHTGRAY);
-        d.draw_text("K: Toggle skybox", 10, 65, 11, Color::LIGHTGRAY);
+        d.draw_text("K: Toggle skybox | L: Toggle luz emisiva | P: Path tracing | R: Recargar escena", 10, 65, 11, Color::LIGHTGRAY);
+        d.draw_text(&format!("O: Proyección ({})", cam.projection().name()), 10, 80, 11, Color::LIGHTGRAY);
+        let lens = if cam.has_lens() {
+            format!("apertura {:.3}, foco {:.1}, {:?}", cam.aperture, cam.focus(), cam.bokeh)
+        } else {
+            "estenopeica".to_string()
+        };
+        d.draw_text(&format!("[ ]: Apertura | B: Bokeh | Clic derecho: enfocar ({})", lens), 10, 95, 11, Color::LIGHTGRAY);
+        d.draw_text(&format!("T: Tonos ({:?}) | - =: Exposición ({:+.1} EV)", tone_mapping.operator, tone_mapping.exposure),
+                    10, 110, 11, Color::LIGHTGRAY);
+
+        if integrator == Integrator::Path {
+            d.draw_text(&format!("PATH TRACING: {} muestras", accumulator.samples()),
+                        10, window_height - 20, 12, Color::ORANGE);
+        }
         
         frame_count += 1;
         if fps_timer.elapsed().as_secs() >= 1 {
-            d.draw_text(&format!("FPS: {}", frame_count), WIDTH - 80, 10, 20, Color::GREEN);
+            d.draw_text(&format!("FPS: {}", frame_count), window_width - 80, 10, 20, Color::GREEN);
             frame_count = 0;
             fps_timer = std::time::Instant::now();
         }
         
         if skybox_enabled {
-            d.draw_text("SKYBOX: ON", WIDTH - 150, HEIGHT - 50, 12, Color::CYAN);
+            d.draw_text("SKYBOX: ON", window_width - 150, window_height - 50, 12, Color::CYAN);
         }
         
         let performance_color = if avg_render_time < 16.0 {
@@ -512,11 +991,23 @@ fn main() {
         } else {
             Color::RED
         };
-        d.draw_text(&format!("Perf: {:.1}ms", avg_render_time), WIDTH - 150, HEIGHT - 20, 12, performance_color);
+        d.draw_text(&format!("Perf: {:.1}ms", avg_render_time), window_width - 150, window_height - 20, 12, performance_color);
     }
+
+    Ok(())
+}
+
+/// Índice de la cámara predefinida elegida con las teclas 1-9 en este frame.
+fn pressed_camera_preset(rl: &RaylibHandle) -> Option<usize> {
+    const KEYS: [KeyboardKey; 9] = [
+        KeyboardKey::KEY_ONE, KeyboardKey::KEY_TWO, KeyboardKey::KEY_THREE,
+        KeyboardKey::KEY_FOUR, KeyboardKey::KEY_FIVE, KeyboardKey::KEY_SIX,
+        KeyboardKey::KEY_SEVEN, KeyboardKey::KEY_EIGHT, KeyboardKey::KEY_NINE,
+    ];
+    KEYS.iter().position(|key| rl.is_key_pressed(*key))
 }
 
-fn handle_input_smooth(cam: &mut SimpleCamera, rl: &RaylibHandle) {
+fn handle_input_smooth(cam: &mut Camera, rl: &RaylibHandle) {
     let dt = rl.get_frame_time();
     let base_speed = 8.0 * dt;
     let speed = if rl.is_key_down(KeyboardKey::KEY_LEFT_SHIFT) { 
@@ -525,35 +1016,30 @@ fn handle_input_smooth(cam: &mut SimpleCamera, rl: &RaylibHandle) {
         base_speed 
     };
     
-    let mut eye = cam.eye;
-    let mut target = cam.target;
-    
-    let forward = cam.forward;
-    let right = cam.right;
+    let forward = cam.forward();
+    let right = cam.right();
+    let mut offset = Vector3::zero();
 
     if rl.is_key_down(KeyboardKey::KEY_W) { 
-        eye += forward * speed; 
-        target += forward * speed; 
+        offset += forward * speed; 
     }
     if rl.is_key_down(KeyboardKey::KEY_S) { 
-        eye -= forward * speed; 
-        target -= forward * speed; 
+        offset -= forward * speed; 
     }
     if rl.is_key_down(KeyboardKey::KEY_A) { 
-        eye -= right * speed;   
-        target -= right * speed; 
+        offset -= right * speed;   
     }
     if rl.is_key_down(KeyboardKey::KEY_D) { 
-        eye += right * speed;   
-        target += right * speed; 
+        offset += right * speed;   
     }
     if rl.is_key_down(KeyboardKey::KEY_Q) { 
-        eye.y -= speed; 
-        target.y -= speed; 
+        offset.y -= speed; 
     }
     if rl.is_key_down(KeyboardKey::KEY_E) { 
-        eye.y += speed; 
-        target.y += speed; 
+        offset.y += speed; 
+    }
+    if offset != Vector3::zero() {
+        cam.translate(offset);
     }
 
     if rl.is_mouse_button_down(MouseButton::MOUSE_BUTTON_LEFT) {
@@ -561,25 +1047,7 @@ fn handle_input_smooth(cam: &mut SimpleCamera, rl: &RaylibHandle) {
         let sensitivity = 0.003;
         
         if mouse_delta.length() > 0.01 {
-            let yaw = mouse_delta.x * sensitivity;   
-            let pitch = mouse_delta.y * sensitivity; 
-            
-            let relative = eye - target;
-            let radius = relative.length();
-            
-            let theta = relative.z.atan2(relative.x) + yaw;
-            let phi = (relative.y / radius).asin() + pitch;
-            let phi = phi.clamp(-1.5, 1.5);
-            
-            let new_relative = Vector3::new(
-                radius * phi.cos() * theta.cos(),
-                radius * phi.sin(),
-                radius * phi.cos() * theta.sin(),
-            );
-            
-            eye = target + new_relative;
+            cam.orbit(mouse_delta.x * sensitivity, mouse_delta.y * sensitivity);
         }
     }
-
-    cam.set_position(eye, target);
 }
\ No newline at end of file
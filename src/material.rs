@@ -1,5 +1,8 @@
-use raylib::prelude::{Color, Vector3};
-use crate::texture::Texture;
+use raylib::prelude::Vector3;
+use std::collections::HashMap;
+use crate::color::srgb_to_linear_rgb;
+use crate::shape::{Axis, BlockShape, Face, Facing, Half, VoxelState};
+use crate::texture::{FaceTextures, Texture};
 
 #[derive(Debug, Clone, Copy)]
 pub enum MaterialType {
@@ -15,18 +18,73 @@ pub enum MaterialType {
     StonePillar,
     WoodChest,
     GlowingObsidian,
+    Glass,
+    Water,
+    Ice,
+    StainedGlass,
 }
 
-#[derive(Debug, Clone)]
+impl MaterialType {
+    pub const ALL: [MaterialType; 16] = [
+        MaterialType::Grass,
+        MaterialType::Netherrack,
+        MaterialType::Dirt,
+        MaterialType::Stone,
+        MaterialType::Magma,
+        MaterialType::Gold,
+        MaterialType::Obsidian,
+        MaterialType::StoneStairs,
+        MaterialType::StoneSlab,
+        MaterialType::StonePillar,
+        MaterialType::WoodChest,
+        MaterialType::GlowingObsidian,
+        MaterialType::Glass,
+        MaterialType::Water,
+        MaterialType::Ice,
+        MaterialType::StainedGlass,
+    ];
+
+    /// Nombre con el que se referencia el material desde los archivos de escena.
+    pub fn name(&self) -> &'static str {
+        match self {
+            MaterialType::Grass => "grass",
+            MaterialType::Netherrack => "netherrack",
+            MaterialType::Dirt => "dirt",
+            MaterialType::Stone => "stone",
+            MaterialType::Magma => "magma",
+            MaterialType::Gold => "gold",
+            MaterialType::Obsidian => "obsidian",
+            MaterialType::StoneStairs => "stone_stairs",
+            MaterialType::StoneSlab => "stone_slab",
+            MaterialType::StonePillar => "stone_pillar",
+            MaterialType::WoodChest => "wood_chest",
+            MaterialType::GlowingObsidian => "glowing_obsidian",
+            MaterialType::Glass => "glass",
+            MaterialType::Water => "water",
+            MaterialType::Ice => "ice",
+            MaterialType::StainedGlass => "stained_glass",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<MaterialType> {
+        MaterialType::ALL.iter().copied().find(|m| m.name() == name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Material {
     pub diffuse: Vector3,
     pub albedo: [f32; 4], 
     pub specular: f32,
     pub refractive_index: f32,
     pub texture: Option<Texture>,
+    /// Texturas propias de arriba, abajo, los lados o el frente.
+    pub faces: FaceTextures,
     pub roughness: f32,
     pub metallic: f32,
     pub emission: f32,
+    /// Geometría dentro de la celda; las escaleras, losas y pilares no son cubos.
+    pub shape: BlockShape,
     cached_color: Option<Vector3>,
 }
 
@@ -43,9 +101,11 @@ impl Material {
             specular,
             refractive_index,
             texture: None,
+            faces: FaceTextures::default(),
             roughness: 0.5,
             metallic: 0.0,
             emission: 0.0,
+            shape: BlockShape::Full,
             cached_color: None,
         }
     }
@@ -63,9 +123,11 @@ impl Material {
             specular,
             refractive_index,
             texture: Some(texture),
+            faces: FaceTextures::default(),
             roughness: 0.5,
             metallic: 0.0,
             emission: 0.0,
+            shape: BlockShape::Full,
             cached_color: None,
         }
     }
@@ -78,9 +140,11 @@ impl Material {
                 specular: 8.0,
                 refractive_index: 1.0,
                 texture: Some(Texture::new_with_type(material_type)),
+                faces: FaceTextures::for_type(material_type),
                 roughness: 0.8,
                 metallic: 0.0,
                 emission: 0.0,
+                shape: BlockShape::Full,
                 cached_color: Some(Vector3::new(0.4, 0.7, 0.2)),
             },
             
@@ -90,9 +154,11 @@ impl Material {
                 specular: 4.0,
                 refractive_index: 1.0,
                 texture: Some(Texture::new_with_type(material_type)),
+                faces: FaceTextures::for_type(material_type),
                 roughness: 0.9,
                 metallic: 0.0,
                 emission: 0.15,
+                shape: BlockShape::Full,
                 cached_color: Some(Vector3::new(0.6, 0.2, 0.2)),
             },
             
@@ -102,9 +168,11 @@ impl Material {
                 specular: 2.0,
                 refractive_index: 1.0,
                 texture: Some(Texture::new_with_type(material_type)),
+                faces: FaceTextures::for_type(material_type),
                 roughness: 0.95,
                 metallic: 0.0,
                 emission: 0.0,
+                shape: BlockShape::Full,
                 cached_color: Some(Vector3::new(0.5, 0.3, 0.2)),
             },
             
@@ -114,9 +182,11 @@ impl Material {
                 specular: 18.0,
                 refractive_index: 1.0,
                 texture: Some(Texture::new_with_type(material_type)),
+                faces: FaceTextures::for_type(material_type),
                 roughness: 0.6,
                 metallic: 0.0,
                 emission: 0.0,
+                shape: BlockShape::Full,
                 cached_color: Some(Vector3::new(0.5, 0.5, 0.5)),
             },
             
@@ -126,9 +196,11 @@ impl Material {
                 specular: 25.0,
                 refractive_index: 1.0,
                 texture: Some(Texture::new_with_type(material_type)),
+                faces: FaceTextures::for_type(material_type),
                 roughness: 0.3,
                 metallic: 0.0,
                 emission: 0.8,
+                shape: BlockShape::Full,
                 cached_color: Some(Vector3::new(0.8, 0.3, 0.1)),
             },
             
@@ -138,9 +210,11 @@ impl Material {
                 specular: 120.0,
                 refractive_index: 1.0,
                 texture: Some(Texture::new_with_type(material_type)),
+                faces: FaceTextures::for_type(material_type),
                 roughness: 0.15,
                 metallic: 1.0,
                 emission: 0.0,
+                shape: BlockShape::Full,
                 cached_color: Some(Vector3::new(1.0, 0.8, 0.0)),
             },
             
@@ -150,9 +224,11 @@ impl Material {
                 specular: 90.0,
                 refractive_index: 1.0,
                 texture: Some(Texture::new_with_type(material_type)),
+                faces: FaceTextures::for_type(material_type),
                 roughness: 0.15,
                 metallic: 0.0,
                 emission: 0.0,
+                shape: BlockShape::Full,
                 cached_color: Some(Vector3::new(0.15, 0.1, 0.25)),
             },
             
@@ -162,9 +238,11 @@ impl Material {
                 specular: 120.0,
                 refractive_index: 1.0,
                 texture: Some(Texture::new_with_type(material_type)),
+                faces: FaceTextures::for_type(material_type),
                 roughness: 0.1,
                 metallic: 0.0,
                 emission: 1.2,
+                shape: BlockShape::Full,
                 cached_color: Some(Vector3::new(0.5, 0.3, 0.9)),
             },
             
@@ -174,9 +252,11 @@ impl Material {
                 specular: 15.0,
                 refractive_index: 1.0,
                 texture: Some(Texture::new_with_type(material_type)),
+                faces: FaceTextures::for_type(material_type),
                 roughness: 0.7,
                 metallic: 0.0,
                 emission: 0.0,
+                shape: BlockShape::Stairs(Facing::North, Half::Bottom),
                 cached_color: Some(Vector3::new(0.6, 0.6, 0.6)),
             },
             
@@ -186,9 +266,11 @@ impl Material {
                 specular: 12.0,
                 refractive_index: 1.0,
                 texture: Some(Texture::new_with_type(material_type)),
+                faces: FaceTextures::for_type(material_type),
                 roughness: 0.65,
                 metallic: 0.0,
                 emission: 0.0,
+                shape: BlockShape::Slab(Half::Bottom),
                 cached_color: Some(Vector3::new(0.55, 0.55, 0.55)),
             },
             
@@ -198,9 +280,11 @@ impl Material {
                 specular: 22.0,
                 refractive_index: 1.0,
                 texture: Some(Texture::new_with_type(material_type)),
+                faces: FaceTextures::for_type(material_type),
                 roughness: 0.4,
                 metallic: 0.0,
                 emission: 0.0,
+                shape: BlockShape::Pillar(Axis::Y),
                 cached_color: Some(Vector3::new(0.7, 0.7, 0.7)),
             },
             
@@ -210,11 +294,71 @@ impl Material {
                 specular: 6.0,
                 refractive_index: 1.0,
                 texture: Some(Texture::new_with_type(material_type)),
+                faces: FaceTextures::for_type(material_type),
                 roughness: 0.75,
                 metallic: 0.0,
                 emission: 0.0,
+                shape: BlockShape::Full,
                 cached_color: Some(Vector3::new(0.6, 0.4, 0.2)),
             },
+
+            // Materiales transparentes: albedo[3] es el peso de transmisión y la
+            // reflexión la decide Fresnel, por eso albedo[2] queda en cero.
+            MaterialType::Glass => Material {
+                diffuse: Vector3::new(0.92, 0.95, 0.98),
+                albedo: [0.1, 0.5, 0.0, 0.9],
+                specular: 125.0,
+                refractive_index: 1.5,
+                texture: Some(Texture::new_with_type(material_type)),
+                faces: FaceTextures::for_type(material_type),
+                roughness: 0.05,
+                metallic: 0.0,
+                emission: 0.0,
+                shape: BlockShape::Full,
+                cached_color: Some(Vector3::new(0.92, 0.95, 0.98)),
+            },
+
+            MaterialType::Water => Material {
+                diffuse: Vector3::new(0.25, 0.45, 0.8),
+                albedo: [0.2, 0.3, 0.0, 0.8],
+                specular: 80.0,
+                refractive_index: 1.33,
+                texture: Some(Texture::new_with_type(material_type)),
+                faces: FaceTextures::for_type(material_type),
+                roughness: 0.1,
+                metallic: 0.0,
+                emission: 0.0,
+                shape: BlockShape::Full,
+                cached_color: Some(Vector3::new(0.25, 0.45, 0.8)),
+            },
+
+            MaterialType::Ice => Material {
+                diffuse: Vector3::new(0.7, 0.85, 1.0),
+                albedo: [0.35, 0.3, 0.0, 0.6],
+                specular: 60.0,
+                refractive_index: 1.31,
+                texture: Some(Texture::new_with_type(material_type)),
+                faces: FaceTextures::for_type(material_type),
+                roughness: 0.25,
+                metallic: 0.0,
+                emission: 0.0,
+                shape: BlockShape::Full,
+                cached_color: Some(Vector3::new(0.7, 0.85, 1.0)),
+            },
+
+            MaterialType::StainedGlass => Material {
+                diffuse: Vector3::new(0.6, 0.3, 0.9),
+                albedo: [0.25, 0.4, 0.0, 0.75],
+                specular: 110.0,
+                refractive_index: 1.5,
+                texture: Some(Texture::new_with_type(material_type)),
+                faces: FaceTextures::for_type(material_type),
+                roughness: 0.05,
+                metallic: 0.0,
+                emission: 0.0,
+                shape: BlockShape::Full,
+                cached_color: Some(Vector3::new(0.6, 0.3, 0.9)),
+            },
         }
     }
 
@@ -225,25 +369,56 @@ impl Material {
             specular: 0.0,
             refractive_index: 1.0,
             texture: None,
+            faces: FaceTextures::default(),
             roughness: 1.0,
             metallic: 0.0,
             emission: 0.0,
+            shape: BlockShape::Full,
             cached_color: Some(Vector3::zero()),
         }
     }
 
-    pub fn get_diffuse_color_sharp(&self, u: f32, v: f32, _normal: &Vector3) -> Vector3 {
-        match &self.texture {
+    /// Fracción de la luz que atraviesa el material (albedo[3]).
+    #[inline]
+    pub fn transmission(&self) -> f32 {
+        self.albedo[3]
+    }
+
+    #[inline]
+    pub fn is_transparent(&self) -> bool {
+        self.transmission() > 0.01
+    }
+
+    /// Textura de la cara alcanzada, según la normal y el estado del voxel.
+    pub fn texture_for(&self, normal: &Vector3, state: &VoxelState) -> Option<&Texture> {
+        let faces = &self.faces;
+        let texture = match state.face(normal) {
+            Face::Top => faces.top.as_ref(),
+            Face::Bottom => faces.bottom.as_ref().or(faces.top.as_ref()),
+            Face::Side => faces.side.as_ref(),
+            Face::Front => faces.front.as_ref().or(faces.side.as_ref()),
+        };
+        texture.or(self.texture.as_ref())
+    }
+
+    /// `diffuse` en lineal; como los colores de las paletas, se escribe en sRGB.
+    #[inline]
+    pub fn base_color(&self) -> Vector3 {
+        srgb_to_linear_rgb(self.diffuse)
+    }
+
+    pub fn get_diffuse_color_sharp(&self, u: f32, v: f32, normal: &Vector3, state: &VoxelState) -> Vector3 {
+        match self.texture_for(normal, state) {
             Some(texture) => {
                 let texture_color = texture.get_nearest_color(u, v);
-                texture_color * 0.98 + self.diffuse * 0.02
+                texture_color * 0.98 + self.base_color() * 0.02
             },
-            None => self.diffuse,
+            None => self.base_color(),
         }
     }
 
     pub fn get_diffuse_color(&self, u: f32, v: f32, normal: &Vector3) -> Vector3 {
-        self.get_diffuse_color_sharp(u, v, normal)
+        self.get_diffuse_color_sharp(u, v, normal, &VoxelState::default())
     }
 
     pub fn get_diffuse_color_improved(&self, u: f32, v: f32, normal: &Vector3, lod_bias: f32) -> Vector3 {
@@ -255,7 +430,7 @@ impl Material {
                     texture.get_nearest_color(u, v)
                 };
                 
-                texture_color * 0.96 + self.diffuse * 0.04
+                texture_color * 0.96 + self.base_color() * 0.04
             },
             None => {
                 self.get_procedural_variation(u, v, normal)
@@ -266,15 +441,15 @@ impl Material {
     pub fn get_procedural_variation(&self, u: f32, v: f32, _normal: &Vector3) -> Vector3 {
         let noise = (u * 23.0 + v * 17.0).sin() * 0.005;
         let variation = 1.0 + noise;
-        self.diffuse * variation
+        self.base_color() * variation
     }
     
-    pub fn get_emission_color(&self, u: f32, v: f32, normal: &Vector3) -> Vector3 {
+    pub fn get_emission_color(&self, u: f32, v: f32, normal: &Vector3, state: &VoxelState) -> Vector3 {
         if self.emission > 0.0 {
-            match &self.texture {
+            match self.texture_for(normal, state) {
                 Some(texture) => {
                     let texture_color = texture.get_nearest_color(u, v);
-                    let emission_color = self.diffuse + texture_color * 0.3;
+                    let emission_color = self.base_color() + texture_color * 0.3;
                     emission_color * self.emission
                 },
                 None => {
@@ -303,17 +478,128 @@ impl Material {
     }
 }
 
-pub fn vector3_to_color(v: Vector3) -> Color {
-    let gamma_corrected = Vector3::new(
-        v.x.powf(1.0 / 2.1),
-        v.y.powf(1.0 / 2.1), 
-        v.z.powf(1.0 / 2.1)
-    );
-    
-    Color::new(
-        (gamma_corrected.x.clamp(0.0, 1.0) * 255.0) as u8,
-        (gamma_corrected.y.clamp(0.0, 1.0) * 255.0) as u8,
-        (gamma_corrected.z.clamp(0.0, 1.0) * 255.0) as u8,
-        255,
-    )
-}
\ No newline at end of file
+/// Índice de un material en una `MaterialPalette`. Es lo que guardan los
+/// voxels y los impactos en lugar del material completo.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct MaterialId(u16);
+
+impl MaterialId {
+    /// Posición en la paleta; los materiales de `MaterialPalette::iter`
+    /// salen en este orden.
+    #[inline]
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// Lo que compara `==` entre materiales, en una forma que se puede hashear:
+/// los números por sus bits y las texturas por identidad.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct MaterialKey {
+    numbers: [u32; 12],
+    cached_color: Option<[u32; 3]>,
+    texture: Option<Texture>,
+    faces: FaceTextures,
+    shape: BlockShape,
+}
+
+impl MaterialKey {
+    fn new(m: &Material) -> Self {
+        // 0.0 y -0.0 son iguales para `==` pero no en bits.
+        let bits = |v: f32| if v == 0.0 { 0 } else { v.to_bits() };
+        let vector = |v: Vector3| [bits(v.x), bits(v.y), bits(v.z)];
+        let [dr, dg, db] = vector(m.diffuse);
+        let [a0, a1, a2, a3] = m.albedo.map(bits);
+        MaterialKey {
+            numbers: [
+                dr, dg, db, a0, a1, a2, a3,
+                bits(m.specular), bits(m.refractive_index),
+                bits(m.roughness), bits(m.metallic), bits(m.emission),
+            ],
+            cached_color: m.cached_color.map(vector),
+            texture: m.texture.clone(),
+            faces: m.faces.clone(),
+            shape: m.shape,
+        }
+    }
+}
+
+/// Materiales distintos de una escena, cada uno guardado una sola vez.
+#[derive(Debug, Clone)]
+pub struct MaterialPalette {
+    materials: Vec<Material>,
+    index: HashMap<MaterialKey, MaterialId>,
+}
+
+impl MaterialPalette {
+    /// El índice 0 es el material negro, el de `MaterialId::default()` y los
+    /// impactos vacíos.
+    pub fn new() -> Self {
+        let black = Material::black();
+        let index = HashMap::from([(MaterialKey::new(&black), MaterialId(0))]);
+        MaterialPalette { materials: vec![black], index }
+    }
+
+    /// Índice del material, añadiéndolo si no estaba. Los materiales iguales
+    /// (mismos valores y mismas texturas) comparten índice. Los `MaterialId`
+    /// son de 16 bits: pasado ese límite es un error.
+    pub fn intern(&mut self, material: Material) -> Result<MaterialId, String> {
+        let key = MaterialKey::new(&material);
+        if let Some(&id) = self.index.get(&key) {
+            return Ok(id);
+        }
+        let id = u16::try_from(self.materials.len())
+            .map(MaterialId)
+            .map_err(|_| "más de 65536 materiales distintos en la escena".to_string())?;
+        self.materials.push(material);
+        self.index.insert(key, id);
+        Ok(id)
+    }
+
+    #[inline]
+    pub fn get(&self, id: MaterialId) -> &Material {
+        &self.materials[id.0 as usize]
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Material> + '_ {
+        self.materials.iter()
+    }
+}
+
+impl Default for MaterialPalette {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gray(value: f32) -> Material {
+        Material::new(Vector3::new(value, value, value), 0.0, [1.0, 0.0, 0.0, 0.0], 1.0)
+    }
+
+    #[test]
+    fn equal_materials_share_id() {
+        let mut palette = MaterialPalette::new();
+        let a = palette.intern(gray(0.5)).unwrap();
+        let b = palette.intern(gray(0.25)).unwrap();
+        assert_ne!(a, b);
+        assert_eq!(palette.intern(gray(0.5)).unwrap(), a);
+        assert_eq!(palette.intern(gray(-0.0)).unwrap(), palette.intern(gray(0.0)).unwrap());
+        assert_eq!(palette.intern(Material::black()).unwrap(), MaterialId::default());
+    }
+
+    #[test]
+    fn too_many_materials_is_an_error() {
+        let mut palette = MaterialPalette::new();
+        for i in 1..=u16::MAX {
+            palette.intern(gray(i as f32)).unwrap();
+        }
+        let error = palette.intern(gray(-1.0)).unwrap_err();
+        assert!(error.contains("65536"), "{}", error);
+        // Los que ya estaban se siguen encontrando.
+        assert_eq!(palette.intern(gray(1.0)).unwrap().index(), 1);
+    }
+}
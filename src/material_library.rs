@@ -0,0 +1,216 @@
+use raylib::prelude::Vector3;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::material::{Material, MaterialType};
+use crate::scene::NonNegative;
+use crate::shape::BlockShape;
+use crate::texture::{FaceTextures, Texture};
+
+/// Materiales definidos en archivos de biblioteca. Los nombres que no están
+/// aquí se buscan entre los materiales integrados (`stone`, `glass`, ...).
+#[derive(Debug, Clone, Default)]
+pub struct MaterialLibrary {
+    materials: HashMap<String, Material>,
+}
+
+impl MaterialLibrary {
+    /// Carga una biblioteca TOML o JSON y la añade a esta. Un material puede
+    /// redefinir uno integrado o partir de otro con `base`.
+    pub fn load(&mut self, path: &Path) -> Result<(), String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("No se pudo leer {}: {}", path.display(), e))?;
+
+        let is_json = path
+            .extension()
+            .map(|ext| ext.eq_ignore_ascii_case("json"))
+            .unwrap_or(false);
+        let file: LibraryFile = if is_json {
+            serde_json::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))?
+        } else {
+            toml::from_str(&text).map_err(|e| format!("{}:\n{}", path.display(), e))?
+        };
+
+        let base_dir = path.parent().unwrap_or(Path::new("."));
+        for def in file.materials {
+            let name = def.name.clone();
+            let material = self
+                .build(def, base_dir)
+                .map_err(|e| format!("{}: material `{}`: {}", path.display(), name, e))?;
+            self.materials.insert(name, material);
+        }
+        Ok(())
+    }
+
+    /// Material con ese nombre: primero la biblioteca y luego los integrados.
+    pub fn get(&self, name: &str) -> Option<Material> {
+        self.materials
+            .get(name)
+            .cloned()
+            .or_else(|| MaterialType::from_name(name).map(Material::new_with_type))
+    }
+
+    /// Todos los nombres disponibles, para los mensajes de error.
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = MaterialType::ALL.iter().map(|m| m.name().to_string()).collect();
+        let mut custom: Vec<&String> = self.materials.keys().filter(|n| !names.contains(n)).collect();
+        custom.sort();
+        names.extend(custom.into_iter().cloned());
+        names
+    }
+
+    fn build(&self, def: MaterialDef, base_dir: &Path) -> Result<Material, String> {
+        let mut material = match &def.base {
+            Some(base) => self
+                .get(base)
+                .ok_or_else(|| format!("el material base `{}` no existe", base))?,
+            None => Material::new(Vector3::new(0.8, 0.8, 0.8), 10.0, [0.9, 0.1, 0.0, 0.0], 1.0),
+        };
+
+        if let Some(color) = def.diffuse {
+            material.diffuse = color.0;
+        }
+        if let Some(albedo) = def.albedo {
+            material.albedo = albedo.map(|a| a.0);
+        }
+        if let Some(specular) = def.specular {
+            material.specular = specular.0;
+        }
+        if let Some(ior) = def.refractive_index {
+            material.refractive_index = ior.0;
+        }
+        if let Some(roughness) = def.roughness {
+            material.roughness = roughness.0;
+        }
+        if let Some(metallic) = def.metallic {
+            material.metallic = metallic.0;
+        }
+        if let Some(emission) = def.emission {
+            material.emission = emission.0;
+        }
+        if let Some(shape) = def.shape {
+            material.shape = shape.0;
+        }
+        // Una textura nueva sustituye también a las caras heredadas de `base`.
+        if let Some(texture) = &def.texture {
+            material.texture = Some(Texture::from_file(&base_dir.join(texture), material.diffuse)?);
+            material.faces = FaceTextures::default();
+        }
+        if let Some(faces) = &def.faces {
+            let load = |path: &Option<String>| -> Result<Option<Texture>, String> {
+                path.as_ref()
+                    .map(|p| Texture::from_file(&base_dir.join(p), material.diffuse))
+                    .transpose()
+            };
+            let top = load(&faces.top)?;
+            let bottom = load(&faces.bottom)?;
+            let side = load(&faces.side)?;
+            let front = load(&faces.front)?;
+            let inherited = &mut material.faces;
+            inherited.top = top.or(inherited.top.take());
+            inherited.bottom = bottom.or(inherited.bottom.take());
+            inherited.side = side.or(inherited.side.take());
+            inherited.front = front.or(inherited.front.take());
+        }
+        Ok(material)
+    }
+}
+
+// Formato del archivo. Los rangos se validan al deserializar para que el
+// error indique línea y columna.
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LibraryFile {
+    #[serde(default)]
+    materials: Vec<MaterialDef>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MaterialDef {
+    name: String,
+    base: Option<String>,
+    texture: Option<String>,
+    diffuse: Option<UnitColor>,
+    /// `[difuso, especular, reflexión, transmisión]`, como `Material::albedo`.
+    albedo: Option<[Unit; 4]>,
+    specular: Option<NonNegative>,
+    refractive_index: Option<Ior>,
+    roughness: Option<Unit>,
+    metallic: Option<Unit>,
+    emission: Option<NonNegative>,
+    /// `full`, `slab`, `stairs` o `pillar`, con orientación opcional:
+    /// `stairs[facing=east,half=top]`.
+    shape: Option<Shape>,
+    faces: Option<FacesDef>,
+}
+
+/// Texturas por cara, relativas a la biblioteca como `texture`.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FacesDef {
+    top: Option<String>,
+    bottom: Option<String>,
+    side: Option<String>,
+    front: Option<String>,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(try_from = "f32")]
+struct Unit(f32);
+
+impl TryFrom<f32> for Unit {
+    type Error = &'static str;
+
+    fn try_from(v: f32) -> Result<Self, Self::Error> {
+        if !(0.0..=1.0).contains(&v) {
+            return Err("el valor debe estar entre 0 y 1");
+        }
+        Ok(Unit(v))
+    }
+}
+
+/// Color `[r, g, b]` con componentes entre 0 y 1.
+#[derive(Deserialize)]
+#[serde(try_from = "[f32; 3]")]
+struct UnitColor(Vector3);
+
+impl TryFrom<[f32; 3]> for UnitColor {
+    type Error = &'static str;
+
+    fn try_from(c: [f32; 3]) -> Result<Self, Self::Error> {
+        if c.iter().any(|v| !(0.0..=1.0).contains(v)) {
+            return Err("las componentes del color deben estar entre 0 y 1");
+        }
+        Ok(UnitColor(Vector3::new(c[0], c[1], c[2])))
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(try_from = "String")]
+struct Shape(BlockShape);
+
+impl TryFrom<String> for Shape {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, String> {
+        BlockShape::parse(&s).map(Shape)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(try_from = "f32")]
+struct Ior(f32);
+
+impl TryFrom<f32> for Ior {
+    type Error = &'static str;
+
+    fn try_from(v: f32) -> Result<Self, Self::Error> {
+        if !(1.0..=3.0).contains(&v) {
+            return Err("el índice de refracción debe estar entre 1 y 3");
+        }
+        Ok(Ior(v))
+    }
+}
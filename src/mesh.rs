@@ -0,0 +1,189 @@
+use raylib::prelude::Vector3;
+use std::path::Path;
+
+use crate::bvh::Aabb;
+use crate::material::MaterialId;
+use crate::ray_intersect::{Intersect, RayIntersect};
+
+/// Triángulo de una malla. Las normales y UV de los vértices son opcionales:
+/// sin normales se usa la del plano del triángulo y sin UV, las baricéntricas.
+pub struct Triangle {
+    pub positions: [Vector3; 3],
+    pub normals: Option<[Vector3; 3]>,
+    pub uvs: Option<[(f32, f32); 3]>,
+    pub material: MaterialId,
+}
+
+impl RayIntersect for Triangle {
+    /// Möller-Trumbore. La normal devuelta siempre enfrenta al rayo, así que
+    /// el triángulo se ve por las dos caras.
+    fn ray_intersect(&self, ray_origin: &Vector3, ray_direction: &Vector3) -> Intersect {
+        let [p0, p1, p2] = self.positions;
+        let edge1 = p1 - p0;
+        let edge2 = p2 - p0;
+        let h = ray_direction.cross(edge2);
+        let det = edge1.dot(h);
+        if det.abs() < 1e-8 {
+            return Intersect::empty();
+        }
+
+        let inv_det = 1.0 / det;
+        let s = *ray_origin - p0;
+        let b1 = s.dot(h) * inv_det;
+        if !(0.0..=1.0).contains(&b1) {
+            return Intersect::empty();
+        }
+        let q = s.cross(edge1);
+        let b2 = ray_direction.dot(q) * inv_det;
+        if b2 < 0.0 || b1 + b2 > 1.0 {
+            return Intersect::empty();
+        }
+        let t = edge2.dot(q) * inv_det;
+        if t <= 1e-6 {
+            return Intersect::empty();
+        }
+
+        let b0 = 1.0 - b1 - b2;
+        let mut facing = edge1.cross(edge2).normalized();
+        if facing.dot(*ray_direction) > 0.0 {
+            facing = -facing;
+        }
+        // La normal interpolada se gira al lado de la cara alcanzada, sea cual
+        // sea el sentido de los vértices en el archivo.
+        let normal = match self.normals {
+            Some([n0, n1, n2]) => {
+                let smooth = (n0 * b0 + n1 * b1 + n2 * b2).normalized();
+                if smooth.dot(facing) < 0.0 { -smooth } else { smooth }
+            }
+            None => facing,
+        };
+
+        let (u, v) = match self.uvs {
+            Some([uv0, uv1, uv2]) => (
+                uv0.0 * b0 + uv1.0 * b1 + uv2.0 * b2,
+                uv0.1 * b0 + uv1.1 * b1 + uv2.1 * b2,
+            ),
+            None => (b1, b2),
+        };
+
+        let point = *ray_origin + *ray_direction * t;
+        Intersect::new_with_uv(point, normal, t, self.material, u, v)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        // Un poco de grosor para que los triángulos alineados a un eje no
+        // tengan una caja plana.
+        let pad = Vector3::new(1e-4, 1e-4, 1e-4);
+        let [p0, p1, p2] = self.positions;
+        let bounds = Aabb::new(p0, p0).grow(p1).grow(p2);
+        Some(Aabb::new(bounds.min - pad, bounds.max + pad))
+    }
+}
+
+/// Carga un archivo Wavefront `.obj` como triángulos, escalado por `scale`
+/// y desplazado a `position`. Se leen vértices, coordenadas de textura,
+/// normales y caras (los polígonos se dividen en abanico); grupos, objetos y
+/// materiales `.mtl` se ignoran y toda la malla usa `material`.
+pub fn load_obj(path: &Path, position: Vector3, scale: f32, material: MaterialId) -> Result<Vec<Triangle>, String> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("No se pudo leer {}: {}", path.display(), e))?;
+
+    let mut positions = Vec::new();
+    let mut uvs = Vec::new();
+    let mut normals = Vec::new();
+    let mut triangles = Vec::new();
+
+    for (number, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("");
+        let mut tokens = line.split_whitespace();
+        let Some(keyword) = tokens.next() else {
+            continue;
+        };
+        let at = |e: String| format!("{}:{}: {}", path.display(), number + 1, e);
+        let values: Vec<&str> = tokens.collect();
+
+        match keyword {
+            "v" => {
+                let [x, y, z] = floats(&values).map_err(at)?;
+                positions.push(Vector3::new(x, y, z) * scale + position);
+            }
+            "vt" => {
+                let [u, v] = floats(&values).map_err(at)?;
+                // En OBJ la v crece hacia arriba; en las texturas, hacia abajo.
+                uvs.push((u, 1.0 - v));
+            }
+            "vn" => {
+                let [x, y, z] = floats(&values).map_err(at)?;
+                normals.push(Vector3::new(x, y, z).normalized());
+            }
+            "f" => {
+                if values.len() < 3 {
+                    return Err(at("una cara necesita al menos tres vértices".to_string()));
+                }
+                let corners = values
+                    .iter()
+                    .map(|corner| parse_corner(corner, positions.len(), uvs.len(), normals.len()))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(at)?;
+                for i in 1..corners.len() - 1 {
+                    let [a, b, c] = [corners[0], corners[i], corners[i + 1]];
+                    let all = |f: fn(&Corner) -> Option<usize>| Some([f(&a)?, f(&b)?, f(&c)?]);
+                    triangles.push(Triangle {
+                        positions: [positions[a.position], positions[b.position], positions[c.position]],
+                        normals: all(|c| c.normal).map(|i| i.map(|i| normals[i])),
+                        uvs: all(|c| c.uv).map(|i| i.map(|i| uvs[i])),
+                        material,
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if triangles.is_empty() {
+        return Err(format!("{}: la malla no tiene caras", path.display()));
+    }
+    Ok(triangles)
+}
+
+/// Vértice de una cara, con los índices ya resueltos desde cero.
+#[derive(Clone, Copy)]
+struct Corner {
+    position: usize,
+    uv: Option<usize>,
+    normal: Option<usize>,
+}
+
+/// `v`, `v/vt`, `v//vn` o `v/vt/vn`, con índices desde 1 o negativos
+/// (relativos al final).
+fn parse_corner(text: &str, positions: usize, uvs: usize, normals: usize) -> Result<Corner, String> {
+    let mut parts = text.split('/');
+    let index = |part: Option<&str>, count: usize| -> Result<Option<usize>, String> {
+        let Some(part) = part.filter(|p| !p.is_empty()) else {
+            return Ok(None);
+        };
+        let value: i64 = part.parse().map_err(|_| format!("índice inválido `{}`", part))?;
+        let resolved = if value > 0 { value - 1 } else { count as i64 + value };
+        if value == 0 || resolved < 0 || resolved >= count as i64 {
+            return Err(format!("índice `{}` fuera de rango en `{}`", value, text));
+        }
+        Ok(Some(resolved as usize))
+    };
+    let position = index(parts.next(), positions)?.ok_or_else(|| format!("vértice vacío en `{}`", text))?;
+    let uv = index(parts.next(), uvs)?;
+    let normal = index(parts.next(), normals)?;
+    Ok(Corner { position, uv, normal })
+}
+
+/// Los primeros `N` números de la línea; los que sobran (como la `w` de los
+/// vértices) se ignoran.
+fn floats<const N: usize>(values: &[&str]) -> Result<[f32; N], String> {
+    if values.len() < N {
+        return Err(format!("se esperaban {} números", N));
+    }
+    let mut out = [0.0; N];
+    for (slot, text) in out.iter_mut().zip(values) {
+        *slot = text.parse().map_err(|_| format!("número inválido `{}`", text))?;
+    }
+    Ok(out)
+}
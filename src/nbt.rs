@@ -0,0 +1,342 @@
+use flate2::read::GzDecoder;
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::Path;
+
+/// Valor NBT, el formato binario de Minecraft para estructuras y esquemas.
+/// Se leen todos los tipos aunque los cargadores solo usen algunos.
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub enum Tag {
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    ByteArray(Vec<u8>),
+    String(String),
+    List(Vec<Tag>),
+    Compound(HashMap<String, Tag>),
+    IntArray(Vec<i32>),
+    LongArray(Vec<i64>),
+}
+
+impl Tag {
+    pub fn get(&self, key: &str) -> Option<&Tag> {
+        match self {
+            Tag::Compound(map) => map.get(key),
+            _ => None,
+        }
+    }
+
+    /// Entero de cualquier tamaño. Los `Short` de las dimensiones se leen sin
+    /// signo, como hace el juego.
+    pub fn as_i32(&self) -> Option<i32> {
+        match self {
+            Tag::Byte(v) => Some(*v as i32),
+            Tag::Short(v) => Some(*v as u16 as i32),
+            Tag::Int(v) => Some(*v),
+            Tag::Long(v) => i32::try_from(*v).ok(),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Tag::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Tag::ByteArray(bytes) => Some(bytes),
+            _ => None,
+        }
+    }
+
+    pub fn as_list(&self) -> Option<&[Tag]> {
+        match self {
+            Tag::List(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    pub fn as_compound(&self) -> Option<&HashMap<String, Tag>> {
+        match self {
+            Tag::Compound(map) => Some(map),
+            _ => None,
+        }
+    }
+
+    /// Lista de tres enteros (`size`, `pos`) como `List` o `IntArray`.
+    pub fn as_ivec3(&self) -> Option<[i32; 3]> {
+        let values: Vec<i32> = match self {
+            Tag::List(items) => items.iter().map(Tag::as_i32).collect::<Option<_>>()?,
+            Tag::IntArray(values) => values.clone(),
+            _ => return None,
+        };
+        <[i32; 3]>::try_from(values).ok()
+    }
+}
+
+/// Lee un archivo NBT, comprimido con gzip o sin comprimir, y devuelve el
+/// compuesto raíz.
+pub fn read_file(path: &Path) -> Result<Tag, String> {
+    let raw = std::fs::read(path).map_err(|e| format!("No se pudo leer {}: {}", path.display(), e))?;
+    let bytes = if raw.starts_with(&[0x1f, 0x8b]) {
+        let mut bytes = Vec::new();
+        GzDecoder::new(raw.as_slice())
+            .read_to_end(&mut bytes)
+            .map_err(|e| format!("{}: no se pudo descomprimir: {}", path.display(), e))?;
+        bytes
+    } else {
+        raw
+    };
+    parse(&bytes).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+fn parse(bytes: &[u8]) -> Result<Tag, String> {
+    let mut reader = Reader { bytes, pos: 0 };
+    if reader.u8()? != 10 {
+        return Err("no es un archivo NBT (la raíz debe ser un compuesto)".to_string());
+    }
+    let _root_name = reader.string()?;
+    reader.payload(10, 0)
+}
+
+/// Límite de anidamiento para no desbordar la pila con archivos corruptos.
+const MAX_DEPTH: u32 = 512;
+
+/// Lector big-endian de los bytes descomprimidos.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], String> {
+        if n > self.bytes.len() - self.pos {
+            return Err(format!("archivo truncado en el byte {}", self.pos));
+        }
+        let slice = &self.bytes[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(slice)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn i16(&mut self) -> Result<i16, String> {
+        let b = self.take(2)?;
+        Ok(i16::from_be_bytes([b[0], b[1]]))
+    }
+
+    fn i32(&mut self) -> Result<i32, String> {
+        let b = self.take(4)?;
+        Ok(i32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn i64(&mut self) -> Result<i64, String> {
+        let b = self.take(8)?;
+        Ok(i64::from_be_bytes(b.try_into().unwrap()))
+    }
+
+    fn len(&mut self) -> Result<usize, String> {
+        let value = self.i32()?;
+        usize::try_from(value).map_err(|_| format!("longitud negativa en el byte {}", self.pos - 4))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let len = self.i16()? as u16 as usize;
+        // Java usa UTF-8 modificado; para nombres de bloques basta la conversión con pérdida.
+        Ok(String::from_utf8_lossy(self.take(len)?).into_owned())
+    }
+
+    fn payload(&mut self, kind: u8, depth: u32) -> Result<Tag, String> {
+        if depth > MAX_DEPTH {
+            return Err("anidamiento demasiado profundo".to_string());
+        }
+        Ok(match kind {
+            1 => Tag::Byte(self.u8()? as i8),
+            2 => Tag::Short(self.i16()?),
+            3 => Tag::Int(self.i32()?),
+            4 => Tag::Long(self.i64()?),
+            5 => Tag::Float(f32::from_bits(self.i32()? as u32)),
+            6 => Tag::Double(f64::from_bits(self.i64()? as u64)),
+            7 => {
+                let len = self.len()?;
+                Tag::ByteArray(self.take(len)?.to_vec())
+            }
+            8 => Tag::String(self.string()?),
+            9 => {
+                let item_kind = self.u8()?;
+                let len = self.len()?;
+                let mut items = Vec::with_capacity(len.min(4096));
+                for _ in 0..len {
+                    items.push(self.payload(item_kind, depth + 1)?);
+                }
+                Tag::List(items)
+            }
+            10 => {
+                let mut map = HashMap::new();
+                loop {
+                    let child_kind = self.u8()?;
+                    if child_kind == 0 {
+                        break;
+                    }
+                    let name = self.string()?;
+                    map.insert(name, self.payload(child_kind, depth + 1)?);
+                }
+                Tag::Compound(map)
+            }
+            11 => {
+                let len = self.len()?;
+                let bytes = self.take(len.checked_mul(4).ok_or("longitud inválida")?)?;
+                Tag::IntArray(bytes.chunks_exact(4).map(|b| i32::from_be_bytes(b.try_into().unwrap())).collect())
+            }
+            12 => {
+                let len = self.len()?;
+                let bytes = self.take(len.checked_mul(8).ok_or("longitud inválida")?)?;
+                Tag::LongArray(bytes.chunks_exact(8).map(|b| i64::from_be_bytes(b.try_into().unwrap())).collect())
+            }
+            other => return Err(format!("tipo de etiqueta desconocido {} en el byte {}", other, self.pos - 1)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    fn name(s: &str) -> Vec<u8> {
+        let mut out = (s.len() as u16).to_be_bytes().to_vec();
+        out.extend_from_slice(s.as_bytes());
+        out
+    }
+
+    /// Etiqueta con nombre dentro de un compuesto.
+    fn named(kind: u8, key: &str, payload: &[u8]) -> Vec<u8> {
+        let mut out = vec![kind];
+        out.extend(name(key));
+        out.extend_from_slice(payload);
+        out
+    }
+
+    /// Compuesto raíz sin nombre con las etiquetas dadas.
+    fn root(children: &[Vec<u8>]) -> Vec<u8> {
+        let mut out = named(10, "", &children.concat());
+        out.push(0);
+        out
+    }
+
+    #[test]
+    fn reads_every_tag_type() {
+        let mut list = vec![3];
+        list.extend(2i32.to_be_bytes());
+        list.extend(7i32.to_be_bytes());
+        list.extend((-7i32).to_be_bytes());
+        let mut inner = named(8, "Name", &name("minecraft:stone"));
+        inner.push(0);
+        let mut byte_array = 3i32.to_be_bytes().to_vec();
+        byte_array.extend([1, 2, 255]);
+        let mut int_array = 2i32.to_be_bytes().to_vec();
+        int_array.extend(5i32.to_be_bytes());
+        int_array.extend((-1i32).to_be_bytes());
+        let mut long_array = 1i32.to_be_bytes().to_vec();
+        long_array.extend(i64::MIN.to_be_bytes());
+
+        let tag = parse(&root(&[
+            named(1, "byte", &[0xff]),
+            named(2, "short", &(-1i16).to_be_bytes()),
+            named(3, "int", &123456i32.to_be_bytes()),
+            named(4, "long", &(1i64 << 40).to_be_bytes()),
+            named(5, "float", &1.5f32.to_be_bytes()),
+            named(6, "double", &(-2.25f64).to_be_bytes()),
+            named(7, "bytes", &byte_array),
+            named(8, "string", &name("hola")),
+            named(9, "list", &list),
+            named(10, "compound", &inner),
+            named(11, "ints", &int_array),
+            named(12, "longs", &long_array),
+        ]))
+        .unwrap();
+
+        assert_eq!(tag.get("byte").and_then(Tag::as_i32), Some(-1));
+        // Los `Short` se leen sin signo, como las dimensiones de los esquemas.
+        assert_eq!(tag.get("short").and_then(Tag::as_i32), Some(65535));
+        assert_eq!(tag.get("int").and_then(Tag::as_i32), Some(123456));
+        assert!(matches!(tag.get("long"), Some(Tag::Long(v)) if *v == 1 << 40));
+        assert_eq!(tag.get("long").and_then(Tag::as_i32), None);
+        assert!(matches!(tag.get("float"), Some(Tag::Float(v)) if *v == 1.5));
+        assert!(matches!(tag.get("double"), Some(Tag::Double(v)) if *v == -2.25));
+        assert_eq!(tag.get("bytes").and_then(Tag::as_bytes), Some(&[1, 2, 255][..]));
+        assert_eq!(tag.get("string").and_then(Tag::as_str), Some("hola"));
+        assert_eq!(tag.get("list").and_then(Tag::as_list).map(<[Tag]>::len), Some(2));
+        assert_eq!(tag.get("compound").and_then(|c| c.get("Name")).and_then(Tag::as_str), Some("minecraft:stone"));
+        assert!(matches!(tag.get("ints"), Some(Tag::IntArray(v)) if *v == [5, -1]));
+        assert!(matches!(tag.get("longs"), Some(Tag::LongArray(v)) if *v == [i64::MIN]));
+    }
+
+    #[test]
+    fn reads_three_int_vectors() {
+        let mut list = vec![3];
+        list.extend(3i32.to_be_bytes());
+        for v in [1i32, -2, 3] {
+            list.extend(v.to_be_bytes());
+        }
+        let mut array = 3i32.to_be_bytes().to_vec();
+        for v in [4i32, 5, 6] {
+            array.extend(v.to_be_bytes());
+        }
+        let tag = parse(&root(&[named(9, "pos", &list), named(11, "size", &array)])).unwrap();
+        assert_eq!(tag.get("pos").and_then(Tag::as_ivec3), Some([1, -2, 3]));
+        assert_eq!(tag.get("size").and_then(Tag::as_ivec3), Some([4, 5, 6]));
+    }
+
+    #[test]
+    fn reads_gzip_files() {
+        let bytes = root(&[named(3, "Version", &2i32.to_be_bytes())]);
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&bytes).unwrap();
+        let path = std::env::temp_dir().join(format!("nbt-test-{}.nbt", std::process::id()));
+        std::fs::write(&path, encoder.finish().unwrap()).unwrap();
+
+        let tag = read_file(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(tag.unwrap().get("Version").and_then(Tag::as_i32), Some(2));
+    }
+
+    #[test]
+    fn malformed_data_is_an_error() {
+        let err = parse(&named(8, "", &name("x"))).unwrap_err();
+        assert!(err.contains("compuesto"), "{}", err);
+
+        let bytes = root(&[named(3, "int", &1i32.to_be_bytes())]);
+        let err = parse(&bytes[..bytes.len() - 3]).unwrap_err();
+        assert!(err.contains("truncado"), "{}", err);
+
+        let err = parse(&root(&[named(7, "bytes", &(-1i32).to_be_bytes())])).unwrap_err();
+        assert!(err.contains("longitud negativa"), "{}", err);
+
+        let err = parse(&root(&[named(11, "ints", &i32::MAX.to_be_bytes())])).unwrap_err();
+        assert!(err.contains("truncado"), "{}", err);
+
+        let err = parse(&root(&[named(13, "x", &[])])).unwrap_err();
+        assert!(err.contains("desconocido"), "{}", err);
+
+        // Listas de listas anidadas más allá del límite.
+        let mut nested = Vec::new();
+        for _ in 0..MAX_DEPTH + 8 {
+            nested.push(9);
+            nested.extend(1i32.to_be_bytes());
+        }
+        let err = parse(&root(&[named(9, "deep", &nested)])).unwrap_err();
+        assert!(err.contains("anidamiento"), "{}", err);
+    }
+}
@@ -0,0 +1,301 @@
+use raylib::prelude::*;
+use rayon::prelude::*;
+use std::f32::consts::PI;
+
+use crate::camera::{Camera, Ray};
+use crate::ray_intersect::Intersect;
+use crate::color::color_to_linear;
+use crate::framebuffer::Framebuffer;
+use crate::{
+    enhanced_skybox, fresnel_schlick, light_transmittance, reflect_vector,
+    refract_vector, SharedRenderState, MAX_INTERNAL_BOUNCES,
+};
+
+/// Rebotes a partir de los cuales se aplica ruleta rusa.
+const RUSSIAN_ROULETTE_DEPTH: u32 = 3;
+
+/// Generador PCG32: suficiente para muestreo y reproducible por pixel y frame.
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        let mut rng = Rng { state: 0 };
+        rng.next_u32();
+        rng.state = rng.state.wrapping_add(seed);
+        rng.next_u32();
+        rng
+    }
+
+    #[inline]
+    fn next_u32(&mut self) -> u32 {
+        let old = self.state;
+        self.state = old
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        let xorshifted = (((old >> 18) ^ old) >> 27) as u32;
+        let rot = (old >> 59) as u32;
+        xorshifted.rotate_right(rot)
+    }
+
+    /// Número uniforme en [0, 1).
+    #[inline]
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u32() >> 8) as f32 / (1u32 << 24) as f32
+    }
+}
+
+/// Suma de las muestras de cada pixel a lo largo de los frames. Se reinicia
+/// cuando cambia la cámara o la escena.
+pub struct Accumulator {
+    sum: Framebuffer,
+    samples: u32,
+}
+
+impl Accumulator {
+    pub fn new(width: i32, height: i32) -> Self {
+        Accumulator {
+            sum: Framebuffer::new(width, height),
+            samples: 0,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.sum.pixels_mut().iter_mut().for_each(|s| *s = [0.0; 4]);
+        self.samples = 0;
+    }
+
+    pub fn samples(&self) -> u32 {
+        self.samples
+    }
+
+    pub fn add_frame(&mut self, frame: &Framebuffer) {
+        for (sum, sample) in self.sum.pixels_mut().iter_mut().zip(frame.pixels()) {
+            for (total, value) in sum.iter_mut().zip(sample) {
+                *total += value;
+            }
+        }
+        self.samples += 1;
+    }
+
+    /// Radiancia y cobertura promedio de cada pixel.
+    pub fn resolve(&self) -> Framebuffer {
+        let inv = 1.0 / self.samples.max(1) as f32;
+        let pixels = self.sum.pixels().par_iter().map(|sum| sum.map(|c| c * inv)).collect();
+        Framebuffer::from_pixels(self.sum.width(), self.sum.height(), pixels)
+            .expect("el acumulador tiene el tamaño del frame")
+    }
+}
+
+/// Traza una muestra por pixel con subpixel aleatorio. `frame_index` cambia la
+/// semilla para que cada frame aporte muestras nuevas.
+pub fn render_path_traced(
+    camera: &Camera,
+    width: i32,
+    height: i32,
+    state: &SharedRenderState,
+    frame_index: u32,
+) -> Framebuffer {
+    let pixels = (0..width * height)
+        .into_par_iter()
+        .map(|index| {
+            let x = index % width;
+            let y = index / width;
+            let seed = (index as u64) << 32 | frame_index as u64;
+            let mut rng = Rng::new(seed.wrapping_mul(0x9E3779B97F4A7C15));
+
+            let offset = (rng.next_f32(), rng.next_f32());
+            // Sin lente no se gastan números: la secuencia sigue igual.
+            let lens = if camera.has_lens() { (rng.next_f32(), rng.next_f32()) } else { (0.5, 0.5) };
+            let ray = camera.get_ray_lens(x, y, offset, lens, width, height);
+            let (radiance, hit) = trace_path(ray, state, &mut rng);
+            let alpha = if hit || state.skybox_enabled { 1.0 } else { 0.0 };
+            [radiance.x, radiance.y, radiance.z, alpha]
+        })
+        .collect();
+    Framebuffer::from_pixels(width, height, pixels).expect("un pixel por cada posición de la imagen")
+}
+
+/// Radiancia del cielo, la misma que ve el modo Whitted.
+fn sky_radiance(dir: Vector3, state: &SharedRenderState) -> Vector3 {
+    if state.skybox_enabled {
+        enhanced_skybox(dir)
+    } else {
+        Vector3::zero()
+    }
+}
+
+/// Luz directa del sol y de las luces puntuales/focos reflejada por una
+/// superficie lambertiana (BRDF `albedo / π`), en la misma escala que los
+/// rebotes indirectos. Los voxels emisivos no se muestrean aquí: los caminos
+/// los encuentran al rebotar.
+fn direct_lighting(hit: &Intersect, albedo: Vector3, state: &SharedRenderState) -> Vector3 {
+    let shadow_origin = hit.point + hit.normal * 0.0001;
+    let mut total = Vector3::zero();
+
+    let sun = &state.sun;
+    let sun_ndotl = hit.normal.dot(-sun.dir);
+    if sun_ndotl > 0.0 {
+        let visibility = light_transmittance(&state.world, shadow_origin, -sun.dir, state.settings.max_ray_dist);
+        let sun_color = color_to_linear(sun.color) * sun.intensity;
+        total += sun_color * (sun_ndotl * visibility);
+    }
+
+    for light in &state.lights {
+        let to_light = light.position - hit.point;
+        let distance = to_light.length();
+        if distance < 1e-4 || distance > light.range() {
+            continue;
+        }
+        let light_dir = to_light / distance;
+        let ndotl = hit.normal.dot(light_dir);
+        if ndotl <= 0.0 {
+            continue;
+        }
+        let cone = light.cone_factor(-light_dir);
+        if cone <= 0.0 {
+            continue;
+        }
+        let visibility = light_transmittance(&state.world, shadow_origin, light_dir, distance);
+        total += light.color_vector() * (ndotl * cone * visibility * light.attenuation(distance));
+    }
+
+    albedo * total / PI
+}
+
+/// Base ortonormal alrededor de `n` para muestrear el hemisferio.
+fn orthonormal_basis(n: Vector3) -> (Vector3, Vector3) {
+    let helper = if n.x.abs() > 0.9 {
+        Vector3::new(0.0, 1.0, 0.0)
+    } else {
+        Vector3::new(1.0, 0.0, 0.0)
+    };
+    let tangent = n.cross(helper).normalized();
+    let bitangent = n.cross(tangent);
+    (tangent, bitangent)
+}
+
+/// Dirección con distribución coseno sobre el hemisferio de `n`.
+fn sample_cosine_hemisphere(n: Vector3, rng: &mut Rng) -> Vector3 {
+    let r1 = rng.next_f32();
+    let r2 = rng.next_f32();
+    let phi = 2.0 * PI * r1;
+    let r = r2.sqrt();
+    let (t, b) = orthonormal_basis(n);
+    (t * (r * phi.cos()) + b * (r * phi.sin()) + n * (1.0 - r2).sqrt()).normalized()
+}
+
+/// Reflejo especular perturbado según la rugosidad (lóbulo brillante).
+fn sample_glossy(incident: Vector3, n: Vector3, roughness: f32, rng: &mut Rng) -> Vector3 {
+    let mirror = reflect_vector(incident, n);
+    let jitter = sample_cosine_hemisphere(mirror, rng);
+    let dir = (mirror + (jitter - mirror) * (roughness * roughness)).normalized();
+    if dir.dot(n) > 0.0 { dir } else { mirror }
+}
+
+/// Sigue el rayo refractado a través del medio transparente y devuelve el rayo
+/// de salida. Con reflexión interna total rebota dentro del bloque.
+fn refract_through(ray: &Ray, hit: &Intersect, state: &SharedRenderState) -> Option<Ray> {
+    let ior = state.world.material(hit.material).refractive_index.max(1.0);
+    let mut direction = refract_vector(ray.direction, hit.normal, 1.0 / ior)?;
+    let mut origin = hit.point - hit.normal * 0.0001;
+
+    for _ in 0..MAX_INTERNAL_BOUNCES {
+        let exit = state.world.exit_medium(&origin, &direction);
+        if !exit.is_intersecting {
+            return None;
+        }
+        match refract_vector(direction, -exit.normal, ior) {
+            Some(out_dir) => {
+                return Some(Ray {
+                    origin: exit.point + exit.normal * 0.0001,
+                    direction: out_dir,
+                });
+            }
+            None => {
+                direction = reflect_vector(direction, -exit.normal);
+                origin = exit.point - exit.normal * 0.0001;
+            }
+        }
+    }
+    None
+}
+
+/// Integra la radiancia que llega por `ray` con rebotes difusos, brillantes y
+/// de transmisión. El resultado es lineal y sin limitar; el segundo valor dice
+/// si el rayo inicial chocó con la escena.
+pub fn trace_path(mut ray: Ray, state: &SharedRenderState, rng: &mut Rng) -> (Vector3, bool) {
+    let mut radiance = Vector3::zero();
+    let mut throughput = Vector3::new(1.0, 1.0, 1.0);
+    let mut primary_hit = false;
+
+    for bounce in 0..state.settings.max_bounces {
+        let hit = state.world.intersect_ray(&ray.origin, &ray.direction, state.settings.max_ray_dist);
+        if !hit.is_intersecting {
+            radiance += throughput * sky_radiance(ray.direction, state);
+            break;
+        }
+        primary_hit |= bounce == 0;
+
+        let mat = state.world.material(hit.material);
+        let albedo = mat.get_diffuse_color_sharp(hit.u, hit.v, &hit.normal, &hit.state);
+
+        if mat.emission > 0.0 {
+            radiance += throughput * (mat.get_emission_color(hit.u, hit.v, &hit.normal, &hit.state) * mat.emission);
+        }
+
+        let transmission = mat.transmission();
+        // Mismo peso del reflejo que usa `cast_ray_recursive` al mezclar.
+        let reflection = if mat.metallic > 0.5 { mat.albedo[2] * 0.5 } else { mat.albedo[2] * 0.4 };
+        let choice = rng.next_f32();
+
+        if choice < transmission {
+            // Fresnel decide entre reflejar y atravesar el medio.
+            let ior = mat.refractive_index.max(1.0);
+            let cos_i = (-ray.direction.dot(hit.normal)).clamp(0.0, 1.0);
+            let fresnel = fresnel_schlick(cos_i, 1.0, ior);
+            let refracted = if rng.next_f32() >= fresnel {
+                refract_through(&ray, &hit, state)
+            } else {
+                None
+            };
+            ray = match refracted {
+                Some(out_ray) => {
+                    throughput *= albedo;
+                    out_ray
+                }
+                None => Ray {
+                    origin: hit.point + hit.normal * 0.0001,
+                    direction: reflect_vector(ray.direction, hit.normal),
+                },
+            };
+        } else if choice < transmission + (1.0 - transmission) * reflection {
+            // Lóbulo brillante: los metales tiñen el reflejo con su color.
+            if mat.metallic > 0.5 {
+                throughput *= albedo;
+            }
+            ray = Ray {
+                origin: hit.point + hit.normal * 0.0001,
+                direction: sample_glossy(ray.direction, hit.normal, mat.roughness, rng),
+            };
+        } else {
+            radiance += throughput * direct_lighting(&hit, albedo, state);
+            throughput *= albedo;
+            ray = Ray {
+                origin: hit.point + hit.normal * 0.0001,
+                direction: sample_cosine_hemisphere(hit.normal, rng),
+            };
+        }
+
+        if bounce >= RUSSIAN_ROULETTE_DEPTH {
+            let survive = throughput.x.max(throughput.y).max(throughput.z).clamp(0.05, 0.95);
+            if rng.next_f32() > survive {
+                break;
+            }
+            throughput /= survive;
+        }
+    }
+
+    (radiance, primary_hit)
+}
@@ -1,11 +1,22 @@
-use crate::material::Material;
+use crate::bvh::Aabb;
+use crate::material::MaterialId;
 use crate::ray_intersect::{Intersect, RayIntersect};
 use raylib::prelude::Vector3;
 
 pub struct Plane {
     pub point: Vector3,   
     pub normal: Vector3,  
-    pub material: Material,
+    pub material: MaterialId,
+}
+
+impl Plane {
+    /// Dos ejes del plano, alineados con la grilla cuando el plano también lo está.
+    fn tangents(&self) -> (Vector3, Vector3) {
+        let n = self.normal;
+        let helper = if n.y.abs() > 0.9 { Vector3::new(0.0, 0.0, 1.0) } else { Vector3::new(0.0, 1.0, 0.0) };
+        let tangent = helper.cross(n).normalized();
+        (tangent, n.cross(tangent))
+    }
 }
 
 impl RayIntersect for Plane {
@@ -24,7 +35,16 @@ impl RayIntersect for Plane {
         
         let point = *ray_origin + *ray_direction * t;
         let normal = if denom < 0.0 { self.normal } else { -self.normal };
-        
-        Intersect::new(point, normal, t, self.material.clone())
+
+        // La textura se repite cada bloque, como si el plano fuera un suelo de voxels.
+        let (tangent, bitangent) = self.tangents();
+        let u = point.dot(tangent).rem_euclid(1.0);
+        let v = point.dot(bitangent).rem_euclid(1.0);
+
+        Intersect::new_with_uv(point, normal, t, self.material, u, v)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        None
     }
 }
\ No newline at end of file
@@ -1,5 +1,7 @@
 use raylib::prelude::Vector3;
-use crate::material::Material;
+use crate::bvh::Aabb;
+use crate::material::MaterialId;
+use crate::shape::VoxelState;
 
 #[derive(Debug, Clone)] 
 #[allow(dead_code)]
@@ -8,25 +10,16 @@ pub struct Intersect {
     pub normal: Vector3,
     pub distance: f32,
     pub is_intersecting: bool,
-    pub material: Material,
+    /// Material alcanzado, en la paleta de la escena.
+    pub material: MaterialId,
     pub u: f32,
     pub v: f32,
+    /// Estado del voxel alcanzado; decide qué cara es el frente o la tapa.
+    pub state: VoxelState,
 }
 
 impl Intersect {
-    pub fn new(point: Vector3, normal: Vector3, distance: f32, material: Material) -> Self {
-        Intersect {
-            point,
-            normal,
-            distance,
-            is_intersecting: true,
-            material,
-            u: 0.0,
-            v: 0.0,
-        }
-    }
-
-    pub fn new_with_uv(point: Vector3, normal: Vector3, distance: f32, material: Material, u: f32, v: f32) -> Self {
+    pub fn new_with_uv(point: Vector3, normal: Vector3, distance: f32, material: MaterialId, u: f32, v: f32) -> Self {
         Intersect {
             point,
             normal,
@@ -35,22 +28,49 @@ impl Intersect {
             material,
             u,
             v,
+            state: VoxelState::default(),
         }
     }
 
+    pub fn with_state(mut self, state: VoxelState) -> Self {
+        self.state = state;
+        self
+    }
+
     pub fn empty() -> Self {
         Intersect {
             point: Vector3::zero(),
             normal: Vector3::zero(),
             distance: 0.0,
             is_intersecting: false,
-            material: Material::black(),
+            material: MaterialId::default(),
             u: 0.0,
             v: 0.0,
+            state: VoxelState::default(),
         }
     }
 }
 
-pub trait RayIntersect {
+/// Objeto que se puede trazar junto a la grilla. Se comparte entre los hilos
+/// del render, por eso tiene que ser `Send + Sync`.
+pub trait RayIntersect: Send + Sync {
     fn ray_intersect(&self, ray_origin: &Vector3, ray_direction: &Vector3) -> Intersect;
-}
\ No newline at end of file
+
+    /// Caja que envuelve el objeto, para la BVH. `None` si no tiene límites,
+    /// como un plano infinito.
+    fn bounding_box(&self) -> Option<Aabb>;
+
+    /// Como `ray_intersect`, pero sin interés en lo que esté más allá de
+    /// `t_max`. Las instancias de modelos lo usan para cortar su recorrido.
+    fn ray_intersect_before(&self, ray_origin: &Vector3, ray_direction: &Vector3, t_max: f32) -> Intersect {
+        let _ = t_max;
+        self.ray_intersect(ray_origin, ray_direction)
+    }
+
+    /// Salida del rayo cuando empieza dentro del objeto, para la refracción.
+    /// En una superficie cerrada es el impacto de siempre; los objetos con
+    /// medios propios, como las instancias de modelos, la buscan a su manera.
+    fn exit_medium(&self, ray_origin: &Vector3, ray_direction: &Vector3) -> Intersect {
+        self.ray_intersect(ray_origin, ray_direction)
+    }
+}
@@ -0,0 +1,992 @@
+use raylib::prelude::{Color, Vector3};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::num::NonZeroU32;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::antialias::Filter;
+use crate::camera::{Bokeh, Projection};
+use crate::color::ToneMapper;
+use crate::cli::Integrator;
+use crate::light::{Light, DEFAULT_LIGHT_INTENSITY, SPOT_INNER_FRACTION};
+use crate::cube::Cube;
+use crate::instance::{Transform, VoxelInstance, VoxelModel};
+use crate::material::{Material, MaterialType};
+use crate::mesh;
+use crate::block_mapping::{BlockMapping, BlockState};
+use crate::material_library::MaterialLibrary;
+use crate::plane::Plane;
+use crate::ray_intersect::RayIntersect;
+use crate::shape::VoxelState;
+use crate::sphere::Sphere;
+use crate::voxel::{Voxel, VoxelGrid};
+use crate::world::World;
+use crate::{schematic, vox};
+
+/// Errores de capas que se muestran antes de cortar el listado.
+const MAX_REPORTED_ERRORS: usize = 20;
+const DEFAULT_EMISSIVE_STRENGTH: f32 = 4.0;
+
+/// Paleta de las capas originales del diorama; se usa cuando la escena no
+/// define la suya.
+const DEFAULT_PALETTE: [(char, Option<MaterialType>); 18] = [
+    ('M', Some(MaterialType::Dirt)),
+    ('T', Some(MaterialType::Grass)),
+    ('P', Some(MaterialType::Netherrack)),
+    ('R', Some(MaterialType::Stone)),
+    ('L', Some(MaterialType::Magma)),
+    ('O', Some(MaterialType::Gold)),
+    ('B', Some(MaterialType::Obsidian)),
+    ('S', Some(MaterialType::StoneStairs)),
+    ('Z', Some(MaterialType::StoneSlab)),
+    ('J', Some(MaterialType::StonePillar)),
+    ('C', Some(MaterialType::WoodChest)),
+    ('W', Some(MaterialType::GlowingObsidian)),
+    ('G', Some(MaterialType::Glass)),
+    ('A', Some(MaterialType::Water)),
+    ('H', Some(MaterialType::Ice)),
+    ('X', Some(MaterialType::StainedGlass)),
+    ('V', None),
+    ('.', None),
+];
+
+/// Sol y cielo de la escena.
+#[derive(Debug, Clone, Copy)]
+pub struct Sky {
+    pub enabled: bool,
+    pub sun_direction: Vector3,
+    pub sun_color: Color,
+    pub sun_intensity: f32,
+}
+
+impl Default for Sky {
+    fn default() -> Self {
+        Sky {
+            enabled: true,
+            sun_direction: Vector3::new(-0.6, -0.8, -0.4).normalized(),
+            sun_color: Color::WHITE,
+            sun_intensity: 1.2,
+        }
+    }
+}
+
+/// Material y estado que pone un carácter de las capas.
+#[derive(Debug, Clone)]
+pub struct PaletteEntry {
+    pub material: Material,
+    pub state: VoxelState,
+}
+
+/// Primitiva analítica que se traza junto a la grilla, con su material ya
+/// resuelto.
+#[derive(Debug, Clone)]
+pub struct SceneObject {
+    pub shape: ObjectShape,
+    pub material: Material,
+}
+
+#[derive(Debug, Clone)]
+pub enum ObjectShape {
+    Sphere { center: Vector3, radius: f32 },
+    /// Plano infinito; `normal` está normalizada.
+    Plane { point: Vector3, normal: Vector3 },
+    /// Cubo alineado a los ejes de lado `size`.
+    Cube { center: Vector3, size: f32 },
+    /// Malla `.obj` escalada por `scale` y desplazada a `position`.
+    Mesh { path: PathBuf, position: Vector3, scale: f32 },
+}
+
+/// Modelo importado de otro formato (MagicaVoxel `.vox` o estructura de
+/// Minecraft). `offset` es la posición de su esquina mínima en la grilla.
+#[derive(Debug, Clone)]
+pub struct ModelSource {
+    pub path: PathBuf,
+    pub offset: [i32; 3],
+}
+
+/// Copia de un modelo colocada en la escena. `path` es un modelo `.vox` o
+/// de Minecraft, o un directorio de capas que usa la paleta de la escena;
+/// cada archivo se carga una sola vez aunque se coloque muchas veces.
+#[derive(Debug, Clone)]
+pub struct InstanceSource {
+    pub path: PathBuf,
+    /// Esquina mínima de la caja del modelo ya girado.
+    pub position: Vector3,
+    /// Grados alrededor de X, Y y Z, aplicados en ese orden.
+    pub rotation: [f32; 3],
+    /// Ejes locales reflejados antes de girar.
+    pub mirror: [bool; 3],
+}
+
+#[derive(Debug, Clone)]
+pub struct CameraPreset {
+    pub name: String,
+    pub eye: Vector3,
+    pub target: Vector3,
+    pub fov: Option<f32>,
+    pub projection: Option<Projection>,
+    pub aperture: Option<f32>,
+    pub focus_distance: Option<f32>,
+    pub bokeh: Option<Bokeh>,
+}
+
+/// Ajustes de render que define la escena. Los que faltan toman el valor de la
+/// línea de comandos o el valor por defecto del programa.
+#[derive(Debug, Clone, Default)]
+pub struct RenderOverrides {
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    pub max_dist: Option<f32>,
+    pub max_reflections: Option<u32>,
+    pub integrator: Option<Integrator>,
+    pub samples: Option<u32>,
+    pub max_bounces: Option<u32>,
+    pub aa: Option<u32>,
+    pub filter: Option<Filter>,
+    pub adaptive: Option<bool>,
+    pub tonemap: Option<ToneMapper>,
+    pub exposure: Option<f32>,
+    pub emissive_strength: Option<f32>,
+}
+
+/// Escena lista para construir la grilla: capas, paleta, luces, cielo,
+/// cámaras predefinidas y ajustes de render.
+#[derive(Debug, Clone)]
+pub struct Scene {
+    pub source: Option<PathBuf>,
+    pub layers: Vec<PathBuf>,
+    pub base_y: i32,
+    pub models: Vec<ModelSource>,
+    pub instances: Vec<InstanceSource>,
+    /// Biblioteca con la que se resolvieron la paleta y los bloques; la línea
+    /// de comandos la usa para los archivos de `--blocks`.
+    pub materials: MaterialLibrary,
+    pub blocks: BlockMapping,
+    /// Carácter de las capas -> material y estado del voxel; `None` es aire.
+    pub palette: HashMap<char, Option<PaletteEntry>>,
+    pub objects: Vec<SceneObject>,
+    pub lights: Vec<Light>,
+    pub sky: Sky,
+    pub cameras: Vec<CameraPreset>,
+    pub default_camera: Option<usize>,
+    /// Proyección de las cámaras que no indican la suya.
+    pub projection: Option<Projection>,
+    pub render: RenderOverrides,
+}
+
+impl Default for Scene {
+    fn default() -> Self {
+        Scene {
+            source: None,
+            layers: Vec::new(),
+            base_y: 0,
+            models: Vec::new(),
+            instances: Vec::new(),
+            materials: MaterialLibrary::default(),
+            blocks: BlockMapping::with_defaults(&MaterialLibrary::default()),
+            palette: default_palette(&MaterialLibrary::default()),
+            objects: Vec::new(),
+            lights: Vec::new(),
+            sky: Sky::default(),
+            cameras: Vec::new(),
+            default_camera: None,
+            projection: None,
+            render: RenderOverrides::default(),
+        }
+    }
+}
+
+impl Scene {
+    /// Escena equivalente al diorama original: todas las capas `.txt` del
+    /// directorio con la paleta por defecto.
+    pub fn from_layers_dir(dir: &Path) -> Scene {
+        let layers = match list_layer_files(dir) {
+            Ok(layers) => layers,
+            Err(e) => {
+                println!("⚠ {}", e);
+                Vec::new()
+            }
+        };
+        Scene { layers, ..Scene::default() }
+    }
+
+    /// Carga una escena TOML o JSON (según la extensión). Los errores de
+    /// sintaxis y de valores indican línea y columna.
+    pub fn load(path: &Path) -> Result<Scene, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("No se pudo leer {}: {}", path.display(), e))?;
+
+        let is_json = path
+            .extension()
+            .map(|ext| ext.eq_ignore_ascii_case("json"))
+            .unwrap_or(false);
+        let file: SceneFile = if is_json {
+            serde_json::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))?
+        } else {
+            toml::from_str(&text).map_err(|e| format!("{}:\n{}", path.display(), e))?
+        };
+
+        let base_dir = path.parent().unwrap_or(Path::new("."));
+        Scene::from_file(file, base_dir)
+            .map(|mut scene| {
+                scene.source = Some(path.to_path_buf());
+                scene
+            })
+            .map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    fn from_file(file: SceneFile, base_dir: &Path) -> Result<Scene, String> {
+        if file.layers.is_none() && file.models.is_empty() && file.instances.is_empty() {
+            return Err("la escena debe tener [layers], [[models]] o [[instances]]".to_string());
+        }
+        let (layers, base_y) = match file.layers {
+            Some(layers_def) => {
+                let layers = match (layers_def.directory, layers_def.files) {
+                    (Some(dir), None) => list_layer_files(&base_dir.join(dir))?,
+                    (None, Some(files)) => files.into_iter().map(|f| base_dir.join(f)).collect(),
+                    _ => return Err("[layers] debe indicar `directory` o `files`, pero no ambos".to_string()),
+                };
+                (layers, layers_def.base_y)
+            }
+            None => (Vec::new(), 0),
+        };
+        let models = file
+            .models
+            .into_iter()
+            .map(|m| ModelSource { path: base_dir.join(m.path), offset: m.offset })
+            .collect();
+        let instances = file
+            .instances
+            .into_iter()
+            .map(|i| {
+                let mut mirror = [false; 3];
+                for axis in i.mirror {
+                    mirror[axis as usize] = true;
+                }
+                InstanceSource { path: base_dir.join(i.path), position: i.position.0, rotation: i.rotation, mirror }
+            })
+            .collect();
+
+        let mut materials = MaterialLibrary::default();
+        for library in &file.materials {
+            materials.load(&base_dir.join(library))?;
+        }
+
+        let mut blocks = BlockMapping::with_defaults(&materials);
+        for mapping in &file.blocks {
+            blocks.load(&base_dir.join(mapping), &materials)?;
+        }
+
+        let palette = match file.palette {
+            Some(entries) => {
+                let mut palette = HashMap::with_capacity(entries.len());
+                for (key, value) in entries {
+                    let voxel = palette_voxel(&value, &materials)
+                        .map_err(|e| format!("[palette] `{}`: {}", key.0, e))?;
+                    palette.insert(key.0, voxel);
+                }
+                palette
+            }
+            None => default_palette(&materials),
+        };
+
+        let mut objects = Vec::with_capacity(file.objects.len());
+        for (index, object) in file.objects.into_iter().enumerate() {
+            let object = object
+                .resolve(&materials, base_dir)
+                .map_err(|e| format!("[[objects]] #{}: {}", index + 1, e))?;
+            objects.push(object);
+        }
+
+        let lights = file.lights.into_iter().map(LightDef::into_light).collect();
+
+        let mut cameras: Vec<CameraPreset> = Vec::with_capacity(file.camera.presets.len());
+        for preset in file.camera.presets {
+            if cameras.iter().any(|c| c.name == preset.name) {
+                return Err(format!("la cámara `{}` está definida dos veces", preset.name));
+            }
+            cameras.push(CameraPreset {
+                name: preset.name,
+                eye: preset.eye.0,
+                target: preset.target.0,
+                fov: preset.fov.map(|f| f.0),
+                projection: preset.projection,
+                aperture: preset.aperture.map(|a| a.0),
+                focus_distance: preset.focus_distance.map(|f| f.0),
+                bokeh: preset.bokeh,
+            });
+        }
+        let default_camera = match &file.camera.default {
+            Some(name) => Some(
+                cameras
+                    .iter()
+                    .position(|c| &c.name == name)
+                    .ok_or_else(|| format!("[camera] default: no existe la cámara `{}`", name))?,
+            ),
+            None => None,
+        };
+
+        let sky = Sky {
+            enabled: file.sky.enabled,
+            sun_direction: file.sky.sun_direction.0,
+            sun_color: file.sky.sun_color.0,
+            sun_intensity: file.sky.sun_intensity.0,
+        };
+
+        let render = file.render;
+        let render = RenderOverrides {
+            width: render.width.map(to_i32),
+            height: render.height.map(to_i32),
+            max_dist: render.max_dist.map(|v| v.0),
+            max_reflections: render.max_reflections,
+            integrator: render.integrator,
+            samples: render.samples.map(NonZeroU32::get),
+            max_bounces: render.max_bounces.map(NonZeroU32::get),
+            aa: render.aa.map(NonZeroU32::get),
+            filter: render.filter,
+            adaptive: render.adaptive,
+            tonemap: render.tonemap,
+            exposure: render.exposure,
+            emissive_strength: render.emissive_strength.map(|v| v.0),
+        };
+
+        Ok(Scene {
+            source: None,
+            layers,
+            base_y,
+            models,
+            instances,
+            materials,
+            blocks,
+            palette,
+            objects,
+            lights,
+            sky,
+            cameras,
+            default_camera,
+            projection: file.camera.projection,
+            render,
+        })
+    }
+
+    pub fn emissive_strength(&self) -> f32 {
+        self.render.emissive_strength.unwrap_or(DEFAULT_EMISSIVE_STRENGTH)
+    }
+
+    pub fn camera(&self, name: &str) -> Result<&CameraPreset, String> {
+        self.cameras.iter().find(|c| c.name == name).ok_or_else(|| {
+            let names: Vec<&str> = self.cameras.iter().map(|c| c.name.as_str()).collect();
+            if names.is_empty() {
+                format!("la escena no define la cámara `{}`", name)
+            } else {
+                format!("no existe la cámara `{}` (disponibles: {})", name, names.join(", "))
+            }
+        })
+    }
+
+    /// La grilla más los objetos de la escena, listos para trazar.
+    pub fn build_world(&self) -> Result<World, String> {
+        let mut grid = self.build_grid()?;
+        let mut objects: Vec<Box<dyn RayIntersect>> = Vec::new();
+        for object in &self.objects {
+            let material = grid.intern_material(object.material.clone())?;
+            match object.shape {
+                ObjectShape::Sphere { center, radius } => objects.push(Box::new(Sphere { center, radius, material })),
+                ObjectShape::Plane { point, normal } => objects.push(Box::new(Plane { point, normal, material })),
+                ObjectShape::Cube { center, size } => objects.push(Box::new(Cube { center, size, material })),
+                ObjectShape::Mesh { ref path, position, scale } => {
+                    let triangles = mesh::load_obj(path, position, scale, material)?;
+                    println!("✓ Malla {}: {} triángulos", path.display(), triangles.len());
+                    objects.extend(triangles.into_iter().map(|t| Box::new(t) as Box<dyn RayIntersect>));
+                }
+            }
+        }
+
+        // Cada modelo se carga una vez y todas sus instancias lo comparten.
+        let mut models: HashMap<&Path, Arc<VoxelModel>> = HashMap::new();
+        let mut instances = Vec::with_capacity(self.instances.len());
+        for instance in &self.instances {
+            let model = match models.get(instance.path.as_path()) {
+                Some(model) => Arc::clone(model),
+                None => {
+                    let model = Arc::new(VoxelModel::new(self.load_instance_model(&instance.path)?, &mut grid)?);
+                    models.insert(&instance.path, Arc::clone(&model));
+                    model
+                }
+            };
+            let (min, max) = model.grid.cell_bounds();
+            let transform = Transform::rotation(instance.rotation, instance.mirror).placed(min, max, instance.position);
+            instances.push(VoxelInstance::new(model, transform));
+        }
+        if !instances.is_empty() {
+            println!("✓ Instancias: {} de {} modelos", instances.len(), models.len());
+        }
+
+        let world = World::new(grid, objects, instances);
+        if !world.objects.is_empty() {
+            println!("✓ Objetos: {} primitivas en la BVH", world.objects.len());
+        }
+        Ok(world)
+    }
+
+    /// Lee las capas y crea la grilla. Cada carácter fuera de la paleta se
+    /// informa como `archivo:línea:columna`; los espacios cuentan como aire.
+    pub fn build_grid(&self) -> Result<VoxelGrid, String> {
+        let mut grid = VoxelGrid::new();
+
+        if self.layers.is_empty() && self.models.is_empty() && self.instances.is_empty() {
+            println!("⚠ No hay archivos de capas, creando capa de prueba");
+            insert_test_layer(&mut grid)?;
+            return Ok(grid);
+        }
+
+        let mut errors = Vec::new();
+        self.insert_layers(&self.layers, self.base_y, &mut grid, &mut errors)?;
+
+        for model in &self.models {
+            let count = load_model(model, &mut grid, &self.blocks)?;
+            println!("✓ Modelo {}: {} voxels", model.path.display(), count);
+        }
+
+        report_errors(&errors)?;
+
+        println!("✓ Grilla creada con {} voxels", grid.len());
+        Ok(grid)
+    }
+
+    /// Pone las capas en `grid`, la primera a la altura `base_y`. Los
+    /// caracteres que no están en la paleta se anotan en `errors`.
+    fn insert_layers(
+        &self,
+        layers: &[PathBuf],
+        base_y: i32,
+        grid: &mut VoxelGrid,
+        errors: &mut Vec<String>,
+    ) -> Result<(), String> {
+        // Cada carácter se resuelve una vez al material de la paleta de la grilla.
+        let voxels: HashMap<char, Option<Voxel>> = self
+            .palette
+            .iter()
+            .map(|(c, entry)| {
+                let voxel = entry
+                    .as_ref()
+                    .map(|e| grid.intern_material(e.material.clone()).map(|material| Voxel { material, state: e.state }))
+                    .transpose()?;
+                Ok((*c, voxel))
+            })
+            .collect::<Result<_, String>>()?;
+
+        for (index, path) in layers.iter().enumerate() {
+            let content = std::fs::read_to_string(path)
+                .map_err(|e| format!("No se pudo leer {}: {}", path.display(), e))?;
+            let y = base_y + index as i32;
+            println!("✓ Cargando {}: y={}", path.display(), y);
+
+            for (z, line) in content.lines().enumerate() {
+                for (x, ch) in line.chars().enumerate() {
+                    if ch == ' ' {
+                        continue;
+                    }
+                    match voxels.get(&ch) {
+                        Some(Some(voxel)) => grid.insert_voxel(x as i32, y, z as i32, *voxel)?,
+                        Some(None) => {}
+                        None => errors.push(format!(
+                            "{}:{}:{}: el carácter `{}` no está en la paleta",
+                            path.display(), z + 1, x + 1, ch
+                        )),
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Grilla propia de un modelo de `[[instances]]`: un directorio de capas
+    /// con la paleta de la escena o un archivo de modelo.
+    fn load_instance_model(&self, path: &Path) -> Result<VoxelGrid, String> {
+        let mut grid = VoxelGrid::new();
+        if path.is_dir() {
+            let layers = list_layer_files(path)?;
+            let mut errors = Vec::new();
+            self.insert_layers(&layers, 0, &mut grid, &mut errors)?;
+            report_errors(&errors)?;
+        } else {
+            load_model(&ModelSource { path: path.to_path_buf(), offset: [0; 3] }, &mut grid, &self.blocks)?;
+        }
+        if grid.is_empty() {
+            return Err(format!("{}: el modelo no tiene voxels", path.display()));
+        }
+        println!("✓ Modelo para instancias {}: {} voxels", path.display(), grid.len());
+        Ok(grid)
+    }
+}
+
+/// Junta los errores de las capas en un solo mensaje, cortando el listado
+/// después de `MAX_REPORTED_ERRORS`.
+fn report_errors(errors: &[String]) -> Result<(), String> {
+    if errors.is_empty() {
+        return Ok(());
+    }
+    let mut message = errors
+        .iter()
+        .take(MAX_REPORTED_ERRORS)
+        .cloned()
+        .collect::<Vec<_>>()
+        .join("\n");
+    if errors.len() > MAX_REPORTED_ERRORS {
+        message.push_str(&format!("\n... y {} errores más", errors.len() - MAX_REPORTED_ERRORS));
+    }
+    Err(message)
+}
+
+fn load_model(model: &ModelSource, grid: &mut VoxelGrid, blocks: &BlockMapping) -> Result<usize, String> {
+    let extension = model
+        .path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default();
+    match extension.as_str() {
+        "vox" => vox::load_vox(&model.path, grid, model.offset),
+        "schem" | "schematic" | "nbt" => schematic::load_schematic(&model.path, grid, model.offset, blocks),
+        _ => Err(format!("{}: formato de modelo no soportado", model.path.display())),
+    }
+}
+
+fn default_palette(materials: &MaterialLibrary) -> HashMap<char, Option<PaletteEntry>> {
+    DEFAULT_PALETTE
+        .iter()
+        .map(|(c, material)| {
+            let voxel = material
+                .and_then(|m| materials.get(m.name()))
+                .map(|material| PaletteEntry { material, state: VoxelState::default() });
+            (*c, voxel)
+        })
+        .collect()
+}
+
+/// Entrada de la paleta: `air`, un material o un material con estado,
+/// p. ej. `stone_stairs[facing=east,half=top]`.
+fn palette_voxel(value: &str, materials: &MaterialLibrary) -> Result<Option<PaletteEntry>, String> {
+    let (name, state) = match value.find('[') {
+        Some(open) => {
+            let properties = BlockState::parse(value)?.properties;
+            (value[..open].trim(), VoxelState::from_properties(&properties)?)
+        }
+        None => (value.trim(), VoxelState::default()),
+    };
+    if name == "air" {
+        return Ok(None);
+    }
+    let material = materials.get(name).ok_or_else(|| {
+        format!("material desconocido `{}` (disponibles: air, {})", name, materials.names().join(", "))
+    })?;
+    Ok(Some(PaletteEntry { material, state }))
+}
+
+/// Archivos `.txt` del directorio en orden natural (`Capa 2` antes que `Capa 10`).
+fn list_layer_files(dir: &Path) -> Result<Vec<PathBuf>, String> {
+    let entries = std::fs::read_dir(dir)
+        .map_err(|e| format!("No se pudo leer el directorio {}: {}", dir.display(), e))?;
+
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| {
+            path.is_file()
+                && path
+                    .extension()
+                    .map(|ext| ext.eq_ignore_ascii_case("txt"))
+                    .unwrap_or(false)
+        })
+        .collect();
+    files.sort_by_cached_key(|path| natural_key(&path.file_name().unwrap_or_default().to_string_lossy()));
+    Ok(files)
+}
+
+/// Clave de orden que compara los números del nombre por su valor.
+fn natural_key(name: &str) -> Vec<(String, u64)> {
+    let mut key = Vec::new();
+    let mut text = String::new();
+    let mut chars = name.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_ascii_digit() {
+            let mut number = 0u64;
+            while let Some(d) = chars.peek().and_then(|c| c.to_digit(10)) {
+                number = number.saturating_mul(10).saturating_add(d as u64);
+                chars.next();
+            }
+            key.push((std::mem::take(&mut text), number));
+        } else {
+            text.push(c);
+            chars.next();
+        }
+    }
+    key.push((text, 0));
+    key
+}
+
+/// Piso de prueba para poder abrir la ventana aunque falten las capas.
+fn insert_test_layer(grid: &mut VoxelGrid) -> Result<(), String> {
+    for x in 0..16 {
+        for z in 0..16 {
+            let mat = match (x + z) % 5 {
+                0 => Material::new_with_type(MaterialType::Grass),
+                1 => Material::new_with_type(MaterialType::Stone),
+                2 => Material::new_with_type(MaterialType::Gold),
+                3 => Material::new_with_type(MaterialType::GlowingObsidian),
+                _ => Material::new_with_type(MaterialType::Dirt),
+            };
+            grid.insert(x, 0, z, mat)?;
+        }
+    }
+    Ok(())
+}
+
+fn to_i32(value: NonZeroU32) -> i32 {
+    value.get().min(i32::MAX as u32) as i32
+}
+
+// Formato del archivo. Los valores se validan al deserializar para que el
+// error lleve la posición del valor en el archivo.
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneFile {
+    layers: Option<LayersDef>,
+    #[serde(default)]
+    models: Vec<ModelDef>,
+    #[serde(default)]
+    instances: Vec<InstanceDef>,
+    /// Bibliotecas de materiales, relativas al archivo de escena.
+    #[serde(default)]
+    materials: Vec<PathBuf>,
+    /// Correspondencias de bloques de Minecraft para los modelos NBT.
+    #[serde(default)]
+    blocks: Vec<PathBuf>,
+    palette: Option<HashMap<PaletteChar, String>>,
+    #[serde(default)]
+    objects: Vec<ObjectDef>,
+    #[serde(default)]
+    lights: Vec<LightDef>,
+    #[serde(default)]
+    sky: SkyDef,
+    #[serde(default)]
+    camera: CameraDef,
+    #[serde(default)]
+    render: RenderDef,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LayersDef {
+    directory: Option<PathBuf>,
+    files: Option<Vec<PathBuf>>,
+    #[serde(default)]
+    base_y: i32,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ModelDef {
+    path: PathBuf,
+    #[serde(default)]
+    offset: [i32; 3],
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct InstanceDef {
+    path: PathBuf,
+    #[serde(default)]
+    position: Vec3,
+    #[serde(default)]
+    rotation: [f32; 3],
+    #[serde(default)]
+    mirror: Vec<MirrorAxis>,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum MirrorAxis {
+    X,
+    Y,
+    Z,
+}
+
+#[derive(Deserialize, PartialEq, Eq, Hash)]
+#[serde(try_from = "String")]
+struct PaletteChar(char);
+
+impl TryFrom<String> for PaletteChar {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, String> {
+        let mut chars = s.chars();
+        match (chars.next(), chars.next()) {
+            (Some(' '), None) => Err("el espacio siempre es aire y no se puede redefinir".to_string()),
+            (Some(c), None) => Ok(PaletteChar(c)),
+            _ => Err(format!("la clave de la paleta debe ser un único carácter, no `{}`", s)),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase", deny_unknown_fields)]
+enum LightDef {
+    Point {
+        position: Vec3,
+        #[serde(default)]
+        color: Rgb,
+        #[serde(default = "default_light_intensity")]
+        intensity: NonNegative,
+    },
+    Spot {
+        position: Vec3,
+        direction: Direction,
+        angle: SpotAngle,
+        #[serde(default)]
+        color: Rgb,
+        #[serde(default = "default_light_intensity")]
+        intensity: NonNegative,
+    },
+}
+
+impl LightDef {
+    fn into_light(self) -> Light {
+        match self {
+            LightDef::Point { position, color, intensity } => Light::new(position.0, color.0, intensity.0),
+            LightDef::Spot { position, direction, angle, color, intensity } => Light::spot(
+                position.0,
+                direction.0,
+                color.0,
+                intensity.0,
+                angle.0 * SPOT_INNER_FRACTION,
+                angle.0,
+            ),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase", deny_unknown_fields)]
+enum ObjectDef {
+    Sphere { center: Vec3, radius: Positive, material: String },
+    Plane { point: Vec3, normal: Direction, material: String },
+    Cube { center: Vec3, size: Positive, material: String },
+    Mesh {
+        path: PathBuf,
+        #[serde(default)]
+        position: Vec3,
+        #[serde(default = "unit_scale")]
+        scale: Positive,
+        material: String,
+    },
+}
+
+impl ObjectDef {
+    fn resolve(self, materials: &MaterialLibrary, base_dir: &Path) -> Result<SceneObject, String> {
+        let (shape, name) = match self {
+            ObjectDef::Sphere { center, radius, material } => {
+                (ObjectShape::Sphere { center: center.0, radius: radius.0 }, material)
+            }
+            ObjectDef::Plane { point, normal, material } => {
+                (ObjectShape::Plane { point: point.0, normal: normal.0 }, material)
+            }
+            ObjectDef::Cube { center, size, material } => {
+                (ObjectShape::Cube { center: center.0, size: size.0 }, material)
+            }
+            ObjectDef::Mesh { path, position, scale, material } => {
+                let path = base_dir.join(path);
+                (ObjectShape::Mesh { path, position: position.0, scale: scale.0 }, material)
+            }
+        };
+        let material = materials.get(&name).ok_or_else(|| {
+            format!("material desconocido `{}` (disponibles: {})", name, materials.names().join(", "))
+        })?;
+        Ok(SceneObject { shape, material })
+    }
+}
+
+fn unit_scale() -> Positive {
+    Positive(1.0)
+}
+
+fn default_light_intensity() -> NonNegative {
+    NonNegative(DEFAULT_LIGHT_INTENSITY)
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct SkyDef {
+    enabled: bool,
+    sun_direction: Direction,
+    sun_color: Rgb,
+    sun_intensity: NonNegative,
+}
+
+impl Default for SkyDef {
+    fn default() -> Self {
+        let sky = Sky::default();
+        SkyDef {
+            enabled: sky.enabled,
+            sun_direction: Direction(sky.sun_direction),
+            sun_color: Rgb(sky.sun_color),
+            sun_intensity: NonNegative(sky.sun_intensity),
+        }
+    }
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct CameraDef {
+    default: Option<String>,
+    projection: Option<Projection>,
+    #[serde(default)]
+    presets: Vec<PresetDef>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PresetDef {
+    name: String,
+    eye: Vec3,
+    target: Vec3,
+    fov: Option<Fov>,
+    projection: Option<Projection>,
+    aperture: Option<NonNegative>,
+    focus_distance: Option<Positive>,
+    bokeh: Option<Bokeh>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct RenderDef {
+    width: Option<NonZeroU32>,
+    height: Option<NonZeroU32>,
+    max_dist: Option<Positive>,
+    max_reflections: Option<u32>,
+    integrator: Option<Integrator>,
+    samples: Option<NonZeroU32>,
+    max_bounces: Option<NonZeroU32>,
+    aa: Option<NonZeroU32>,
+    filter: Option<Filter>,
+    adaptive: Option<bool>,
+    tonemap: Option<ToneMapper>,
+    exposure: Option<f32>,
+    emissive_strength: Option<NonNegative>,
+}
+
+#[derive(Deserialize)]
+#[serde(from = "[f32; 3]")]
+struct Vec3(Vector3);
+
+impl From<[f32; 3]> for Vec3 {
+    fn from(v: [f32; 3]) -> Self {
+        Vec3(Vector3::new(v[0], v[1], v[2]))
+    }
+}
+
+impl Default for Vec3 {
+    fn default() -> Self {
+        Vec3(Vector3::zero())
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(try_from = "[f32; 3]")]
+struct Direction(Vector3);
+
+impl TryFrom<[f32; 3]> for Direction {
+    type Error = &'static str;
+
+    fn try_from(v: [f32; 3]) -> Result<Self, Self::Error> {
+        let dir = Vector3::new(v[0], v[1], v[2]);
+        if dir.length() < 1e-6 {
+            return Err("la dirección no puede ser el vector cero");
+        }
+        Ok(Direction(dir.normalized()))
+    }
+}
+
+/// Color `[r, g, b]` con componentes 0-255.
+#[derive(Deserialize)]
+#[serde(from = "[u8; 3]")]
+struct Rgb(Color);
+
+impl From<[u8; 3]> for Rgb {
+    fn from(c: [u8; 3]) -> Self {
+        Rgb(Color::new(c[0], c[1], c[2], 255))
+    }
+}
+
+impl Default for Rgb {
+    fn default() -> Self {
+        Rgb(Color::WHITE)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(try_from = "f32")]
+struct Fov(f32);
+
+impl TryFrom<f32> for Fov {
+    type Error = &'static str;
+
+    fn try_from(fov: f32) -> Result<Self, Self::Error> {
+        if fov <= 0.0 || fov >= 90.0 {
+            return Err("el campo de visión debe estar entre 0 y 90 grados");
+        }
+        Ok(Fov(fov))
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(try_from = "f32")]
+struct SpotAngle(f32);
+
+impl TryFrom<f32> for SpotAngle {
+    type Error = &'static str;
+
+    fn try_from(angle: f32) -> Result<Self, Self::Error> {
+        if angle <= 0.0 || angle >= 90.0 {
+            return Err("el ángulo del foco debe estar entre 0 y 90 grados");
+        }
+        Ok(SpotAngle(angle))
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(try_from = "f32")]
+struct Positive(f32);
+
+impl TryFrom<f32> for Positive {
+    type Error = &'static str;
+
+    fn try_from(v: f32) -> Result<Self, Self::Error> {
+        if !v.is_finite() {
+            return Err("el valor debe ser un número finito");
+        }
+        if v <= 0.0 {
+            return Err("el valor debe ser mayor que cero");
+        }
+        Ok(Positive(v))
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(try_from = "f32")]
+pub(crate) struct NonNegative(pub(crate) f32);
+
+impl TryFrom<f32> for NonNegative {
+    type Error = &'static str;
+
+    fn try_from(v: f32) -> Result<Self, Self::Error> {
+        if !v.is_finite() {
+            return Err("el valor debe ser un número finito");
+        }
+        if v < 0.0 {
+            return Err("el valor no puede ser negativo");
+        }
+        Ok(NonNegative(v))
+    }
+}
@@ -0,0 +1,506 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::block_mapping::{BlockMapping, BlockState, Mapped, DYE_COLORS};
+use crate::nbt::{self, Tag};
+use crate::shape::VoxelState;
+use crate::voxel::{Voxel, VoxelGrid};
+
+/// Tipos de bloque sin mapear que se listan en el informe.
+const MAX_REPORTED_BLOCKS: usize = 20;
+
+/// Bloques leídos de un archivo: posición e índice en la paleta.
+struct Structure {
+    palette: Vec<BlockState>,
+    blocks: Vec<([i32; 3], usize)>,
+}
+
+/// Carga una estructura de Minecraft (Sponge `.schem`, MCEdit `.schematic` o
+/// estructura vanilla `.nbt`) con su esquina mínima en `offset`. El formato se
+/// detecta por el contenido. Los bloques sin regla en `blocks` se omiten y se
+/// listan al final. Devuelve el número de voxels insertados.
+pub fn load_schematic(
+    path: &Path,
+    grid: &mut VoxelGrid,
+    offset: [i32; 3],
+    blocks: &BlockMapping,
+) -> Result<usize, String> {
+    let root = nbt::read_file(path)?;
+    let structure = if root.get("Schematic").is_some() || root.get("BlockData").is_some() {
+        read_sponge(&root)
+    } else if root.get("Blocks").and_then(Tag::as_bytes).is_some() {
+        read_legacy(&root)
+    } else if root.get("blocks").is_some() {
+        read_structure(&root)
+    } else {
+        Err("formato NBT no reconocido (se esperaba .schem, .schematic o estructura .nbt)".to_string())
+    }
+    .map_err(|e| format!("{}: {}", path.display(), e))?;
+
+    // Cada entrada de la paleta se resuelve una vez: aire, sin mapear o voxel.
+    let voxels: Vec<Mapped<Voxel>> = structure
+        .palette
+        .iter()
+        .map(|state| {
+            Ok(match blocks.lookup(state) {
+                Mapped::Block(material) => Mapped::Block(Voxel {
+                    material: grid.intern_material(material.clone())?,
+                    state: VoxelState::from_minecraft(&state.properties),
+                }),
+                Mapped::Air => Mapped::Air,
+                Mapped::Unmapped => Mapped::Unmapped,
+            })
+        })
+        .collect::<Result<_, String>>()?;
+    let mut unmapped = vec![0usize; structure.palette.len()];
+    let mut count = 0;
+    for (pos, index) in &structure.blocks {
+        match &voxels[*index] {
+            Mapped::Block(voxel) => {
+                grid.insert_voxel(pos[0] + offset[0], pos[1] + offset[1], pos[2] + offset[2], *voxel)?;
+                count += 1;
+            }
+            Mapped::Air => {}
+            Mapped::Unmapped => unmapped[*index] += 1,
+        }
+    }
+
+    report_unmapped(path, &structure.palette, &unmapped, blocks);
+    Ok(count)
+}
+
+/// Agrupa los bloques omitidos por identificador. Las propiedades solo se
+/// muestran si el bloque tiene reglas pero ninguna coincide con su estado.
+fn report_unmapped(path: &Path, palette: &[BlockState], unmapped: &[usize], blocks: &BlockMapping) {
+    let mut totals: HashMap<String, usize> = HashMap::new();
+    for (state, count) in palette.iter().zip(unmapped) {
+        if *count == 0 {
+            continue;
+        }
+        let key = if blocks.knows(&state.name) { state.to_string() } else { state.name.clone() };
+        *totals.entry(key).or_default() += count;
+    }
+    if totals.is_empty() {
+        return;
+    }
+
+    let mut totals: Vec<(String, usize)> = totals.into_iter().collect();
+    totals.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    let total: usize = totals.iter().map(|(_, count)| count).sum();
+    println!(
+        "⚠ {}: {} bloques sin mapear ({} tipos), añádelos con --blocks:",
+        path.display(), total, totals.len()
+    );
+    for (key, count) in totals.iter().take(MAX_REPORTED_BLOCKS) {
+        println!("    {:>7}  {}", count, key);
+    }
+    if totals.len() > MAX_REPORTED_BLOCKS {
+        println!("    ... y {} tipos más", totals.len() - MAX_REPORTED_BLOCKS);
+    }
+}
+
+fn int(tag: &Tag, key: &str) -> Result<i32, String> {
+    tag.get(key)
+        .and_then(Tag::as_i32)
+        .ok_or_else(|| format!("falta el entero `{}`", key))
+}
+
+/// Dimensiones `Width`, `Height`, `Length` de los formatos de esquema. Deben
+/// ser positivas y cubrir exactamente los `blocks` bloques de los datos.
+fn dimensions(tag: &Tag, blocks: usize) -> Result<[i32; 3], String> {
+    let size = [int(tag, "Width")?, int(tag, "Height")?, int(tag, "Length")?];
+    if size.iter().any(|d| *d <= 0) {
+        return Err(format!("dimensiones inválidas {}x{}x{}", size[0], size[1], size[2]));
+    }
+    let volume = size.iter().try_fold(1usize, |volume, d| volume.checked_mul(*d as usize));
+    if volume != Some(blocks) {
+        return Err(format!(
+            "las dimensiones {}x{}x{} no coinciden con los {} bloques de los datos",
+            size[0], size[1], size[2], blocks
+        ));
+    }
+    Ok(size)
+}
+
+/// Posición del bloque `i` en el orden de los esquemas: X, luego Z, luego Y.
+fn index_to_pos(i: usize, size: [i32; 3]) -> [i32; 3] {
+    let i = i as i32;
+    let layer = size[0] * size[2];
+    [i % size[0], i / layer, (i % layer) / size[0]]
+}
+
+/// Sponge Schematic v1-v3 (`.schem`, WorldEdit 7). La v3 anida todo bajo
+/// `Schematic` y mueve la paleta a `Blocks`.
+fn read_sponge(root: &Tag) -> Result<Structure, String> {
+    let schematic = root.get("Schematic").unwrap_or(root);
+    let version = schematic.get("Version").and_then(Tag::as_i32).unwrap_or(1);
+    let (palette_tag, data) = if version >= 3 {
+        let blocks = schematic.get("Blocks").ok_or("el esquema no tiene bloques")?;
+        (blocks.get("Palette"), blocks.get("Data"))
+    } else {
+        (schematic.get("Palette"), schematic.get("BlockData"))
+    };
+    let palette_tag = palette_tag.and_then(Tag::as_compound).ok_or("falta la paleta")?;
+    let data = data.and_then(Tag::as_bytes).ok_or("faltan los datos de bloques")?;
+
+    let mut palette = vec![BlockState::new("air"); palette_tag.len()];
+    for (name, id) in palette_tag {
+        let id = id
+            .as_i32()
+            .and_then(|id| usize::try_from(id).ok())
+            .filter(|id| *id < palette.len())
+            .ok_or_else(|| format!("índice de paleta inválido para `{}`", name))?;
+        palette[id] = BlockState::parse(name)?;
+    }
+
+    // Los índices van codificados como varint.
+    let mut indices = Vec::new();
+    let mut value = 0usize;
+    let mut shift = 0;
+    for byte in data {
+        value |= ((byte & 0x7f) as usize) << shift;
+        if byte & 0x80 != 0 {
+            shift += 7;
+            if shift > 28 {
+                return Err("varint demasiado largo en los datos de bloques".to_string());
+            }
+            continue;
+        }
+        if value >= palette.len() {
+            return Err(format!("el bloque {} usa el índice de paleta {} que no existe", indices.len(), value));
+        }
+        indices.push(value);
+        value = 0;
+        shift = 0;
+    }
+    if shift != 0 {
+        return Err("varint incompleto al final de los datos de bloques".to_string());
+    }
+
+    let size = dimensions(schematic, indices.len())?;
+    let blocks = indices.into_iter().enumerate().map(|(i, value)| (index_to_pos(i, size), value)).collect();
+    Ok(Structure { palette, blocks })
+}
+
+/// Formato MCEdit/Schematica (`.schematic`), anterior a la 1.13: IDs
+/// numéricos con un nibble de datos que se traducen a nombres y estados.
+fn read_legacy(root: &Tag) -> Result<Structure, String> {
+    let ids = root.get("Blocks").and_then(Tag::as_bytes).ok_or("faltan los bloques")?;
+    let size = dimensions(root, ids.len())?;
+    let data = root.get("Data").and_then(Tag::as_bytes).ok_or("faltan los datos de bloques")?;
+    let add = root.get("AddBlocks").and_then(Tag::as_bytes);
+    if data.len() < ids.len() {
+        return Err("`Data` es más corto que `Blocks`".to_string());
+    }
+
+    let mut palette = Vec::new();
+    let mut palette_index: HashMap<(u16, u8), usize> = HashMap::new();
+    let mut blocks = Vec::with_capacity(ids.len());
+    for (i, &low) in ids.iter().enumerate() {
+        // `AddBlocks` guarda los 4 bits altos del ID, dos bloques por byte.
+        let high = add
+            .and_then(|add| add.get(i >> 1))
+            .map(|b| if i & 1 == 0 { b >> 4 } else { b & 0x0f })
+            .unwrap_or(0);
+        let key = ((high as u16) << 8 | low as u16, data[i] & 0x0f);
+        let index = *palette_index.entry(key).or_insert_with(|| {
+            palette.push(legacy_state(key.0, key.1));
+            palette.len() - 1
+        });
+        blocks.push((index_to_pos(i, size), index));
+    }
+    Ok(Structure { palette, blocks })
+}
+
+/// Estructura vanilla (`.nbt` del bloque de estructuras): lista explícita de
+/// bloques con su posición y paleta de `Name` + `Properties`.
+fn read_structure(root: &Tag) -> Result<Structure, String> {
+    let palette_list = match root.get("palette") {
+        Some(palette) => palette,
+        // Las estructuras con variantes aleatorias guardan varias paletas; se usa la primera.
+        None => root
+            .get("palettes")
+            .and_then(Tag::as_list)
+            .and_then(|p| p.first())
+            .ok_or("falta la paleta")?,
+    };
+    let mut palette = Vec::new();
+    for entry in palette_list.as_list().ok_or("la paleta no es una lista")? {
+        let name = entry.get("Name").and_then(Tag::as_str).ok_or("entrada de paleta sin `Name`")?;
+        let mut state = BlockState::new(name);
+        if let Some(properties) = entry.get("Properties").and_then(Tag::as_compound) {
+            for (key, value) in properties {
+                if let Some(value) = value.as_str() {
+                    state = state.with(key, value);
+                }
+            }
+        }
+        palette.push(state);
+    }
+
+    let list = root.get("blocks").and_then(Tag::as_list).ok_or("`blocks` no es una lista")?;
+    let mut blocks = Vec::with_capacity(list.len());
+    for block in list {
+        let pos = block.get("pos").and_then(Tag::as_ivec3).ok_or("bloque sin `pos`")?;
+        let state = block
+            .get("state")
+            .and_then(Tag::as_i32)
+            .and_then(|s| usize::try_from(s).ok())
+            .filter(|s| *s < palette.len())
+            .ok_or("bloque con `state` inválido")?;
+        blocks.push((pos, state));
+    }
+    Ok(Structure { palette, blocks })
+}
+
+const WOODS: [&str; 6] = ["oak", "spruce", "birch", "jungle", "acacia", "dark_oak"];
+const STONES: [&str; 7] = [
+    "stone", "granite", "polished_granite", "diorite", "polished_diorite", "andesite", "polished_andesite",
+];
+const SLABS: [&str; 8] = [
+    "smooth_stone_slab", "sandstone_slab", "petrified_oak_slab", "cobblestone_slab",
+    "brick_slab", "stone_brick_slab", "nether_brick_slab", "quartz_slab",
+];
+const STONE_BRICKS: [&str; 4] = ["stone_bricks", "mossy_stone_bricks", "cracked_stone_bricks", "chiseled_stone_bricks"];
+
+/// Traduce un ID numérico y su nibble de datos al bloque actual equivalente.
+/// Los IDs que no están en la tabla quedan como `legacy:<id>` para poder
+/// mapearlos igualmente.
+fn legacy_state(id: u16, data: u8) -> BlockState {
+    let pick = |names: &[&'static str]| names.get(data as usize).copied().unwrap_or(names[0]);
+    let stairs = |name: &str| {
+        let facing = ["east", "west", "south", "north"][(data & 3) as usize];
+        let half = if data & 4 != 0 { "top" } else { "bottom" };
+        BlockState::new(name).with("facing", facing).with("half", half)
+    };
+    let log = |wood: &str| {
+        let axis = ["y", "x", "z", "y"][((data >> 2) & 3) as usize];
+        BlockState::new(&format!("{}_log", wood)).with("axis", axis)
+    };
+
+    match id {
+        0 => BlockState::new("air"),
+        1 => BlockState::new(pick(&STONES)),
+        2 => BlockState::new("grass_block"),
+        3 => BlockState::new(pick(&["dirt", "coarse_dirt", "podzol"])),
+        4 => BlockState::new("cobblestone"),
+        5 => BlockState::new(&format!("{}_planks", pick(&WOODS))),
+        7 => BlockState::new("bedrock"),
+        8 | 9 => BlockState::new("water"),
+        10 | 11 => BlockState::new("lava"),
+        12 => BlockState::new(if data == 1 { "red_sand" } else { "sand" }),
+        13 => BlockState::new("gravel"),
+        14 => BlockState::new("gold_ore"),
+        17 => log(WOODS[(data & 3) as usize]),
+        18 => BlockState::new(&format!("{}_leaves", WOODS[(data & 3) as usize])),
+        20 => BlockState::new("glass"),
+        24 => BlockState::new("sandstone"),
+        41 => BlockState::new("gold_block"),
+        43 => BlockState::new(SLABS[(data & 7) as usize]).with("type", "double"),
+        44 => {
+            let half = if data & 8 != 0 { "top" } else { "bottom" };
+            BlockState::new(SLABS[(data & 7) as usize]).with("type", half)
+        }
+        45 => BlockState::new("bricks"),
+        48 => BlockState::new("mossy_cobblestone"),
+        49 => BlockState::new("obsidian"),
+        53 => stairs("oak_stairs"),
+        54 => BlockState::new("chest"),
+        67 => stairs("cobblestone_stairs"),
+        79 => BlockState::new("ice"),
+        85 => BlockState::new("oak_fence"),
+        87 => BlockState::new("netherrack"),
+        89 => BlockState::new("glowstone"),
+        95 => BlockState::new(&format!("{}_stained_glass", DYE_COLORS[data as usize])),
+        98 => BlockState::new(pick(&STONE_BRICKS)),
+        102 => BlockState::new("glass_pane"),
+        108 => stairs("brick_stairs"),
+        109 => stairs("stone_brick_stairs"),
+        112 => BlockState::new("nether_bricks"),
+        114 => stairs("nether_brick_stairs"),
+        128 => stairs("sandstone_stairs"),
+        134 => stairs("spruce_stairs"),
+        135 => stairs("birch_stairs"),
+        136 => stairs("jungle_stairs"),
+        139 => BlockState::new(if data == 1 { "mossy_cobblestone_wall" } else { "cobblestone_wall" }),
+        146 => BlockState::new("trapped_chest"),
+        156 => stairs("quartz_stairs"),
+        160 => BlockState::new(&format!("{}_stained_glass_pane", DYE_COLORS[data as usize])),
+        162 => log(WOODS[4 + (data & 1) as usize]),
+        163 => stairs("acacia_stairs"),
+        164 => stairs("dark_oak_stairs"),
+        174 => BlockState::new("packed_ice"),
+        213 => BlockState::new("magma_block"),
+        _ => {
+            let state = BlockState::new(&format!("legacy:{}", id));
+            if data != 0 { state.with("data", &data.to_string()) } else { state }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compound(entries: Vec<(&str, Tag)>) -> Tag {
+        Tag::Compound(entries.into_iter().map(|(k, v)| (k.to_string(), v)).collect())
+    }
+
+    fn sizes(width: i32, height: i32, length: i32) -> Vec<(&'static str, Tag)> {
+        vec![("Width", Tag::Short(width as i16)), ("Height", Tag::Short(height as i16)), ("Length", Tag::Short(length as i16))]
+    }
+
+    fn sponge(size: Vec<(&'static str, Tag)>, palette: &[&str], data: &[u8]) -> Tag {
+        let palette = palette.iter().enumerate().map(|(i, name)| (*name, Tag::Int(i as i32))).collect();
+        let mut entries = size;
+        entries.push(("Version", Tag::Int(2)));
+        entries.push(("Palette", compound(palette)));
+        entries.push(("BlockData", Tag::ByteArray(data.to_vec())));
+        compound(entries)
+    }
+
+    fn legacy(size: Vec<(&'static str, Tag)>, blocks: &[u8], data: &[u8]) -> Tag {
+        let mut entries = size;
+        entries.push(("Blocks", Tag::ByteArray(blocks.to_vec())));
+        entries.push(("Data", Tag::ByteArray(data.to_vec())));
+        compound(entries)
+    }
+
+    fn blocks(structure: &Structure) -> Vec<([i32; 3], String)> {
+        structure.blocks.iter().map(|(pos, i)| (*pos, structure.palette[*i].to_string())).collect()
+    }
+
+    fn error(result: Result<Structure, String>) -> String {
+        result.err().expect("el esquema debería dar error")
+    }
+
+    #[test]
+    fn sponge_blocks_go_x_then_z_then_y() {
+        let root = sponge(sizes(2, 2, 1), &["minecraft:air", "minecraft:stone_stairs[facing=east]"], &[0, 1, 1, 0]);
+        let structure = read_sponge(&root).unwrap();
+        assert_eq!(
+            blocks(&structure),
+            [
+                ([0, 0, 0], "minecraft:air".to_string()),
+                ([1, 0, 0], "minecraft:stone_stairs[facing=east]".to_string()),
+                ([0, 1, 0], "minecraft:stone_stairs[facing=east]".to_string()),
+                ([1, 1, 0], "minecraft:air".to_string()),
+            ]
+        );
+        assert_eq!(index_to_pos(5, [2, 3, 4]), [1, 0, 2]);
+        assert_eq!(index_to_pos(9, [2, 3, 4]), [1, 1, 0]);
+    }
+
+    #[test]
+    fn sponge_indices_are_varints() {
+        let names: Vec<String> = (0..200).map(|i| format!("minecraft:b{}", i)).collect();
+        let names: Vec<&str> = names.iter().map(String::as_str).collect();
+        // 150 = 0x96 ocupa dos bytes: 0x96 con el bit de continuación y 0x01.
+        let structure = read_sponge(&sponge(sizes(2, 1, 1), &names, &[0x96, 0x01, 0x7f])).unwrap();
+        assert_eq!(structure.blocks, [([0, 0, 0], 150), ([1, 0, 0], 127)]);
+
+        let err = error(read_sponge(&sponge(sizes(1, 1, 1), &names, &[0x80, 0x80, 0x80, 0x80, 0x80, 0x01])));
+        assert!(err.contains("varint"), "{}", err);
+        let err = error(read_sponge(&sponge(sizes(1, 1, 1), &names, &[0x01, 0x80])));
+        assert!(err.contains("varint"), "{}", err);
+    }
+
+    #[test]
+    fn sponge_v3_nests_blocks() {
+        let palette = compound(vec![("minecraft:air", Tag::Int(0)), ("minecraft:glass", Tag::Int(1))]);
+        let mut schematic = sizes(1, 1, 2);
+        schematic.push(("Version", Tag::Int(3)));
+        schematic.push(("Blocks", compound(vec![("Palette", palette), ("Data", Tag::ByteArray(vec![1, 0]))])));
+        let root = compound(vec![("Schematic", compound(schematic))]);
+
+        let structure = read_sponge(&root).unwrap();
+        assert_eq!(blocks(&structure)[0], ([0, 0, 0], "minecraft:glass".to_string()));
+        assert_eq!(structure.blocks[1].0, [0, 0, 1]);
+    }
+
+    #[test]
+    fn sponge_rejects_bad_sizes_and_indices() {
+        let palette = ["minecraft:air", "minecraft:stone"];
+        let err = error(read_sponge(&sponge(sizes(0, 1, 1), &palette, &[1, 1])));
+        assert!(err.contains("dimensiones"), "{}", err);
+
+        let mut negative = sizes(2, 1, 1);
+        negative[0].1 = Tag::Int(-2);
+        let err = error(read_sponge(&sponge(negative, &palette, &[1, 1])));
+        assert!(err.contains("dimensiones"), "{}", err);
+
+        let err = error(read_sponge(&sponge(sizes(2, 2, 1), &palette, &[1, 1, 1])));
+        assert!(err.contains("no coinciden"), "{}", err);
+
+        let err = error(read_sponge(&sponge(sizes(1, 1, 1), &palette, &[2])));
+        assert!(err.contains("no existe"), "{}", err);
+
+        let bad_palette = compound(vec![
+            ("Width", Tag::Short(1)), ("Height", Tag::Short(1)), ("Length", Tag::Short(1)),
+            ("Palette", compound(vec![("minecraft:stone", Tag::Int(4))])),
+            ("BlockData", Tag::ByteArray(vec![0])),
+        ]);
+        let err = error(read_sponge(&bad_palette));
+        assert!(err.contains("paleta"), "{}", err);
+    }
+
+    #[test]
+    fn legacy_ids_and_data_are_translated() {
+        // Granito, escalera de roble hacia el sur boca abajo, tronco de roble en X.
+        let structure = read_legacy(&legacy(sizes(3, 1, 1), &[1, 53, 17], &[1, 6, 4])).unwrap();
+        assert_eq!(
+            blocks(&structure),
+            [
+                ([0, 0, 0], "minecraft:granite".to_string()),
+                ([1, 0, 0], "minecraft:oak_stairs[facing=south,half=top]".to_string()),
+                ([2, 0, 0], "minecraft:oak_log[axis=x]".to_string()),
+            ]
+        );
+
+        // Bloques repetidos comparten la entrada de la paleta.
+        let structure = read_legacy(&legacy(sizes(2, 1, 1), &[87, 87], &[0, 0])).unwrap();
+        assert_eq!(structure.palette.len(), 1);
+
+        // `AddBlocks` aporta los 4 bits altos: 0x1_05 = 261, sin traducción.
+        let mut root = legacy(sizes(1, 1, 1), &[5], &[3]);
+        if let Tag::Compound(map) = &mut root {
+            map.insert("AddBlocks".to_string(), Tag::ByteArray(vec![0x10]));
+        }
+        let structure = read_legacy(&root).unwrap();
+        assert_eq!(structure.palette[0].to_string(), "legacy:261[data=3]");
+    }
+
+    #[test]
+    fn legacy_rejects_bad_sizes() {
+        let err = error(read_legacy(&legacy(sizes(0, 1, 1), &[1, 1], &[0, 0])));
+        assert!(err.contains("dimensiones"), "{}", err);
+        let err = error(read_legacy(&legacy(sizes(1, 1, 1), &[1, 1], &[0, 0])));
+        assert!(err.contains("no coinciden"), "{}", err);
+        let err = error(read_legacy(&legacy(sizes(2, 1, 1), &[1, 1], &[0])));
+        assert!(err.contains("Data"), "{}", err);
+    }
+
+    #[test]
+    fn structure_blocks_use_their_palette() {
+        let palette = Tag::List(vec![
+            compound(vec![("Name", Tag::String("minecraft:air".to_string()))]),
+            compound(vec![
+                ("Name", Tag::String("minecraft:oak_log".to_string())),
+                ("Properties", compound(vec![("axis", Tag::String("z".to_string()))])),
+            ]),
+        ]);
+        let block = |pos: [i32; 3], state: i32| {
+            compound(vec![("pos", Tag::IntArray(pos.to_vec())), ("state", Tag::Int(state))])
+        };
+        let root = compound(vec![
+            ("palette", palette.clone()),
+            ("blocks", Tag::List(vec![block([0, 0, 0], 0), block([3, 1, 2], 1)])),
+        ]);
+        let structure = read_structure(&root).unwrap();
+        assert_eq!(blocks(&structure)[1], ([3, 1, 2], "minecraft:oak_log[axis=z]".to_string()));
+
+        let root = compound(vec![("palette", palette), ("blocks", Tag::List(vec![block([0, 0, 0], 2)]))]);
+        let err = error(read_structure(&root));
+        assert!(err.contains("state"), "{}", err);
+    }
+}
@@ -0,0 +1,452 @@
+use raylib::prelude::Vector3;
+use std::collections::BTreeMap;
+
+use crate::block_mapping::BlockState;
+
+/// Dirección hacia la que mira un bloque, con los nombres de Minecraft:
+/// norte es -Z y este es +X.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Facing {
+    North,
+    South,
+    East,
+    West,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Half {
+    Bottom,
+    Top,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Axis {
+    X,
+    Y,
+    Z,
+}
+
+/// Geometría de un bloque dentro de su celda. Los bloques parciales se
+/// describen con hasta dos cajas en coordenadas locales de 0 a 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum BlockShape {
+    #[default]
+    Full,
+    /// Losa de media altura, abajo o arriba.
+    Slab(Half),
+    /// Escalera en L: una losa más el escalón del lado hacia el que mira.
+    Stairs(Facing, Half),
+    /// Poste de medio bloque de ancho, como los muros, a lo largo del eje.
+    Pillar(Axis),
+}
+
+/// Cara de un bloque según su orientación, para elegir la textura.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Face {
+    Top,
+    Bottom,
+    Side,
+    Front,
+}
+
+/// Estado de un voxel: orientación y propiedades que cambian cómo se ve el
+/// bloque sin cambiar su material. Lo que queda en `None` usa la orientación
+/// por defecto del material.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct VoxelState {
+    pub facing: Option<Facing>,
+    pub half: Option<Half>,
+    pub axis: Option<Axis>,
+    /// Losa doble (`type=double`): ocupa la celda entera.
+    pub double: bool,
+    /// Bloque sumergido. Se conserva al importar, pero todavía no se dibuja
+    /// el agua alrededor de la forma.
+    pub waterlogged: bool,
+}
+
+impl VoxelState {
+    /// Lee `facing`, `half`, `type` (de las losas), `axis` y `waterlogged`
+    /// escritos a mano: las claves desconocidas y los valores inválidos son
+    /// un error.
+    pub fn from_properties(properties: &BTreeMap<String, String>) -> Result<VoxelState, String> {
+        let mut state = VoxelState::default();
+        for (key, value) in properties {
+            state.set(key, value)?;
+        }
+        Ok(state)
+    }
+
+    /// Como `from_properties`, pero para estados de Minecraft: las propiedades
+    /// que no se entienden (`shape`, `powered`, el `type` de los cofres...)
+    /// se ignoran.
+    pub fn from_minecraft(properties: &BTreeMap<String, String>) -> VoxelState {
+        let mut state = VoxelState::default();
+        for (key, value) in properties {
+            let _ = state.set(key, value);
+        }
+        state
+    }
+
+    /// Cara a la que pertenece `normal`. Arriba y abajo siguen el eje del
+    /// bloque (los extremos de un pilar tumbado son su "arriba") y el frente
+    /// es el lado hacia el que mira, por defecto el norte.
+    pub fn face(&self, normal: &Vector3) -> Face {
+        let along = match self.axis.unwrap_or(Axis::Y) {
+            Axis::X => normal.x,
+            Axis::Y => normal.y,
+            Axis::Z => normal.z,
+        };
+        if along > 0.5 {
+            return Face::Top;
+        }
+        if along < -0.5 {
+            return Face::Bottom;
+        }
+        let front = match self.facing.unwrap_or(Facing::North) {
+            Facing::North => -normal.z,
+            Facing::South => normal.z,
+            Facing::East => normal.x,
+            Facing::West => -normal.x,
+        };
+        if front > 0.5 { Face::Front } else { Face::Side }
+    }
+
+    /// Estado visto después de girar o reflejar el bloque con `map`, que
+    /// transforma direcciones. Solo cambian el frente y el eje, que son lo
+    /// que decide las caras de la textura; se toma la dirección de la grilla
+    /// más cercana.
+    pub fn transformed(&self, map: impl Fn(Vector3) -> Vector3) -> VoxelState {
+        let front = match self.facing.unwrap_or(Facing::North) {
+            Facing::North => Vector3::new(0.0, 0.0, -1.0),
+            Facing::South => Vector3::new(0.0, 0.0, 1.0),
+            Facing::East => Vector3::new(1.0, 0.0, 0.0),
+            Facing::West => Vector3::new(-1.0, 0.0, 0.0),
+        };
+        let front = map(front);
+        let facing = if front.x.abs() > front.z.abs() {
+            if front.x > 0.0 { Facing::East } else { Facing::West }
+        } else if front.z > 0.0 {
+            Facing::South
+        } else {
+            Facing::North
+        };
+
+        let along = map(match self.axis.unwrap_or(Axis::Y) {
+            Axis::X => Vector3::new(1.0, 0.0, 0.0),
+            Axis::Y => Vector3::new(0.0, 1.0, 0.0),
+            Axis::Z => Vector3::new(0.0, 0.0, 1.0),
+        });
+        let (ax, ay, az) = (along.x.abs(), along.y.abs(), along.z.abs());
+        let axis = if ax >= ay && ax >= az {
+            Axis::X
+        } else if ay >= az {
+            Axis::Y
+        } else {
+            Axis::Z
+        };
+
+        VoxelState { facing: Some(facing), axis: Some(axis), ..*self }
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match (key, value) {
+            ("facing", "north") => self.facing = Some(Facing::North),
+            ("facing", "south") => self.facing = Some(Facing::South),
+            ("facing", "east") => self.facing = Some(Facing::East),
+            ("facing", "west") => self.facing = Some(Facing::West),
+            ("half" | "type", "bottom") => self.half = Some(Half::Bottom),
+            ("half" | "type", "top") => self.half = Some(Half::Top),
+            ("type", "double") => self.double = true,
+            ("axis", "x") => self.axis = Some(Axis::X),
+            ("axis", "y") => self.axis = Some(Axis::Y),
+            ("axis", "z") => self.axis = Some(Axis::Z),
+            ("waterlogged", "true") => self.waterlogged = true,
+            ("waterlogged", "false") => self.waterlogged = false,
+            ("facing", _) => return Err(format!("orientación inválida `{}` (north, south, east o west)", value)),
+            ("half", _) => return Err(format!("mitad inválida `{}` (bottom o top)", value)),
+            ("type", _) => return Err(format!("tipo de losa inválido `{}` (bottom, top o double)", value)),
+            ("axis", _) => return Err(format!("eje inválido `{}` (x, y o z)", value)),
+            ("waterlogged", _) => return Err(format!("valor inválido `{}` para waterlogged (true o false)", value)),
+            _ => {
+                return Err(format!(
+                    "propiedad desconocida `{}` (disponibles: facing, half, type, axis, waterlogged)",
+                    key
+                ))
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Caja alineada a los ejes en coordenadas locales de la celda.
+#[derive(Debug, Clone, Copy)]
+struct Aabb {
+    min: [f32; 3],
+    max: [f32; 3],
+}
+
+impl Aabb {
+    /// Caja vacía para rellenar las formas de una sola caja.
+    const EMPTY: Aabb = Aabb { min: [1.0; 3], max: [0.0; 3] };
+
+    const fn new(min: [f32; 3], max: [f32; 3]) -> Aabb {
+        Aabb { min, max }
+    }
+}
+
+impl BlockShape {
+    pub fn is_full(&self) -> bool {
+        matches!(self, BlockShape::Full)
+    }
+
+    /// Interpreta una forma con la sintaxis de estados de bloque:
+    /// `full`, `slab[type=top]`, `stairs[facing=east,half=top]`, `pillar[axis=x]`.
+    pub fn parse(text: &str) -> Result<BlockShape, String> {
+        let state = BlockState::parse(text)?;
+        let base = match state.name.trim_start_matches("minecraft:") {
+            "full" => BlockShape::Full,
+            "slab" => BlockShape::Slab(Half::Bottom),
+            "stairs" => BlockShape::Stairs(Facing::North, Half::Bottom),
+            "pillar" => BlockShape::Pillar(Axis::Y),
+            other => {
+                return Err(format!(
+                    "forma desconocida `{}` (disponibles: full, slab, stairs, pillar)",
+                    other
+                ))
+            }
+        };
+        Ok(base.oriented(&VoxelState::from_properties(&state.properties)?))
+    }
+
+    /// Forma con la orientación del voxel; lo que el estado no fija se queda
+    /// como lo define el material.
+    pub fn oriented(self, state: &VoxelState) -> BlockShape {
+        match self {
+            BlockShape::Full => BlockShape::Full,
+            BlockShape::Slab(_) if state.double => BlockShape::Full,
+            BlockShape::Slab(half) => BlockShape::Slab(state.half.unwrap_or(half)),
+            BlockShape::Stairs(facing, half) => {
+                BlockShape::Stairs(state.facing.unwrap_or(facing), state.half.unwrap_or(half))
+            }
+            BlockShape::Pillar(axis) => BlockShape::Pillar(state.axis.unwrap_or(axis)),
+        }
+    }
+
+    fn boxes(&self) -> [Aabb; 2] {
+        const LOWER: (f32, f32) = (0.0, 0.5);
+        const UPPER: (f32, f32) = (0.5, 1.0);
+        match *self {
+            BlockShape::Full => [Aabb::new([0.0; 3], [1.0; 3]), Aabb::EMPTY],
+            BlockShape::Slab(Half::Bottom) => [Aabb::new([0.0, LOWER.0, 0.0], [1.0, LOWER.1, 1.0]), Aabb::EMPTY],
+            BlockShape::Slab(Half::Top) => [Aabb::new([0.0, UPPER.0, 0.0], [1.0, UPPER.1, 1.0]), Aabb::EMPTY],
+            BlockShape::Stairs(facing, half) => {
+                // La losa va en la mitad indicada y el escalón en la otra,
+                // pegado al lado hacia el que mira la escalera.
+                let (slab, step) = match half {
+                    Half::Bottom => (LOWER, UPPER),
+                    Half::Top => (UPPER, LOWER),
+                };
+                let (x, z) = match facing {
+                    Facing::North => ((0.0, 1.0), (0.0, 0.5)),
+                    Facing::South => ((0.0, 1.0), (0.5, 1.0)),
+                    Facing::East => ((0.5, 1.0), (0.0, 1.0)),
+                    Facing::West => ((0.0, 0.5), (0.0, 1.0)),
+                };
+                [
+                    Aabb::new([0.0, slab.0, 0.0], [1.0, slab.1, 1.0]),
+                    Aabb::new([x.0, step.0, z.0], [x.1, step.1, z.1]),
+                ]
+            }
+            BlockShape::Pillar(axis) => {
+                let mut min = [0.25; 3];
+                let mut max = [0.75; 3];
+                let along = match axis {
+                    Axis::X => 0,
+                    Axis::Y => 1,
+                    Axis::Z => 2,
+                };
+                min[along] = 0.0;
+                max[along] = 1.0;
+                [Aabb::new(min, max), Aabb::EMPTY]
+            }
+        }
+    }
+
+    /// Intersección del rayo con la forma dentro de la celda `cell`. Solo se
+    /// aceptan impactos en `[t_min, t_max]`; devuelve la distancia y la normal
+    /// de la cara alcanzada.
+    pub fn intersect(
+        &self,
+        cell: (i32, i32, i32),
+        ro: &Vector3,
+        rd: &Vector3,
+        t_min: f32,
+        t_max: f32,
+    ) -> Option<(f32, Vector3)> {
+        let origin = [ro.x - cell.0 as f32, ro.y - cell.1 as f32, ro.z - cell.2 as f32];
+        let dir = [rd.x, rd.y, rd.z];
+
+        let mut best: Option<(f32, Vector3)> = None;
+        for b in self.boxes() {
+            let mut t_near = f32::NEG_INFINITY;
+            let mut t_far = f32::INFINITY;
+            let mut axis_near = 1;
+            let mut hit = true;
+            for axis in 0..3 {
+                if dir[axis].abs() < 1e-6 {
+                    if origin[axis] < b.min[axis] || origin[axis] > b.max[axis] {
+                        hit = false;
+                        break;
+                    }
+                    continue;
+                }
+                let inv = 1.0 / dir[axis];
+                let mut t0 = (b.min[axis] - origin[axis]) * inv;
+                let mut t1 = (b.max[axis] - origin[axis]) * inv;
+                if inv < 0.0 {
+                    std::mem::swap(&mut t0, &mut t1);
+                }
+                if t0 > t_near {
+                    t_near = t0;
+                    axis_near = axis;
+                }
+                t_far = t_far.min(t1);
+            }
+            if !hit || t_near > t_far || t_near < t_min || t_near > t_max {
+                continue;
+            }
+            if best.is_none_or(|(t, _)| t_near < t) {
+                let mut normal = [0.0; 3];
+                normal[axis_near] = -dir[axis_near].signum();
+                best = Some((t_near, Vector3::new(normal[0], normal[1], normal[2])));
+            }
+        }
+        best
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UP: Vector3 = Vector3 { x: 0.0, y: 1.0, z: 0.0 };
+    const DOWN: Vector3 = Vector3 { x: 0.0, y: -1.0, z: 0.0 };
+
+    /// Rayo desde `origin`, en coordenadas de la celda, contra `shape` puesta
+    /// en `cell`.
+    fn hit_in(shape: BlockShape, cell: (i32, i32, i32), origin: [f32; 3], dir: Vector3) -> Option<(f32, Vector3)> {
+        let ro = Vector3::new(origin[0] + cell.0 as f32, origin[1] + cell.1 as f32, origin[2] + cell.2 as f32);
+        shape.intersect(cell, &ro, &dir, 0.0, 10.0)
+    }
+
+    fn hit(shape: BlockShape, origin: [f32; 3], dir: Vector3) -> Option<(f32, Vector3)> {
+        hit_in(shape, (0, 0, 0), origin, dir)
+    }
+
+    /// Centro, en X y Z, del cuarto de celda donde va el escalón.
+    fn step_column(facing: Facing) -> (f32, f32) {
+        match facing {
+            Facing::North => (0.5, 0.25),
+            Facing::South => (0.5, 0.75),
+            Facing::East => (0.75, 0.5),
+            Facing::West => (0.25, 0.5),
+        }
+    }
+
+    const FACINGS: [Facing; 4] = [Facing::North, Facing::South, Facing::East, Facing::West];
+
+    #[test]
+    fn stairs_step_is_on_the_facing_side() {
+        for facing in FACINGS {
+            let (x, z) = step_column(facing);
+            let (ox, oz) = (1.0 - x, 1.0 - z);
+            for cell in [(0, 0, 0), (-2, -5, -9)] {
+                // Desde arriba: el escalón tapa a la losa en su lado.
+                let shape = BlockShape::Stairs(facing, Half::Bottom);
+                assert_eq!(hit_in(shape, cell, [x, 2.0, z], DOWN), Some((1.0, UP)), "{:?}", facing);
+                assert_eq!(hit_in(shape, cell, [ox, 2.0, oz], DOWN), Some((1.5, UP)), "{:?}", facing);
+
+                // Boca abajo: la losa arriba y el escalón colgando.
+                let shape = BlockShape::Stairs(facing, Half::Top);
+                assert_eq!(hit_in(shape, cell, [x, -1.0, z], UP), Some((1.0, DOWN)), "{:?}", facing);
+                assert_eq!(hit_in(shape, cell, [ox, -1.0, oz], UP), Some((1.5, DOWN)), "{:?}", facing);
+            }
+        }
+    }
+
+    #[test]
+    fn rays_cross_the_empty_part_of_stairs() {
+        let shape = BlockShape::Stairs(Facing::North, Half::Bottom);
+        let east = Vector3::new(1.0, 0.0, 0.0);
+        // A lo ancho, por el cuarto vacío de arriba al sur: no toca nada.
+        assert_eq!(hit(shape, [-1.0, 0.75, 0.75], east), None);
+        // A la misma altura pero del lado norte, choca con el escalón.
+        assert_eq!(hit(shape, [-1.0, 0.75, 0.25], east), Some((1.0, Vector3::new(-1.0, 0.0, 0.0))));
+        // Desde el sur atraviesa el hueco y llega a la cara trasera del escalón.
+        assert_eq!(hit(shape, [0.5, 0.75, 2.0], Vector3::new(0.0, 0.0, -1.0)), Some((1.5, Vector3::new(0.0, 0.0, 1.0))));
+    }
+
+    #[test]
+    fn slabs_fill_their_half() {
+        let east = Vector3::new(1.0, 0.0, 0.0);
+        let west_face = Vector3::new(-1.0, 0.0, 0.0);
+
+        let bottom = BlockShape::Slab(Half::Bottom);
+        assert_eq!(hit(bottom, [0.5, 2.0, 0.5], DOWN), Some((1.5, UP)));
+        assert_eq!(hit(bottom, [0.5, -1.0, 0.5], UP), Some((1.0, DOWN)));
+        assert_eq!(hit(bottom, [-1.0, 0.25, 0.5], east), Some((1.0, west_face)));
+        assert_eq!(hit(bottom, [-1.0, 0.75, 0.5], east), None);
+
+        let top = BlockShape::Slab(Half::Top);
+        assert_eq!(hit(top, [0.5, 2.0, 0.5], DOWN), Some((1.0, UP)));
+        assert_eq!(hit(top, [0.5, -1.0, 0.5], UP), Some((1.5, DOWN)));
+        assert_eq!(hit(top, [-1.0, 0.75, 0.5], east), Some((1.0, west_face)));
+        assert_eq!(hit(top, [-1.0, 0.25, 0.5], east), None);
+    }
+
+    #[test]
+    fn pillars_run_along_their_axis() {
+        for (axis, along) in [(Axis::X, 0), (Axis::Y, 1), (Axis::Z, 2)] {
+            let shape = BlockShape::Pillar(axis);
+            let across = (along + 1) % 3;
+            let unit = |i: usize, v: f32| {
+                let mut d = [0.0; 3];
+                d[i] = v;
+                Vector3::new(d[0], d[1], d[2])
+            };
+
+            // A lo largo del eje entra por el extremo de la celda.
+            let mut origin = [0.5; 3];
+            origin[along] = -1.0;
+            assert_eq!(hit(shape, origin, unit(along, 1.0)), Some((1.0, unit(along, -1.0))), "{:?}", axis);
+
+            // De costado choca con el poste, a un cuarto del borde.
+            let mut origin = [0.5; 3];
+            origin[across] = 2.0;
+            assert_eq!(hit(shape, origin, unit(across, -1.0)), Some((1.25, unit(across, 1.0))), "{:?}", axis);
+
+            // Pegado al borde de la celda pasa al lado del poste.
+            origin[(along + 2) % 3] = 0.1;
+            assert_eq!(hit(shape, origin, unit(across, -1.0)), None, "{:?}", axis);
+        }
+    }
+
+    #[test]
+    fn hits_outside_the_range_are_ignored() {
+        let shape = BlockShape::Full;
+        let ro = Vector3::new(0.5, 2.0, 0.5);
+        assert_eq!(shape.intersect((0, 0, 0), &ro, &DOWN, 0.0, 10.0), Some((1.0, UP)));
+        assert_eq!(shape.intersect((0, 0, 0), &ro, &DOWN, 1.5, 10.0), None);
+        assert_eq!(shape.intersect((0, 0, 0), &ro, &DOWN, 0.0, 0.5), None);
+    }
+
+    #[test]
+    fn parsed_shapes_take_the_state() {
+        assert_eq!(BlockShape::parse("stairs[facing=east,half=top]"), Ok(BlockShape::Stairs(Facing::East, Half::Top)));
+        assert_eq!(BlockShape::parse("slab[type=top]"), Ok(BlockShape::Slab(Half::Top)));
+        assert_eq!(BlockShape::parse("slab[type=double]"), Ok(BlockShape::Full));
+        assert_eq!(BlockShape::parse("pillar[axis=z]"), Ok(BlockShape::Pillar(Axis::Z)));
+        assert!(BlockShape::parse("stairs[facing=up]").unwrap_err().contains("orientación inválida"));
+        assert!(BlockShape::parse("fence").unwrap_err().contains("forma desconocida"));
+    }
+}
@@ -1,11 +1,13 @@
-use crate::material::Material;
+use crate::bvh::Aabb;
+use crate::material::MaterialId;
 use crate::ray_intersect::{Intersect, RayIntersect};
 use raylib::prelude::Vector3;
+use std::f32::consts::PI;
 
 pub struct Sphere {
     pub center: Vector3,
     pub radius: f32,
-    pub material: Material,
+    pub material: MaterialId,
 }
 
 impl RayIntersect for Sphere {
@@ -19,16 +21,28 @@ impl RayIntersect for Sphere {
         let discriminant = b * b - 4.0 * a * c;
 
         if discriminant > 0.0 {
-            let t = (-b - discriminant.sqrt()) / (2.0 * a);
+            // Desde dentro (al atravesar una esfera de vidrio) la raíz cercana
+            // queda detrás y vale la lejana.
+            let near = (-b - discriminant.sqrt()) / (2.0 * a);
+            let far = (-b + discriminant.sqrt()) / (2.0 * a);
+            let t = if near > 0.0 { near } else { far };
             if t > 0.0 {
                 let point = *ray_origin + *ray_direction * t;
                 let normal = (point - self.center).normalized();
                 let distance = t;
 
-                return Intersect::new(point, normal, distance, self.material.clone());
+                // Coordenadas esféricas: u da la vuelta al ecuador y v va de polo a polo.
+                let u = 0.5 + normal.z.atan2(normal.x) / (2.0 * PI);
+                let v = 0.5 - normal.y.clamp(-1.0, 1.0).asin() / PI;
+                return Intersect::new_with_uv(point, normal, distance, self.material, u, v);
             }
         }
 
         Intersect::empty()
     }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let r = Vector3::new(self.radius, self.radius, self.radius);
+        Some(Aabb::new(self.center - r, self.center + r))
+    }
 }
\ No newline at end of file
@@ -1,6 +1,9 @@
 use raylib::prelude::*;
+use crate::color::{color_to_linear, srgb_to_linear_rgb};
 use crate::material::MaterialType;
 use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::path::Path;
 use std::sync::{Arc, Mutex};
 
 lazy_static::lazy_static! {
@@ -21,6 +24,55 @@ pub struct Texture {
     data: Arc<TextureData>,
 }
 
+/// Dos texturas son iguales si comparten los mismos píxeles en la caché.
+impl PartialEq for Texture {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.data, &other.data)
+    }
+}
+
+impl Eq for Texture {}
+
+impl Hash for Texture {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        Arc::as_ptr(&self.data).hash(state);
+    }
+}
+
+/// Texturas por cara que sustituyen a la principal del material. Si falta
+/// una se usa la siguiente: frente -> lado, abajo -> arriba, y por último la
+/// principal.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct FaceTextures {
+    pub top: Option<Texture>,
+    pub bottom: Option<Texture>,
+    pub side: Option<Texture>,
+    pub front: Option<Texture>,
+}
+
+impl FaceTextures {
+    /// Busca en `images/` las variantes `<nombre>_top.png`, `_bottom`,
+    /// `_side` y `_front` de la textura del material. Son opcionales: si no
+    /// existen no se generan procedurales.
+    pub fn for_type(material_type: MaterialType) -> Self {
+        let (filename, fallback_color) = Texture::get_material_info(material_type);
+        let stem = filename.trim_end_matches(".png");
+        let load = |face: &str| {
+            let name = format!("{}_{}.png", stem, face);
+            Path::new("./images")
+                .join(&name)
+                .is_file()
+                .then(|| Texture::load_cached_texture(&name, fallback_color))
+        };
+        FaceTextures {
+            top: load("top"),
+            bottom: load("bottom"),
+            side: load("side"),
+            front: load("front"),
+        }
+    }
+}
+
 impl Texture {
     pub fn solid(color: Vector3) -> Self {
         let data = TextureData {
@@ -59,6 +111,10 @@ impl Texture {
             MaterialType::StoneSlab => ("stone_slab.png".to_string(), Vector3::new(0.55, 0.55, 0.55)),
             MaterialType::StonePillar => ("stone_pillar.png".to_string(), Vector3::new(0.7, 0.7, 0.7)),
             MaterialType::WoodChest => ("wood_chest.png".to_string(), Vector3::new(0.6, 0.4, 0.2)),
+            MaterialType::Glass => ("glass.png".to_string(), Vector3::new(0.92, 0.95, 0.98)),
+            MaterialType::Water => ("water.png".to_string(), Vector3::new(0.25, 0.45, 0.8)),
+            MaterialType::Ice => ("ice.png".to_string(), Vector3::new(0.7, 0.85, 1.0)),
+            MaterialType::StainedGlass => ("stained_glass.png".to_string(), Vector3::new(0.6, 0.3, 0.9)),
         }
     }
 
@@ -75,27 +131,11 @@ impl Texture {
         let image_path = format!("./images/{}", filename);
         println!("Intentando cargar: {}", image_path);
         
-        let texture_data = match image::open(&image_path) {
-            Ok(img) => {
-                let rgba_img = img.to_rgba8();
-                let (width, height) = rgba_img.dimensions();
-                
-                println!("✓ Cargando imagen real: {} ({}x{})", filename, width, height);
-                
-                let mut pixels = Vec::with_capacity((width * height) as usize);
-                for pixel in rgba_img.pixels() {
-                    let [r, g, b, a] = pixel.0;
-                    pixels.push(Color::new(r, g, b, a));
-                }
-                
+        let texture_data = match Self::read_image(&image_path, fallback_color) {
+            Ok(data) => {
+                println!("✓ Cargando imagen real: {} ({}x{})", filename, data.width, data.height);
                 println!("✓ Imagen cargada exitosamente: {}", filename);
-                
-                TextureData {
-                    pixels,
-                    width: width as i32,
-                    height: height as i32,
-                    fallback_color,
-                }
+                data
             },
             Err(e) => {
                 println!("⚠ Error al cargar {} ({}), usando procedural", filename, e);
@@ -115,6 +155,49 @@ impl Texture {
         }
     }
 
+    /// Carga una textura desde una ruta arbitraria. A diferencia de
+    /// `new_with_type`, un archivo ausente o ilegible es un error.
+    pub fn from_file(path: &Path, fallback_color: Vector3) -> Result<Self, String> {
+        let key = path.to_string_lossy().into_owned();
+        {
+            let cache = TEXTURE_CACHE.lock().unwrap();
+            if let Some(cached_data) = cache.get(&key) {
+                return Ok(Texture {
+                    data: cached_data.clone()
+                });
+            }
+        }
+
+        if !path.is_file() {
+            return Err(format!("no existe la textura {}", path.display()));
+        }
+        let data = Self::read_image(path, fallback_color)
+            .map_err(|e| format!("no se pudo leer la textura {}: {}", path.display(), e))?;
+        println!("✓ Cargando imagen real: {} ({}x{})", path.display(), data.width, data.height);
+
+        let arc_data = Arc::new(data);
+        TEXTURE_CACHE.lock().unwrap().insert(key, arc_data.clone());
+        Ok(Texture { data: arc_data })
+    }
+
+    fn read_image<P: AsRef<Path>>(path: P, fallback_color: Vector3) -> Result<TextureData, image::ImageError> {
+        let rgba_img = image::open(path)?.to_rgba8();
+        let (width, height) = rgba_img.dimensions();
+
+        let mut pixels = Vec::with_capacity((width * height) as usize);
+        for pixel in rgba_img.pixels() {
+            let [r, g, b, a] = pixel.0;
+            pixels.push(Color::new(r, g, b, a));
+        }
+
+        Ok(TextureData {
+            pixels,
+            width: width as i32,
+            height: height as i32,
+            fallback_color,
+        })
+    }
+
     fn create_procedural_texture(filename: &str, base_color: Vector3) -> TextureData {
         let size = 16i32;
         let mut pixels = Vec::with_capacity((size * size) as usize);
@@ -178,6 +261,14 @@ impl Texture {
                 let grain = (fx * 12.0 + fy * 10.0).sin() * 0.15;
                 0.85 + grain
             },
+            name if name.contains("glass") || name.contains("ice") => {
+                let streak = ((fx - fy) * 10.0).sin() * 0.04;
+                1.0 + streak
+            },
+            name if name.contains("water") => {
+                let ripple = (fx * 9.0 + (fy * 5.0).sin()).sin() * 0.08;
+                0.95 + ripple
+            },
             name if name.contains("netherrack") => {
                 let roughness = (fx * 14.0).cos() * (fy * 11.0).sin() * 0.25;
                 0.9 + roughness
@@ -192,7 +283,7 @@ impl Texture {
     #[inline(always)]
     pub fn get_nearest_color(&self, u: f32, v: f32) -> Vector3 {
         if self.data.pixels.len() <= 1 {
-            return self.data.fallback_color;
+            return srgb_to_linear_rgb(self.data.fallback_color);
         }
         
         let u_wrapped = u.fract().abs();
@@ -207,20 +298,16 @@ impl Texture {
         
         if index < self.data.pixels.len() {
             let color = self.data.pixels[index];
-            Vector3::new(
-                color.r as f32 / 255.0,
-                color.g as f32 / 255.0,
-                color.b as f32 / 255.0,
-            )
+            color_to_linear(color)
         } else {
-            self.data.fallback_color
+            srgb_to_linear_rgb(self.data.fallback_color)
         }
     }
 
     #[inline]
     pub fn get_bilinear_color(&self, u: f32, v: f32) -> Vector3 {
         if self.data.pixels.len() <= 1 {
-            return self.data.fallback_color;
+            return srgb_to_linear_rgb(self.data.fallback_color);
         }
         
         let u_wrapped = u.fract().abs();
@@ -241,13 +328,9 @@ impl Texture {
             let index = (y * self.data.width + x) as usize;
             if index < self.data.pixels.len() {
                 let color = self.data.pixels[index];
-                Vector3::new(
-                    color.r as f32 / 255.0,
-                    color.g as f32 / 255.0,
-                    color.b as f32 / 255.0,
-                )
+                color_to_linear(color)
             } else {
-                self.data.fallback_color
+                srgb_to_linear_rgb(self.data.fallback_color)
             }
         };
         
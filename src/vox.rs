@@ -0,0 +1,578 @@
+use raylib::prelude::Vector3;
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::material::{Material, MaterialId};
+use crate::shape::VoxelState;
+use crate::voxel::{Voxel, VoxelGrid};
+
+/// Voxels de un modelo: posición dentro del modelo e índice de color (1-255).
+struct VoxModel {
+    size: [i32; 3],
+    voxels: Vec<[u8; 4]>,
+}
+
+type Dict = HashMap<String, String>;
+
+enum Node {
+    Transform { child: i32, hidden: bool, transform: Transform },
+    Group { children: Vec<i32> },
+    Shape { models: Vec<i32> },
+}
+
+/// Rotación entera (matriz de permutación con signos) más traslación, como
+/// las guarda MagicaVoxel en los nodos `nTRN`.
+#[derive(Clone, Copy)]
+struct Transform {
+    rotation: [[i32; 3]; 3],
+    translation: [i32; 3],
+}
+
+impl Transform {
+    const IDENTITY: Transform = Transform {
+        rotation: [[1, 0, 0], [0, 1, 0], [0, 0, 1]],
+        translation: [0, 0, 0],
+    };
+
+    fn apply(&self, p: [i32; 3]) -> [i32; 3] {
+        let r = &self.rotation;
+        [
+            r[0][0] * p[0] + r[0][1] * p[1] + r[0][2] * p[2] + self.translation[0],
+            r[1][0] * p[0] + r[1][1] * p[1] + r[1][2] * p[2] + self.translation[1],
+            r[2][0] * p[0] + r[2][1] * p[1] + r[2][2] * p[2] + self.translation[2],
+        ]
+    }
+
+    /// `self` aplicada después de `inner`.
+    fn then(&self, inner: &Transform) -> Transform {
+        let mut rotation = [[0; 3]; 3];
+        for (i, row) in rotation.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = (0..3).map(|k| self.rotation[i][k] * inner.rotation[k][j]).sum();
+            }
+        }
+        Transform { rotation, translation: self.apply(inner.translation) }
+    }
+
+    /// Decodifica el byte `_r`: bits 0-1 y 2-3 indican la columna no nula de
+    /// las dos primeras filas y los bits 4-6 el signo de cada fila.
+    fn from_attributes(frame: &Dict) -> Result<Transform, String> {
+        let mut transform = Transform::IDENTITY;
+        if let Some(r) = frame.get("_r") {
+            let bits: u8 = r.trim().parse().map_err(|_| format!("rotación inválida `{}`", r))?;
+            let first = (bits & 0b11) as usize;
+            let second = ((bits >> 2) & 0b11) as usize;
+            if first > 2 || second > 2 || first == second {
+                return Err(format!("rotación inválida `{}`", r));
+            }
+            let third = 3 - first - second;
+            transform.rotation = [[0; 3]; 3];
+            for (row, col) in [first, second, third].into_iter().enumerate() {
+                let negative = bits & (1 << (4 + row)) != 0;
+                transform.rotation[row][col] = if negative { -1 } else { 1 };
+            }
+        }
+        if let Some(t) = frame.get("_t") {
+            let values: Vec<i32> = t
+                .split_whitespace()
+                .map(|v| v.parse().map_err(|_| format!("traslación inválida `{}`", t)))
+                .collect::<Result<_, _>>()?;
+            if values.len() != 3 {
+                return Err(format!("traslación inválida `{}`", t));
+            }
+            transform.translation = [values[0], values[1], values[2]];
+        }
+        Ok(transform)
+    }
+}
+
+struct VoxFile {
+    models: Vec<VoxModel>,
+    palette: [[u8; 4]; 256],
+    materials: HashMap<u8, Dict>,
+    nodes: HashMap<i32, Node>,
+}
+
+/// Lector secuencial de los bytes del archivo.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], String> {
+        if self.pos + n > self.bytes.len() {
+            return Err(format!("archivo truncado en el byte {}", self.pos));
+        }
+        let slice = &self.bytes[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(slice)
+    }
+
+    fn i32(&mut self) -> Result<i32, String> {
+        let b = self.take(4)?;
+        Ok(i32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn len(&mut self) -> Result<usize, String> {
+        let value = self.i32()?;
+        usize::try_from(value).map_err(|_| format!("longitud negativa en el byte {}", self.pos - 4))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let len = self.len()?;
+        Ok(String::from_utf8_lossy(self.take(len)?).into_owned())
+    }
+
+    fn dict(&mut self) -> Result<Dict, String> {
+        let count = self.len()?;
+        let mut dict = Dict::with_capacity(count.min(64));
+        for _ in 0..count {
+            let key = self.string()?;
+            let value = self.string()?;
+            dict.insert(key, value);
+        }
+        Ok(dict)
+    }
+}
+
+/// Carga un archivo MagicaVoxel en la grilla. La esquina mínima del modelo
+/// queda en `offset`; el eje Z de MagicaVoxel pasa a ser el eje Y vertical.
+/// Devuelve el número de voxels insertados.
+pub fn load_vox(path: &Path, grid: &mut VoxelGrid, offset: [i32; 3]) -> Result<usize, String> {
+    let bytes = std::fs::read(path).map_err(|e| format!("No se pudo leer {}: {}", path.display(), e))?;
+    load_bytes(&bytes, grid, offset).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// `load_vox` sobre el contenido ya leído del archivo.
+fn load_bytes(bytes: &[u8], grid: &mut VoxelGrid, offset: [i32; 3]) -> Result<usize, String> {
+    let file = parse(bytes)?;
+    let materials = build_materials(&file);
+
+    let mut placed: Vec<([i32; 3], u8)> = Vec::new();
+    for (model_index, transform) in file.instances() {
+        let Some(model) = file.models.get(model_index) else {
+            return Err(format!("el nodo usa el modelo {} que no existe", model_index));
+        };
+        // Igual que MagicaVoxel, el pivote de cada modelo es su centro.
+        let pivot = [model.size[0] / 2, model.size[1] / 2, model.size[2] / 2];
+        for v in &model.voxels {
+            let local = [v[0] as i32 - pivot[0], v[1] as i32 - pivot[1], v[2] as i32 - pivot[2]];
+            placed.push((transform.apply(local), v[3]));
+        }
+    }
+
+    if placed.is_empty() {
+        return Ok(0);
+    }
+
+    // Z arriba -> Y arriba, conservando la orientación: (x, y, z) -> (x, z, -y).
+    let to_grid = |p: [i32; 3]| [p[0], p[2], -p[1]];
+    let mut min = [i32::MAX; 3];
+    for (p, _) in &placed {
+        let g = to_grid(*p);
+        for axis in 0..3 {
+            min[axis] = min[axis].min(g[axis]);
+        }
+    }
+
+    // Solo pasan a la paleta de la grilla los colores que se usan.
+    let mut ids: Vec<Option<MaterialId>> = vec![None; materials.len()];
+    for (p, color_index) in &placed {
+        let g = to_grid(*p);
+        let index = *color_index as usize;
+        let material = match ids[index] {
+            Some(id) => id,
+            None => *ids[index].insert(grid.intern_material(materials[index].clone())?),
+        };
+        grid.insert_voxel(
+            g[0] - min[0] + offset[0],
+            g[1] - min[1] + offset[1],
+            g[2] - min[2] + offset[2],
+            Voxel { material, state: VoxelState::default() },
+        )?;
+    }
+    Ok(placed.len())
+}
+
+fn parse(bytes: &[u8]) -> Result<VoxFile, String> {
+    let mut reader = Reader { bytes, pos: 0 };
+    if reader.take(4)? != b"VOX " {
+        return Err("no es un archivo .vox de MagicaVoxel".to_string());
+    }
+    let _version = reader.i32()?;
+
+    if reader.take(4)? != b"MAIN" {
+        return Err("falta el chunk MAIN".to_string());
+    }
+    let main_content = reader.len()?;
+    let _main_children = reader.len()?;
+    reader.take(main_content)?;
+
+    let mut file = VoxFile {
+        models: Vec::new(),
+        palette: default_palette(),
+        materials: HashMap::new(),
+        nodes: HashMap::new(),
+    };
+    let mut pending_size: Option<[i32; 3]> = None;
+
+    while reader.pos < bytes.len() {
+        let id: [u8; 4] = reader.take(4)?.try_into().unwrap();
+        let content_len = reader.len()?;
+        let children_len = reader.len()?;
+        let content = reader.take(content_len)?;
+        reader.take(children_len)?;
+        let mut chunk = Reader { bytes: content, pos: 0 };
+
+        match &id {
+            b"SIZE" => pending_size = Some([chunk.i32()?, chunk.i32()?, chunk.i32()?]),
+            b"XYZI" => {
+                let size = pending_size.take().ok_or("chunk XYZI sin SIZE previo")?;
+                let count = chunk.len()?;
+                let voxels = chunk
+                    .take(count * 4)?
+                    .chunks_exact(4)
+                    .map(|v| [v[0], v[1], v[2], v[3]])
+                    .filter(|v| v[3] != 0)
+                    .collect();
+                file.models.push(VoxModel { size, voxels });
+            }
+            b"RGBA" => {
+                // La entrada i del chunk corresponde al índice de color i + 1.
+                let data = chunk.take(256 * 4)?;
+                for i in 0..255 {
+                    file.palette[i + 1].copy_from_slice(&data[i * 4..i * 4 + 4]);
+                }
+            }
+            b"MATL" => {
+                let id = chunk.i32()?;
+                let dict = chunk.dict()?;
+                if (1..=255).contains(&id) {
+                    file.materials.insert(id as u8, dict);
+                }
+            }
+            b"nTRN" => {
+                let node_id = chunk.i32()?;
+                let attributes = chunk.dict()?;
+                let child = chunk.i32()?;
+                let _reserved = chunk.i32()?;
+                let _layer = chunk.i32()?;
+                let frames = chunk.len()?;
+                let mut transform = Transform::IDENTITY;
+                for frame in 0..frames {
+                    let dict = chunk.dict()?;
+                    if frame == 0 {
+                        transform = Transform::from_attributes(&dict)?;
+                    }
+                }
+                let hidden = attributes.get("_hidden").map(|h| h == "1").unwrap_or(false);
+                file.nodes.insert(node_id, Node::Transform { child, hidden, transform });
+            }
+            b"nGRP" => {
+                let node_id = chunk.i32()?;
+                let _attributes = chunk.dict()?;
+                let count = chunk.len()?;
+                let children = (0..count).map(|_| chunk.i32()).collect::<Result<_, _>>()?;
+                file.nodes.insert(node_id, Node::Group { children });
+            }
+            b"nSHP" => {
+                let node_id = chunk.i32()?;
+                let _attributes = chunk.dict()?;
+                let count = chunk.len()?;
+                let mut models = Vec::with_capacity(count.min(64));
+                for _ in 0..count {
+                    models.push(chunk.i32()?);
+                    let _model_attributes = chunk.dict()?;
+                }
+                file.nodes.insert(node_id, Node::Shape { models });
+            }
+            // PACK, LAYR, rOBJ, rCAM, NOTE, IMAP y otros no afectan a la geometría.
+            _ => {}
+        }
+    }
+
+    Ok(file)
+}
+
+impl VoxFile {
+    /// Cada modelo a colocar con su transformación acumulada. Sin grafo de
+    /// escena (archivos antiguos) todos los modelos quedan en el origen.
+    fn instances(&self) -> Vec<(usize, Transform)> {
+        let mut out = Vec::new();
+        if self.nodes.contains_key(&0) {
+            self.walk(0, Transform::IDENTITY, &mut out, 0);
+        } else {
+            out.extend((0..self.models.len()).map(|i| (i, Transform::IDENTITY)));
+        }
+        out
+    }
+
+    fn walk(&self, node_id: i32, parent: Transform, out: &mut Vec<(usize, Transform)>, depth: u32) {
+        // Protege contra grafos con ciclos en archivos corruptos.
+        if depth > 64 {
+            return;
+        }
+        match self.nodes.get(&node_id) {
+            Some(Node::Transform { child, hidden: false, transform }) => {
+                self.walk(*child, parent.then(transform), out, depth + 1);
+            }
+            Some(Node::Group { children }) => {
+                for child in children {
+                    self.walk(*child, parent, out, depth + 1);
+                }
+            }
+            Some(Node::Shape { models }) => {
+                out.extend(models.iter().filter_map(|m| usize::try_from(*m).ok()).map(|m| (m, parent)));
+            }
+            Some(Node::Transform { hidden: true, .. }) | None => {}
+        }
+    }
+}
+
+/// Un material por índice de color, combinando el color de la paleta con el
+/// chunk `MATL` correspondiente (metal, vidrio, emisivo).
+fn build_materials(file: &VoxFile) -> Vec<Material> {
+    (0..256)
+        .map(|index| {
+            let [r, g, b, _] = file.palette[index];
+            let color = Vector3::new(r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0);
+            let mut material = Material::new(color, 10.0, [0.9, 0.1, 0.0, 0.0], 1.0);
+            material.roughness = 0.8;
+
+            let Some(dict) = file.materials.get(&(index as u8)) else {
+                return material;
+            };
+            // Los valores que no son números finitos (`inf`, `nan`) se ignoran.
+            let value = |key: &str, default: f32| {
+                dict.get(key).and_then(|v| v.parse::<f32>().ok()).filter(|v| v.is_finite()).unwrap_or(default)
+            };
+
+            match dict.get("_type").map(String::as_str) {
+                Some("_metal") => {
+                    let weight = value("_weight", 1.0).clamp(0.0, 1.0);
+                    material.metallic = weight;
+                    material.roughness = value("_rough", 0.2).clamp(0.0, 1.0);
+                    material.albedo = [0.3, 0.4, 0.3 * weight, 0.0];
+                    material.specular = 120.0;
+                }
+                Some("_glass") | Some("_blend") => {
+                    // MagicaVoxel guarda el índice de refracción menos uno.
+                    let ior = value("_ior", 0.5);
+                    material.refractive_index = if ior < 1.0 { ior + 1.0 } else { ior };
+                    material.roughness = value("_rough", 0.05).clamp(0.0, 1.0);
+                    material.albedo = [0.1, 0.5, 0.0, value("_weight", 1.0).clamp(0.0, 1.0) * 0.9];
+                    material.specular = 125.0;
+                }
+                Some("_emit") => {
+                    let flux = value("_flux", 0.0).max(0.0);
+                    material.emission = value("_weight", 1.0).max(0.0) * (1.0 + flux);
+                }
+                _ => {}
+            }
+            material
+        })
+        .collect()
+}
+
+/// Paleta que usa MagicaVoxel cuando el archivo no trae chunk `RGBA`: un cubo
+/// de 6x6x6 colores seguido de rampas de rojo, verde, azul y gris.
+fn default_palette() -> [[u8; 4]; 256] {
+    const CUBE: [u8; 6] = [0xff, 0xcc, 0x99, 0x66, 0x33, 0x00];
+    const RAMP: [u8; 10] = [0xee, 0xdd, 0xbb, 0xaa, 0x88, 0x77, 0x55, 0x44, 0x22, 0x11];
+
+    let mut palette = [[0u8; 4]; 256];
+    let mut index = 1;
+    for r in CUBE {
+        for g in CUBE {
+            for b in CUBE {
+                if index < 216 {
+                    palette[index] = [r, g, b, 0xff];
+                    index += 1;
+                }
+            }
+        }
+    }
+    for channel in 0..4 {
+        for level in RAMP {
+            palette[index] = match channel {
+                0 => [level, 0, 0, 0xff],
+                1 => [0, level, 0, 0xff],
+                2 => [0, 0, level, 0xff],
+                _ => [level, level, level, 0xff],
+            };
+            index += 1;
+        }
+    }
+    palette
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ints(values: &[i32]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_le_bytes()).collect()
+    }
+
+    fn string(s: &str) -> Vec<u8> {
+        let mut out = ints(&[s.len() as i32]);
+        out.extend_from_slice(s.as_bytes());
+        out
+    }
+
+    fn dict(pairs: &[(&str, &str)]) -> Vec<u8> {
+        let mut out = ints(&[pairs.len() as i32]);
+        for (key, value) in pairs {
+            out.extend(string(key));
+            out.extend(string(value));
+        }
+        out
+    }
+
+    fn chunk(id: &[u8; 4], content: &[u8]) -> Vec<u8> {
+        let mut out = id.to_vec();
+        out.extend(ints(&[content.len() as i32, 0]));
+        out.extend_from_slice(content);
+        out
+    }
+
+    fn model(size: [i32; 3], voxels: &[[u8; 4]]) -> Vec<u8> {
+        let mut xyzi = ints(&[voxels.len() as i32]);
+        xyzi.extend(voxels.iter().flatten());
+        let mut out = chunk(b"SIZE", &ints(&size));
+        out.extend(chunk(b"XYZI", &xyzi));
+        out
+    }
+
+    fn transform(id: i32, attributes: &[(&str, &str)], child: i32, frame: &[(&str, &str)]) -> Vec<u8> {
+        let mut content = ints(&[id]);
+        content.extend(dict(attributes));
+        content.extend(ints(&[child, -1, 0, 1]));
+        content.extend(dict(frame));
+        chunk(b"nTRN", &content)
+    }
+
+    fn group(id: i32, children: &[i32]) -> Vec<u8> {
+        let mut content = ints(&[id]);
+        content.extend(dict(&[]));
+        content.extend(ints(&[children.len() as i32]));
+        content.extend(ints(children));
+        chunk(b"nGRP", &content)
+    }
+
+    fn shape(id: i32, model: i32) -> Vec<u8> {
+        let mut content = ints(&[id]);
+        content.extend(dict(&[]));
+        content.extend(ints(&[1, model]));
+        content.extend(dict(&[]));
+        chunk(b"nSHP", &content)
+    }
+
+    fn vox(chunks: &[Vec<u8>]) -> Vec<u8> {
+        let children: Vec<u8> = chunks.concat();
+        let mut out = b"VOX ".to_vec();
+        out.extend(ints(&[150]));
+        out.extend_from_slice(b"MAIN");
+        out.extend(ints(&[0, children.len() as i32]));
+        out.extend(children);
+        out
+    }
+
+    fn load(bytes: &[u8]) -> Result<VoxelGrid, String> {
+        let mut grid = VoxelGrid::new();
+        load_bytes(bytes, &mut grid, [0, 0, 0])?;
+        Ok(grid)
+    }
+
+    fn load_error(bytes: &[u8]) -> String {
+        load(bytes).err().expect("el archivo debería dar error")
+    }
+
+    #[test]
+    fn model_without_scene_graph_is_turned_z_up() {
+        let grid = load(&vox(&[model([3, 3, 3], &[[0, 0, 0, 1], [2, 0, 1, 1]])])).unwrap();
+        assert_eq!(grid.len(), 2);
+        assert!(grid.get(0, 0, 0).is_some());
+        // La Z de MagicaVoxel queda en Y y su Y en -Z.
+        assert!(grid.get(2, 1, 0).is_some());
+    }
+
+    #[test]
+    fn palette_and_materials_are_applied() {
+        let mut palette = vec![0u8; 256 * 4];
+        palette[..4].copy_from_slice(&[255, 0, 0, 255]);
+        let mut matl = ints(&[1]);
+        matl.extend(dict(&[("_type", "_emit"), ("_weight", "2.0")]));
+        let bytes = vox(&[model([1, 1, 1], &[[0, 0, 0, 1]]), chunk(b"RGBA", &palette), chunk(b"MATL", &matl)]);
+
+        let grid = load(&bytes).unwrap();
+        let material = grid.material(grid.get(0, 0, 0).unwrap().material);
+        assert_eq!(material.diffuse, Vector3::new(1.0, 0.0, 0.0));
+        assert_eq!(material.emission, 2.0);
+    }
+
+    #[test]
+    fn non_finite_material_values_are_ignored() {
+        let mut matl = ints(&[1]);
+        matl.extend(dict(&[("_type", "_emit"), ("_weight", "nan"), ("_flux", "inf")]));
+        let bytes = vox(&[model([1, 1, 1], &[[0, 0, 0, 1]]), chunk(b"MATL", &matl)]);
+
+        let grid = load(&bytes).unwrap();
+        assert_eq!(grid.material(grid.get(0, 0, 0).unwrap().material).emission, 1.0);
+    }
+
+    #[test]
+    fn scene_graph_places_visible_shapes() {
+        let bytes = vox(&[
+            model([1, 1, 1], &[[0, 0, 0, 1]]),
+            transform(0, &[], 1, &[]),
+            group(1, &[2, 4, 6]),
+            transform(2, &[], 3, &[("_t", "10 0 0")]),
+            shape(3, 0),
+            transform(4, &[], 3, &[]),
+            transform(6, &[("_hidden", "1")], 3, &[("_t", "-10 0 0")]),
+        ]);
+        let grid = load(&bytes).unwrap();
+        assert_eq!(grid.len(), 2);
+        assert!(grid.get(0, 0, 0).is_some());
+        assert!(grid.get(10, 0, 0).is_some());
+    }
+
+    #[test]
+    fn rotation_byte_is_decoded() {
+        // Fila 0 -> columna 1 con signo negativo, fila 1 -> columna 0: 90° sobre Z.
+        let frame = Dict::from([("_r".to_string(), "17".to_string()), ("_t".to_string(), "1 2 3".to_string())]);
+        let transform = Transform::from_attributes(&frame).unwrap();
+        assert_eq!(transform.apply([1, 0, 0]), [1, 3, 3]);
+        assert_eq!(transform.apply([0, 1, 0]), [0, 2, 3]);
+        assert_eq!(transform.apply([0, 0, 1]), [1, 2, 4]);
+
+        for bad in ["0", "3", "x"] {
+            let frame = Dict::from([("_r".to_string(), bad.to_string())]);
+            assert!(Transform::from_attributes(&frame).is_err(), "_r = {}", bad);
+        }
+    }
+
+    #[test]
+    fn malformed_files_are_errors() {
+        assert!(load(b"PNG\0").is_err());
+
+        let bytes = vox(&[model([1, 1, 1], &[[0, 0, 0, 1]])]);
+        let err = load_error(&bytes[..bytes.len() - 2]);
+        assert!(err.contains("truncado"), "{}", err);
+
+        let mut negative = vox(&[]);
+        negative.extend_from_slice(b"SIZE");
+        negative.extend(ints(&[-1, 0]));
+        let err = load_error(&negative);
+        assert!(err.contains("longitud negativa"), "{}", err);
+
+        let err = load_error(&vox(&[chunk(b"XYZI", &ints(&[0]))]));
+        assert!(err.contains("SIZE"), "{}", err);
+
+        let bytes = vox(&[model([1, 1, 1], &[[0, 0, 0, 1]]), transform(0, &[], 1, &[]), shape(1, 3)]);
+        let err = load_error(&bytes);
+        assert!(err.contains("no existe"), "{}", err);
+    }
+}
@@ -1,11 +1,235 @@
 use std::collections::HashMap;
 use raylib::prelude::Vector3;
-use crate::material::Material;
+use raylib::prelude::Color;
+use crate::light::{Light, MIN_LIGHT_EMISSION};
+use crate::material::{Material, MaterialId, MaterialPalette};
 use crate::ray_intersect::Intersect;
+use crate::shape::{Axis, Facing, VoxelState};
 
+/// Contenido de una celda: el material y el estado propio de ese voxel
+/// (orientación, mitad, eje...).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Voxel {
+    pub material: MaterialId,
+    pub state: VoxelState,
+}
+
+// Jerarquía de ocupación, de 64 hijos por nivel: una región de 64³ celdas
+// tiene 4³ chunks de 16³, cada chunk 4³ ladrillos de 4³ celdas. El recorrido
+// salta de una vez cualquier región, chunk o ladrillo vacío.
+const BRICK_BITS: i32 = 2;
+const CHUNK_BITS: i32 = 4;
+const REGION_BITS: i32 = 6;
+const CHUNK_MASK: i32 = (1 << CHUNK_BITS) - 1;
+const CHUNK_VOLUME: usize = 1 << (3 * CHUNK_BITS);
+
+/// Bit de un hijo dentro de la máscara de 64 de su padre, siendo `bits` el
+/// tamaño del hijo.
+#[inline(always)]
+fn child_bit(x: i32, y: i32, z: i32, bits: i32) -> u64 {
+    let index = ((x >> bits) & 3) | ((z >> bits) & 3) << 2 | ((y >> bits) & 3) << 4;
+    1 << index
+}
+
+/// Bloque denso de 16³ celdas. Cada celda guarda un índice a la paleta de la
+/// grilla y la máscara de ocupación dice cuáles tienen voxel.
+#[derive(Clone)]
+struct Chunk {
+    occupied: [u64; CHUNK_VOLUME / 64],
+    /// Ladrillos de 4³ con algún voxel.
+    bricks: u64,
+    cells: Box<[u16; CHUNK_VOLUME]>,
+}
+
+impl Chunk {
+    fn new() -> Self {
+        Chunk {
+            occupied: [0; CHUNK_VOLUME / 64],
+            bricks: 0,
+            cells: Box::new([0; CHUNK_VOLUME]),
+        }
+    }
+
+    #[inline(always)]
+    fn key(x: i32, y: i32, z: i32) -> (i32, i32, i32) {
+        (x >> CHUNK_BITS, y >> CHUNK_BITS, z >> CHUNK_BITS)
+    }
+
+    /// Posición de la celda dentro del chunk, con X variando más rápido.
+    #[inline(always)]
+    fn local(x: i32, y: i32, z: i32) -> usize {
+        ((x & CHUNK_MASK) | (z & CHUNK_MASK) << CHUNK_BITS | (y & CHUNK_MASK) << (2 * CHUNK_BITS)) as usize
+    }
+
+    #[inline(always)]
+    fn get(&self, i: usize) -> Option<u16> {
+        if self.occupied[i >> 6] & (1 << (i & 63)) != 0 {
+            Some(self.cells[i])
+        } else {
+            None
+        }
+    }
+
+    /// Inserta o reemplaza; devuelve si la celda estaba vacía.
+    fn set(&mut self, i: usize, index: u16) -> bool {
+        let was_empty = self.occupied[i >> 6] & (1 << (i & 63)) == 0;
+        self.occupied[i >> 6] |= 1 << (i & 63);
+        self.cells[i] = index;
+        was_empty
+    }
+}
+
+/// Recorrido celda a celda (Amanatides & Woo). Las distancias se miden desde
+/// el punto de partida.
+pub struct Dda {
+    pub cell: [i32; 3],
+    step: [i32; 3],
+    /// Distancia al siguiente cruce de cada eje; siempre es `crossing(axis,
+    /// crossed[axis])`, para que `advance` y `skip` den los mismos valores.
+    t_max: [f32; 3],
+    t_delta: [f32; 3],
+    /// Distancia al primer cruce de cada eje.
+    t_first: [f32; 3],
+    /// Caras cruzadas en cada eje desde el punto de partida.
+    crossed: [i32; 3],
+    /// Eje de la última cara cruzada; 3 mientras el rayo siga en la primera celda.
+    pub face: usize,
+}
+
+impl Dda {
+    pub fn new(start: &Vector3, rd: &Vector3) -> Self {
+        let start = [start.x, start.y, start.z];
+        let dir = [rd.x, rd.y, rd.z];
+        let mut dda = Dda {
+            cell: [0; 3],
+            step: [0; 3],
+            t_max: [f32::INFINITY; 3],
+            t_delta: [f32::INFINITY; 3],
+            t_first: [f32::INFINITY; 3],
+            crossed: [0; 3],
+            face: 3,
+        };
+        for axis in 0..3 {
+            dda.cell[axis] = start[axis].floor() as i32;
+            dda.step[axis] = if dir[axis] > 0.0 { 1 } else { -1 };
+            if dir[axis].abs() >= 1e-6 {
+                let next_boundary = if dir[axis] > 0.0 { dda.cell[axis] as f32 + 1.0 } else { dda.cell[axis] as f32 };
+                dda.t_delta[axis] = 1.0 / dir[axis].abs();
+                dda.t_first[axis] = (next_boundary - start[axis]).abs() / dir[axis].abs();
+                dda.t_max[axis] = dda.t_first[axis];
+            }
+        }
+        dda
+    }
+
+    /// Distancia al cruce número `n` (desde 0) del eje. Solo para ejes que el
+    /// rayo cruza: en los paralelos `t_delta` es infinito.
+    #[inline(always)]
+    fn crossing(&self, axis: usize, n: i32) -> f32 {
+        self.t_first[axis] + n as f32 * self.t_delta[axis]
+    }
+
+    /// Cruza `n` caras del eje.
+    #[inline(always)]
+    fn cross(&mut self, axis: usize, n: i32) {
+        self.cell[axis] += self.step[axis] * n;
+        self.crossed[axis] += n;
+        self.t_max[axis] = self.crossing(axis, self.crossed[axis]);
+    }
+
+    /// Pasa a la celda vecina por la cara más cercana. En un empate gana el
+    /// eje menor.
+    #[inline(always)]
+    pub fn advance(&mut self) {
+        let axis = if self.t_max[0] <= self.t_max[1] && self.t_max[0] <= self.t_max[2] {
+            0
+        } else if self.t_max[1] <= self.t_max[2] {
+            1
+        } else {
+            2
+        };
+        self.cross(axis, 1);
+        self.face = axis;
+    }
+
+    /// Sale del bloque alineado de `2^bits` celdas de lado que contiene la
+    /// celda actual sin visitar las demás: queda en la primera celda de fuera,
+    /// igual que tras avanzar celda a celda.
+    pub fn skip(&mut self, bits: i32) {
+        let mask = (1 << bits) - 1;
+        let mut remaining = [0; 3];
+        let mut t_exit = [f32::INFINITY; 3];
+        for axis in 0..3 {
+            // Celdas hasta la última del bloque en este eje.
+            let offset = self.cell[axis] & mask;
+            remaining[axis] = if self.step[axis] > 0 { mask - offset } else { offset };
+            if self.t_delta[axis].is_finite() {
+                t_exit[axis] = self.crossing(axis, self.crossed[axis] + remaining[axis]);
+            }
+        }
+        let exit = if t_exit[0] <= t_exit[1] && t_exit[0] <= t_exit[2] {
+            0
+        } else if t_exit[1] <= t_exit[2] {
+            1
+        } else {
+            2
+        };
+        for (axis, &remaining) in remaining.iter().enumerate() {
+            let crossings = if axis == exit {
+                remaining + 1
+            } else if self.t_delta[axis].is_finite() {
+                // Cruces que `advance` hace antes de salir: los más cercanos
+                // que la salida y, empatados con ella, los de un eje menor.
+                let before = |k: i32| {
+                    let t = self.crossing(axis, self.crossed[axis] + k);
+                    t < t_exit[exit] || (t == t_exit[exit] && axis < exit)
+                };
+                // La división da el número salvo redondeo; se corrige con la
+                // misma comparación que haría `advance`.
+                let estimate = ((t_exit[exit] - self.t_max[axis]) / self.t_delta[axis]).ceil();
+                let mut k = if estimate > 0.0 { (estimate as i32).min(remaining) } else { 0 };
+                while k > 0 && !before(k - 1) {
+                    k -= 1;
+                }
+                while k < remaining && before(k) {
+                    k += 1;
+                }
+                k
+            } else {
+                0
+            };
+            if crossings > 0 {
+                self.cross(axis, crossings);
+            }
+        }
+        self.face = exit;
+    }
+
+    /// Distancia a la que el rayo entró en la celda actual.
+    #[inline(always)]
+    pub fn t_entered(&self) -> f32 {
+        if self.face == 3 {
+            0.0
+        } else {
+            self.t_max[self.face] - self.t_delta[self.face]
+        }
+    }
+}
+
+/// Grilla de voxels guardada en chunks de 16³. Los voxels distintos
+/// (material + estado) se guardan una sola vez en `palette` y las celdas solo
+/// tienen su índice; los materiales, a su vez, están en `materials`. Los
+/// índices son de 16 bits, así que insertar más de 65536 voxels o materiales
+/// distintos es un error.
 #[derive(Clone)]
 pub struct VoxelGrid {
-    pub cells: HashMap<(i32, i32, i32), Material>,
+    /// Chunks ocupados de cada región de 64³.
+    regions: HashMap<(i32, i32, i32), u64>,
+    chunks: HashMap<(i32, i32, i32), Chunk>,
+    palette: Vec<Voxel>,
+    palette_index: HashMap<Voxel, u16>,
+    materials: MaterialPalette,
+    count: usize,
     bounds_min: Vector3,
     bounds_max: Vector3,
     has_bounds: bool,
@@ -17,7 +241,12 @@ pub struct VoxelGrid {
 impl VoxelGrid {
     pub fn new() -> Self {
         Self {
-            cells: HashMap::new(),
+            regions: HashMap::new(),
+            chunks: HashMap::new(),
+            palette: Vec::new(),
+            palette_index: HashMap::new(),
+            materials: MaterialPalette::new(),
+            count: 0,
             bounds_min: Vector3::new(0.0, 0.0, 0.0),
             bounds_max: Vector3::new(0.0, 0.0, 0.0),
             has_bounds: false,
@@ -28,8 +257,50 @@ impl VoxelGrid {
     }
 
     #[inline]
-    pub fn insert(&mut self, x: i32, y: i32, z: i32, m: Material) {
-        self.cells.insert((x, y, z), m);
+    pub fn insert(&mut self, x: i32, y: i32, z: i32, m: Material) -> Result<(), String> {
+        self.insert_with_state(x, y, z, m, VoxelState::default())
+    }
+
+    pub fn insert_with_state(&mut self, x: i32, y: i32, z: i32, m: Material, state: VoxelState) -> Result<(), String> {
+        let material = self.materials.intern(m)?;
+        self.insert_voxel(x, y, z, Voxel { material, state })
+    }
+
+    /// Material de la paleta de la grilla.
+    #[inline]
+    pub fn material(&self, id: MaterialId) -> &Material {
+        self.materials.get(id)
+    }
+
+    /// Materiales de la paleta, en el orden de sus `MaterialId`.
+    pub fn materials(&self) -> impl Iterator<Item = &Material> + '_ {
+        self.materials.iter()
+    }
+
+    /// Añade un material a la paleta sin colocarlo. Los cargadores lo usan
+    /// una vez por material y después insertan con `insert_voxel`.
+    pub fn intern_material(&mut self, material: Material) -> Result<MaterialId, String> {
+        self.materials.intern(material)
+    }
+
+    pub fn insert_voxel(&mut self, x: i32, y: i32, z: i32, voxel: Voxel) -> Result<(), String> {
+        let index = match self.palette_index.get(&voxel) {
+            Some(&index) => index,
+            None => {
+                let index = u16::try_from(self.palette.len())
+                    .map_err(|_| "más de 65536 voxels distintos en la grilla".to_string())?;
+                self.palette.push(voxel);
+                self.palette_index.insert(voxel, index);
+                index
+            }
+        };
+        let chunk = self.chunks.entry(Chunk::key(x, y, z)).or_insert_with(Chunk::new);
+        if chunk.set(Chunk::local(x, y, z), index) {
+            self.count += 1;
+        }
+        chunk.bricks |= child_bit(x, y, z, BRICK_BITS);
+        *self.regions.entry((x >> REGION_BITS, y >> REGION_BITS, z >> REGION_BITS)).or_default() |=
+            child_bit(x, y, z, CHUNK_BITS);
         self.bounds_cached = false;
         
         if !self.has_bounds {
@@ -44,6 +315,35 @@ impl VoxelGrid {
             if (y as f32) > self.bounds_max.y { self.bounds_max.y = y as f32; }
             if (z as f32) > self.bounds_max.z { self.bounds_max.z = z as f32; }
         }
+        Ok(())
+    }
+
+    #[inline]
+    pub fn get(&self, x: i32, y: i32, z: i32) -> Option<&Voxel> {
+        let chunk = self.chunks.get(&Chunk::key(x, y, z))?;
+        chunk.get(Chunk::local(x, y, z)).map(|index| &self.palette[index as usize])
+    }
+
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Todos los voxels con su celda, sin orden definido.
+    pub fn iter(&self) -> impl Iterator<Item = ((i32, i32, i32), &Voxel)> + '_ {
+        self.chunks.iter().flat_map(move |(&(kx, ky, kz), chunk)| {
+            (0..CHUNK_VOLUME).filter_map(move |i| {
+                let index = chunk.get(i)?;
+                let i = i as i32;
+                let x = (kx << CHUNK_BITS) | (i & CHUNK_MASK);
+                let z = (kz << CHUNK_BITS) | ((i >> CHUNK_BITS) & CHUNK_MASK);
+                let y = (ky << CHUNK_BITS) | (i >> (2 * CHUNK_BITS));
+                Some(((x, y, z), &self.palette[index as usize]))
+            })
+        })
     }
 
     fn update_cached_values(&mut self) {
@@ -64,7 +364,7 @@ impl VoxelGrid {
 
     /// Devuelve (min, max) de la estructura.
     pub fn calculate_bounds(&self) -> (Vector3, Vector3) {
-        if !self.has_bounds || self.cells.is_empty() {
+        if !self.has_bounds || self.is_empty() {
             return (Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, 0.0));
         }
         
@@ -81,6 +381,14 @@ impl VoxelGrid {
         (min, max)
     }
 
+    /// Caja justa de las celdas ocupadas, sin el margen de `calculate_bounds`.
+    pub fn cell_bounds(&self) -> (Vector3, Vector3) {
+        if !self.has_bounds || self.is_empty() {
+            return (Vector3::zero(), Vector3::zero());
+        }
+        (self.bounds_min, self.bounds_max + Vector3::new(1.0, 1.0, 1.0))
+    }
+
     pub fn get_center(&mut self) -> Vector3 {
         self.update_cached_values();
         self.center
@@ -91,11 +399,57 @@ impl VoxelGrid {
         self.radius
     }
 
-    pub fn intersect_ray(&self, ro: &Vector3, rd: &Vector3, t_max: f32, max_steps: u32) -> Intersect {
-        if self.cells.is_empty() {
-            return Intersect::empty();
+    /// Convierte cada voxel que emite al menos `MIN_LIGHT_EMISSION` y tiene
+    /// alguna cara expuesta en una luz de área. `strength` escala la emisión
+    /// del material a intensidad de luz.
+    pub fn emissive_lights(&self, strength: f32) -> Vec<Light> {
+        const NEIGHBOURS: [(i32, i32, i32); 6] = [
+            (1, 0, 0), (-1, 0, 0), (0, 1, 0), (0, -1, 0), (0, 0, 1), (0, 0, -1),
+        ];
+
+        if strength <= 0.0 {
+            return Vec::new();
         }
 
+        let mut lights = Vec::new();
+        for ((x, y, z), voxel) in self.iter() {
+            let mat = self.material(voxel.material);
+            if mat.emission < MIN_LIGHT_EMISSION {
+                continue;
+            }
+
+            let mut faces = [Vector3::zero(); 6];
+            for (face, (dx, dy, dz)) in faces.iter_mut().zip(NEIGHBOURS) {
+                let exposed = match self.get(x + dx, y + dy, z + dz) {
+                    Some(neighbour) => {
+                        let neighbour = self.material(neighbour.material);
+                        neighbour.is_transparent() || !neighbour.shape.is_full()
+                    }
+                    None => true,
+                };
+                if exposed {
+                    *face = Vector3::new(dx as f32, dy as f32, dz as f32);
+                }
+            }
+            if faces.iter().all(|n| *n == Vector3::zero()) {
+                continue;
+            }
+
+            let color = Color::new(
+                (mat.diffuse.x.clamp(0.0, 1.0) * 255.0) as u8,
+                (mat.diffuse.y.clamp(0.0, 1.0) * 255.0) as u8,
+                (mat.diffuse.z.clamp(0.0, 1.0) * 255.0) as u8,
+                255,
+            );
+            let center = Vector3::new(x as f32 + 0.5, y as f32 + 0.5, z as f32 + 0.5);
+            lights.push(Light::voxel(center, faces, color, mat.emission * strength));
+        }
+        lights
+    }
+
+    /// Distancia a la que el rayo entra en la caja de la estructura (0 si
+    /// empieza dentro), o `None` si no la toca antes de `t_max`.
+    pub fn entry_distance(&self, ro: &Vector3, rd: &Vector3, t_max: f32) -> Option<f32> {
         let (bmin, bmax) = self.calculate_bounds();
 
         let (mut tmin, mut tmax_box) = (0.0f32, t_max);
@@ -108,7 +462,7 @@ impl VoxelGrid {
             
             if rd_i.abs() < 1e-6 {
                 if ro_i < min_i || ro_i > max_i {
-                    return Intersect::empty();
+                    return None;
                 }
             } else {
                 let inv_d = 1.0 / rd_i;
@@ -124,129 +478,214 @@ impl VoxelGrid {
                     tmax_box = t1; 
                 }
                 if tmax_box < tmin {
-                    return Intersect::empty();
+                    return None;
                 }
             }
         }
+        Some(tmin.max(0.0))
+    }
+
+    /// Celdas extremas que puede visitar el recorrido.
+    pub fn cell_limits(&self) -> ([i32; 3], [i32; 3]) {
+        let (bmin, bmax) = self.calculate_bounds();
+        (
+            [bmin.x.floor() as i32, bmin.y.floor() as i32, bmin.z.floor() as i32],
+            [bmax.x.ceil() as i32, bmax.y.ceil() as i32, bmax.z.ceil() as i32],
+        )
+    }
+
+    /// Primer voxel que toca el rayo antes de `t_max`. Se recorre hasta salir
+    /// de la estructura, sin límite de pasos: las regiones, chunks y ladrillos
+    /// vacíos se cruzan de un salto.
+    pub fn intersect_ray(&self, ro: &Vector3, rd: &Vector3, t_max: f32) -> Intersect {
+        if self.is_empty() {
+            return Intersect::empty();
+        }
+        let Some(t_entry) = self.entry_distance(ro, rd, t_max) else {
+            return Intersect::empty();
+        };
 
-        let t_entry = if tmin < 0.0 { 0.0 } else { tmin };
         let start = Vector3::new(
             ro.x + rd.x * t_entry, 
             ro.y + rd.y * t_entry, 
             ro.z + rd.z * t_entry
         );
-        
-        let mut cx = start.x.floor() as i32;
-        let mut cy = start.y.floor() as i32;
-        let mut cz = start.z.floor() as i32;
+        let mut dda = Dda::new(&start, rd);
+        let (limit_min, limit_max) = self.cell_limits();
+        let outside = |c: &[i32; 3]| (0..3).any(|a| c[a] < limit_min[a] || c[a] > limit_max[a]);
 
-        let step_x = if rd.x > 0.0 { 1 } else { -1 };
-        let step_y = if rd.y > 0.0 { 1 } else { -1 };
-        let step_z = if rd.z > 0.0 { 1 } else { -1 };
+        // La región y el chunk actuales se buscan solo al cruzar a otros.
+        let mut region_key = None;
+        let mut region = 0u64;
+        let mut chunk_key = None;
+        let mut chunk: Option<&Chunk> = None;
 
-        let t_delta_x = if rd.x.abs() < 1e-6 { f32::INFINITY } else { 1.0 / rd.x.abs() };
-        let t_delta_y = if rd.y.abs() < 1e-6 { f32::INFINITY } else { 1.0 / rd.y.abs() };
-        let t_delta_z = if rd.z.abs() < 1e-6 { f32::INFINITY } else { 1.0 / rd.z.abs() };
+        while !outside(&dda.cell) && dda.t_entered() + t_entry <= t_max {
+            let [cx, cy, cz] = dda.cell;
+            let key = (cx >> REGION_BITS, cy >> REGION_BITS, cz >> REGION_BITS);
+            if region_key != Some(key) {
+                region_key = Some(key);
+                region = self.regions.get(&key).copied().unwrap_or(0);
+            }
+            if region == 0 {
+                dda.skip(REGION_BITS);
+                continue;
+            }
+            if region & child_bit(cx, cy, cz, CHUNK_BITS) == 0 {
+                dda.skip(CHUNK_BITS);
+                continue;
+            }
+            let key = Chunk::key(cx, cy, cz);
+            if chunk_key != Some(key) {
+                chunk_key = Some(key);
+                chunk = self.chunks.get(&key);
+            }
+            let Some(chunk) = chunk else {
+                dda.skip(CHUNK_BITS);
+                continue;
+            };
+            if chunk.bricks & child_bit(cx, cy, cz, BRICK_BITS) == 0 {
+                dda.skip(BRICK_BITS);
+                continue;
+            }
 
-        let next_boundary_x = if rd.x > 0.0 { cx as f32 + 1.0 } else { cx as f32 };
-        let next_boundary_y = if rd.y > 0.0 { cy as f32 + 1.0 } else { cy as f32 };
-        let next_boundary_z = if rd.z > 0.0 { cz as f32 + 1.0 } else { cz as f32 };
+            if let Some(index) = chunk.get(Chunk::local(cx, cy, cz)) {
+                let voxel = &self.palette[index as usize];
+                let shape = self.material(voxel.material).shape.oriented(&voxel.state);
+                let t_hit = dda.t_entered() + t_entry;
 
-        let mut t_max_x = if rd.x.abs() < 1e-6 { 
-            f32::INFINITY 
-        } else { 
-            (next_boundary_x - start.x).abs() / rd.x.abs() 
-        };
-        let mut t_max_y = if rd.y.abs() < 1e-6 { 
-            f32::INFINITY 
-        } else { 
-            (next_boundary_y - start.y).abs() / rd.y.abs() 
-        };
-        let mut t_max_z = if rd.z.abs() < 1e-6 { 
-            f32::INFINITY 
-        } else { 
-            (next_boundary_z - start.z).abs() / rd.z.abs() 
-        };
+                // Los bloques parciales se prueban contra sus cajas; si el rayo
+                // solo cruza la parte vacía de la celda, el recorrido sigue.
+                let surface = if shape.is_full() {
+                    let normal = match dda.face {
+                        0 => Vector3::new(if rd.x > 0.0 { -1.0 } else { 1.0 }, 0.0, 0.0),
+                        1 => Vector3::new(0.0, if rd.y > 0.0 { -1.0 } else { 1.0 }, 0.0),
+                        2 => Vector3::new(0.0, 0.0, if rd.z > 0.0 { -1.0 } else { 1.0 }),
+                        _ => Vector3::new(0.0, 1.0, 0.0),
+                    };
+                    Some((t_hit, normal))
+                } else {
+                    shape.intersect((cx, cy, cz), ro, rd, t_hit - 1e-4, t_max)
+                };
 
-        let mut hit_face = 3;
+                if let Some((t_hit, normal)) = surface {
+                    let hit_point = Vector3::new(
+                        ro.x + rd.x * t_hit,
+                        ro.y + rd.y * t_hit,
+                        ro.z + rd.z * t_hit,
+                    );
 
-        let limit_min = (bmin.x.floor() as i32, bmin.y.floor() as i32, bmin.z.floor() as i32);
-        let limit_max = (bmax.x.ceil() as i32, bmax.y.ceil() as i32, bmax.z.ceil() as i32);
+                    let (u, v) = face_uv_optimized(&hit_point, cx, cy, cz, &normal, &voxel.state);
 
-        for _ in 0..max_steps {
-            if cx < limit_min.0 || cy < limit_min.1 || cz < limit_min.2 ||
-               cx > limit_max.0 || cy > limit_max.1 || cz > limit_max.2 {
-                break;
+                    return Intersect::new_with_uv(hit_point, normal, t_hit, voxel.material, u, v)
+                        .with_state(voxel.state);
+                }
             }
 
-            if let Some(mat) = self.cells.get(&(cx, cy, cz)) {
-                let t_hit = if hit_face == 0 {
-                    t_max_x - t_delta_x
-                } else if hit_face == 1 {
-                    t_max_y - t_delta_y
-                } else if hit_face == 2 {
-                    t_max_z - t_delta_z
-                } else {
-                    t_entry
-                } + t_entry;
+            dda.advance();
+        }
 
-                if t_hit > t_max { 
-                    break; 
-                }
+        Intersect::empty()
+    }
 
-                let hit_point = Vector3::new(
-                    ro.x + rd.x * t_hit,
-                    ro.y + rd.y * t_hit,
-                    ro.z + rd.z * t_hit,
-                );
+    /// Recorre desde su interior un bloque transparente (o varios contiguos con el
+    /// mismo índice de refracción) y devuelve el punto por donde sale el rayo.
+    /// La normal devuelta apunta hacia afuera del medio.
+    pub fn exit_medium(&self, ro: &Vector3, rd: &Vector3) -> Intersect {
+        let mut cx = ro.x.floor() as i32;
+        let mut cy = ro.y.floor() as i32;
+        let mut cz = ro.z.floor() as i32;
 
-                let normal = match hit_face {
-                    0 => Vector3::new(if rd.x > 0.0 { -1.0 } else { 1.0 }, 0.0, 0.0),
-                    1 => Vector3::new(0.0, if rd.y > 0.0 { -1.0 } else { 1.0 }, 0.0),
-                    2 => Vector3::new(0.0, 0.0, if rd.z > 0.0 { -1.0 } else { 1.0 }),
-                    _ => Vector3::new(0.0, 1.0, 0.0),
-                };
+        let (medium_id, state) = match self.get(cx, cy, cz) {
+            Some(voxel) if self.material(voxel.material).is_transparent() => (voxel.material, voxel.state),
+            _ => return Intersect::empty(),
+        };
+        let medium = self.material(medium_id);
 
-                let (u, v) = face_uv_optimized(&hit_point, cx, cy, cz, &normal);
+        let step_x = if rd.x > 0.0 { 1 } else { -1 };
+        let step_y = if rd.y > 0.0 { 1 } else { -1 };
+        let step_z = if rd.z > 0.0 { 1 } else { -1 };
 
-                return Intersect::new_with_uv(hit_point, normal, t_hit, mat.clone(), u, v);
-            }
+        let t_delta_x = if rd.x.abs() < 1e-6 { f32::INFINITY } else { 1.0 / rd.x.abs() };
+        let t_delta_y = if rd.y.abs() < 1e-6 { f32::INFINITY } else { 1.0 / rd.y.abs() };
+        let t_delta_z = if rd.z.abs() < 1e-6 { f32::INFINITY } else { 1.0 / rd.z.abs() };
+
+        let next_boundary_x = if rd.x > 0.0 { cx as f32 + 1.0 } else { cx as f32 };
+        let next_boundary_y = if rd.y > 0.0 { cy as f32 + 1.0 } else { cy as f32 };
+        let next_boundary_z = if rd.z > 0.0 { cz as f32 + 1.0 } else { cz as f32 };
 
-            if t_max_x <= t_max_y && t_max_x <= t_max_z {
+        let mut t_max_x = if rd.x.abs() < 1e-6 { f32::INFINITY } else { (next_boundary_x - ro.x).abs() / rd.x.abs() };
+        let mut t_max_y = if rd.y.abs() < 1e-6 { f32::INFINITY } else { (next_boundary_y - ro.y).abs() / rd.y.abs() };
+        let mut t_max_z = if rd.z.abs() < 1e-6 { f32::INFINITY } else { (next_boundary_z - ro.z).abs() / rd.z.abs() };
+
+        // Fuera de la estructura no hay medio, así que el bucle siempre termina.
+        loop {
+            let (t_exit, normal) = if t_max_x <= t_max_y && t_max_x <= t_max_z {
+                let t = t_max_x;
                 cx += step_x;
                 t_max_x += t_delta_x;
-                hit_face = 0;
+                (t, Vector3::new(step_x as f32, 0.0, 0.0))
             } else if t_max_y <= t_max_z {
+                let t = t_max_y;
                 cy += step_y;
                 t_max_y += t_delta_y;
-                hit_face = 1;
+                (t, Vector3::new(0.0, step_y as f32, 0.0))
             } else {
+                let t = t_max_z;
                 cz += step_z;
                 t_max_z += t_delta_z;
-                hit_face = 2;
+                (t, Vector3::new(0.0, 0.0, step_z as f32))
+            };
+
+            let same_medium = self.get(cx, cy, cz).is_some_and(|voxel| {
+                let material = self.material(voxel.material);
+                material.is_transparent() && material.refractive_index == medium.refractive_index
+            });
+
+            if !same_medium {
+                let exit_point = Vector3::new(
+                    ro.x + rd.x * t_exit,
+                    ro.y + rd.y * t_exit,
+                    ro.z + rd.z * t_exit,
+                );
+                let (u, v) = face_uv_optimized(&exit_point, cx, cy, cz, &normal, &state);
+                return Intersect::new_with_uv(exit_point, normal, t_exit, medium_id, u, v)
+                    .with_state(state);
             }
         }
-
-        Intersect::empty()
     }
 }
 
 #[inline]
-fn face_uv_optimized(hit: &Vector3, cx: i32, cy: i32, cz: i32, normal: &Vector3) -> (f32, f32) {
-    let fx = hit.x - cx as f32;
-    let fy = hit.y - cy as f32;
-    let fz = hit.z - cz as f32;
-
-    // Mapeo preciso para cada cara del cubo
-    if normal.x.abs() > 0.5 {
-        // Cara X (izquierda/derecha)
-        (wrap01_precise(fz), 1.0 - wrap01_precise(fy))
-    } else if normal.y.abs() > 0.5 {
-        // Cara Y (arriba/abajo)
-        (wrap01_precise(fx), 1.0 - wrap01_precise(fz))
-    } else {
-        // Cara Z (frente/atrás)
-        (wrap01_precise(fx), 1.0 - wrap01_precise(fy))
+pub fn face_uv_optimized(hit: &Vector3, cx: i32, cy: i32, cz: i32, normal: &Vector3, state: &VoxelState) -> (f32, f32) {
+    let fx = wrap01_precise(hit.x - cx as f32);
+    let fy = wrap01_precise(hit.y - cy as f32);
+    let fz = wrap01_precise(hit.z - cz as f32);
+
+    // El "arriba" de la textura sigue el eje del bloque: en un pilar tumbado
+    // las vetas de los lados van a lo largo de X o Z.
+    match state.axis.unwrap_or(Axis::Y) {
+        Axis::X if normal.y.abs() > 0.5 => (fz, 1.0 - fx),
+        Axis::X if normal.z.abs() > 0.5 => (fy, 1.0 - fx),
+        Axis::Z if normal.x.abs() > 0.5 => (fy, 1.0 - fz),
+        _ if normal.x.abs() > 0.5 => {
+            // Cara X (izquierda/derecha)
+            (fz, 1.0 - fy)
+        }
+        _ if normal.y.abs() > 0.5 => {
+            // Cara Y (arriba/abajo), girada según hacia dónde mira el bloque
+            let (u, v) = (fx, 1.0 - fz);
+            match state.facing {
+                None | Some(Facing::North) => (u, v),
+                Some(Facing::East) => (v, 1.0 - u),
+                Some(Facing::South) => (1.0 - u, 1.0 - v),
+                Some(Facing::West) => (1.0 - v, u),
+            }
+        }
+        _ => {
+            // Cara Z (frente/atrás)
+            (fx, 1.0 - fy)
+        }
     }
 }
 
@@ -258,4 +697,113 @@ fn wrap01_precise(v: f32) -> f32 {
     } else { 
         result 
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Avanza celda a celda hasta salir del bloque de `2^bits` que contiene
+    /// la celda actual: lo que `skip` debe reproducir.
+    fn step_out(dda: &mut Dda, bits: i32) {
+        let block = dda.cell.map(|c| c >> bits);
+        while dda.cell.map(|c| c >> bits) == block {
+            dda.advance();
+        }
+    }
+
+    fn assert_same(skipped: &Dda, stepped: &Dda, context: &str) {
+        assert_eq!(skipped.cell, stepped.cell, "{}", context);
+        assert_eq!(skipped.face, stepped.face, "{}", context);
+        assert_eq!(skipped.t_max, stepped.t_max, "{}", context);
+        assert_eq!(skipped.t_entered(), stepped.t_entered(), "{}", context);
+    }
+
+    /// Compara `skip` con `step_out` en varios bloques seguidos del recorrido,
+    /// con pasos de `advance` entre medias como en `VoxelGrid::intersect_ray`.
+    fn check_ray(start: Vector3, dir: Vector3) {
+        for bits in [BRICK_BITS, CHUNK_BITS, REGION_BITS] {
+            let mut skipped = Dda::new(&start, &dir);
+            let mut stepped = Dda::new(&start, &dir);
+            for block in 0..4 {
+                let context = format!("desde {:?} hacia {:?}, bloque {} de 2^{}", start, dir, block, bits);
+                skipped.skip(bits);
+                step_out(&mut stepped, bits);
+                assert_same(&skipped, &stepped, &context);
+                skipped.advance();
+                stepped.advance();
+                assert_same(&skipped, &stepped, &context);
+            }
+        }
+    }
+
+    #[test]
+    fn skip_matches_stepping_in_every_octant() {
+        let starts = [
+            Vector3::new(0.3, 0.6, 0.2),
+            Vector3::new(-13.3, 5.7, -0.2),
+            Vector3::new(-70.1, -3.4, 129.8),
+        ];
+        for start in starts {
+            for octant in 0..8 {
+                let sign = |bit: i32| if octant & bit == 0 { 1.0 } else { -1.0 };
+                let dir = Vector3::new(0.37 * sign(1), 0.81 * sign(2), 0.46 * sign(4)).normalized();
+                check_ray(start, dir);
+            }
+        }
+    }
+
+    #[test]
+    fn skip_matches_stepping_on_negative_cells() {
+        // Celdas negativas dentro del bloque: `& mask` debe contar desde su
+        // borde, no desde cero.
+        for start in [Vector3::new(-0.5, -1.5, -2.5), Vector3::new(-17.25, -64.75, -3.5)] {
+            check_ray(start, Vector3::new(-0.6, 0.3, -0.74).normalized());
+            check_ray(start, Vector3::new(0.2, -0.9, 0.38).normalized());
+        }
+    }
+
+    #[test]
+    fn skip_matches_stepping_parallel_to_axes() {
+        let start = Vector3::new(-5.5, 2.25, 9.75);
+        for axis in 0..3 {
+            for sign in [1.0, -1.0] {
+                let mut dir = [0.0; 3];
+                dir[axis] = sign;
+                check_ray(start, Vector3::new(dir[0], dir[1], dir[2]));
+            }
+        }
+        // Paralelo a un plano: un solo eje queda sin cruces.
+        check_ray(start, Vector3::new(0.6, 0.0, -0.8));
+        check_ray(start, Vector3::new(0.0, -0.28, 0.96));
+    }
+
+    #[test]
+    fn skip_matches_stepping_on_random_rays() {
+        let mut rng = crate::path_tracer::Rng::new(7);
+        let mut uniform = |range: f32| (rng.next_f32() * 2.0 - 1.0) * range;
+        for i in 0..2000 {
+            // La mitad con componentes enteras y desde esquinas de celda, para
+            // que haya empates entre ejes.
+            let (start, dir) = if i % 2 == 0 {
+                (Vector3::new(uniform(100.0), uniform(100.0), uniform(100.0)), Vector3::new(uniform(1.0), uniform(1.0), uniform(1.0)))
+            } else {
+                let start = Vector3::new(uniform(40.0).round(), uniform(40.0).round(), uniform(40.0).round());
+                (start, Vector3::new(uniform(3.0).round(), uniform(3.0).round(), uniform(3.0).round()))
+            };
+            if dir.length() > 1e-3 {
+                check_ray(start, dir);
+            }
+        }
+    }
+
+    #[test]
+    fn skip_breaks_ties_like_advance() {
+        // Diagonales exactas desde el centro de una celda: las caras de
+        // varios ejes se cruzan a la misma distancia.
+        for start in [Vector3::new(0.5, 0.5, 0.5), Vector3::new(-2.5, 7.5, -9.5)] {
+            for dir in [Vector3::new(1.0, 1.0, 1.0), Vector3::new(-1.0, 1.0, -1.0), Vector3::new(1.0, -1.0, 0.0)] {
+                check_ray(start, dir);
+            }
+        }
+    }
+}
@@ -0,0 +1,97 @@
+use raylib::prelude::Vector3;
+use std::sync::Arc;
+
+use crate::bvh::{Aabb, Bvh};
+use crate::instance::VoxelInstance;
+use crate::light::Light;
+use crate::material::{Material, MaterialId};
+use crate::ray_intersect::{Intersect, RayIntersect};
+use crate::voxel::VoxelGrid;
+
+/// Todo lo que se traza: la grilla de voxels y los objetos colocados
+/// alrededor (planos, esferas, cubos, triángulos de mallas e instancias de
+/// modelos), en una BVH. Los objetos usan la misma paleta de materiales que
+/// la grilla.
+pub struct World {
+    pub grid: VoxelGrid,
+    pub objects: Bvh,
+    /// Las instancias también están en `objects`; aquí se guardan para
+    /// encuadrar la cámara y sacar sus luces.
+    instances: Vec<Arc<VoxelInstance>>,
+}
+
+impl World {
+    pub fn new(grid: VoxelGrid, mut objects: Vec<Box<dyn RayIntersect>>, instances: Vec<VoxelInstance>) -> Self {
+        let instances: Vec<Arc<VoxelInstance>> = instances.into_iter().map(Arc::new).collect();
+        objects.extend(instances.iter().map(|i| Box::new(Arc::clone(i)) as Box<dyn RayIntersect>));
+        World { grid, objects: Bvh::new(objects), instances }
+    }
+
+    #[inline]
+    pub fn material(&self, id: MaterialId) -> &Material {
+        self.grid.material(id)
+    }
+
+    pub fn instances(&self) -> &[Arc<VoxelInstance>] {
+        &self.instances
+    }
+
+    /// Voxels de la grilla más los de cada instancia.
+    pub fn voxel_count(&self) -> usize {
+        self.grid.len() + self.instances.iter().map(|i| i.voxel_count()).sum::<usize>()
+    }
+
+    /// Centro y radio de la esfera que envuelve los voxels, contando las
+    /// instancias; los demás objetos no cuentan para encuadrar la cámara.
+    pub fn bounding_sphere(&mut self) -> (Vector3, f32) {
+        if self.instances.is_empty() {
+            return (self.grid.get_center(), self.grid.get_bounding_sphere_radius());
+        }
+        let bounds = self.bounds();
+        let center = bounds.centroid();
+        (center, (bounds.max - center).length())
+    }
+
+    /// Caja de la grilla unida a las de las instancias.
+    pub fn bounds(&self) -> Aabb {
+        let (min, max) = self.grid.calculate_bounds();
+        let grid = if self.grid.is_empty() { Aabb::EMPTY } else { Aabb::new(min, max) };
+        self.instances
+            .iter()
+            .filter_map(|instance| instance.bounding_box())
+            .fold(grid, |acc, b| acc.union(&b))
+    }
+
+    /// Luces de los bloques emisivos de la grilla y de las instancias.
+    pub fn emissive_lights(&self, strength: f32) -> Vec<Light> {
+        let mut lights = self.grid.emissive_lights(strength);
+        for instance in &self.instances {
+            lights.extend(instance.emissive_lights(strength));
+        }
+        lights
+    }
+
+    /// Impacto más cercano entre la grilla y los objetos, hasta `t_max`.
+    pub fn intersect_ray(&self, ro: &Vector3, rd: &Vector3, t_max: f32) -> Intersect {
+        let voxel = self.grid.intersect_ray(ro, rd, t_max);
+        let limit = if voxel.is_intersecting { voxel.distance } else { t_max };
+        let object = self.objects.intersect(ro, rd, limit);
+        if object.is_intersecting { object } else { voxel }
+    }
+
+    /// Punto por el que el rayo sale del medio transparente en el que empieza.
+    /// Fuera de los voxels transparentes, el medio es un objeto y la salida es
+    /// su superficie más cercana, con la normal hacia afuera como en la grilla.
+    pub fn exit_medium(&self, ro: &Vector3, rd: &Vector3) -> Intersect {
+        let exit = self.grid.exit_medium(ro, rd);
+        if exit.is_intersecting {
+            return exit;
+        }
+
+        let mut closest = self.objects.exit_medium(ro, rd);
+        if closest.normal.dot(*rd) < 0.0 {
+            closest.normal = -closest.normal;
+        }
+        closest
+    }
+}